@@ -0,0 +1,125 @@
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Device;
+use serde::{Deserialize, Serialize};
+use std::{
+    process::Command,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// GPU temperature in °C
+    Temperature,
+    /// Power draw in W
+    Power,
+}
+
+impl Metric {
+    fn read(self, device: &Device) -> Result<f64, NvmlError> {
+        match self {
+            Metric::Temperature => device
+                .temperature(TemperatureSensor::Gpu)
+                .map(|temp| temp as f64),
+            Metric::Power => device.power_usage().map(|mw| mw as f64 / 1000.0),
+        }
+    }
+
+    fn unit(self) -> &'static str {
+        match self {
+            Metric::Temperature => "°C",
+            Metric::Power => "W",
+        }
+    }
+}
+
+/// A threshold checked by `nvidia_oc daemon`, configured per GPU under `alerts`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    pub metric: Metric,
+    pub above: f64,
+    /// Seconds the value has to stay above the threshold before firing
+    #[serde(default)]
+    pub sustained_for: u64,
+    /// Shell command to run, gets NVIDIA_OC_GPU, NVIDIA_OC_METRIC,
+    /// NVIDIA_OC_VALUE and NVIDIA_OC_THRESHOLD in its environment
+    pub command: Option<String>,
+    /// Show a desktop notification via notify-send, only reaches a desktop
+    /// when the daemon runs inside the user's session
+    #[serde(default)]
+    pub notify: bool,
+}
+
+/// Tracks one [`Alert`], firing once per excursion above the threshold and
+/// re-arming when the value drops back below it.
+pub struct AlertMonitor {
+    alert: Alert,
+    above_since: Option<Instant>,
+    fired: bool,
+}
+
+impl AlertMonitor {
+    pub fn new(alert: Alert) -> Self {
+        Self {
+            alert,
+            above_since: None,
+            fired: false,
+        }
+    }
+
+    pub fn check(&mut self, gpu: &str, device: &Device) -> Result<(), NvmlError> {
+        let value = self.alert.metric.read(device)?;
+
+        if value <= self.alert.above {
+            self.above_since = None;
+            self.fired = false;
+            return Ok(());
+        }
+
+        let above_since = *self.above_since.get_or_insert_with(Instant::now);
+        if self.fired || above_since.elapsed() < Duration::from_secs(self.alert.sustained_for) {
+            return Ok(());
+        }
+        self.fired = true;
+        self.fire(gpu, value);
+        Ok(())
+    }
+
+    fn fire(&self, gpu: &str, value: f64) {
+        let metric = format!("{:?}", self.alert.metric).to_lowercase();
+        let unit = self.alert.metric.unit();
+        let message = format!(
+            "GPU {} {} is {:.0}{}, above the {:.0}{} threshold",
+            gpu, metric, value, unit, self.alert.above, unit
+        );
+        eprintln!("Alert: {}", message);
+
+        if self.alert.notify {
+            if let Err(e) = Command::new("notify-send")
+                .args(["--urgency=critical", "nvidia_oc", &message])
+                .status()
+            {
+                eprintln!("Failed to run notify-send: {}", e);
+            }
+        }
+
+        if let Some(command) = &self.alert.command {
+            let status = Command::new("sh")
+                .args(["-c", command])
+                .env("NVIDIA_OC_GPU", gpu)
+                .env("NVIDIA_OC_METRIC", &metric)
+                .env("NVIDIA_OC_VALUE", format!("{:.1}", value))
+                .env("NVIDIA_OC_THRESHOLD", format!("{:.1}", self.alert.above))
+                .status();
+            match status {
+                Ok(status) if !status.success() => {
+                    eprintln!("Alert command `{}` exited with {}", command, status)
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to run alert command `{}`: {}", command, e),
+            }
+        }
+    }
+}
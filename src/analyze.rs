@@ -0,0 +1,237 @@
+use crate::output::{self, OutputFormat};
+use crate::power::format_watts;
+use nvml_wrapper::bitmasks::device::ThrottleReasons;
+use nvml_wrapper::enum_wrappers::device::{Clock, TemperatureSensor};
+use nvml_wrapper::Device;
+use serde::Serialize;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Shares of the time under load above which a limit is worth acting on
+const POWER_LIMITED: f64 = 0.3;
+const THERMAL_LIMITED: f64 = 0.05;
+
+/// Samples with less than this GPU utilization don't count as load
+const MIN_UTILIZATION: u32 = 10;
+
+#[derive(Default)]
+struct Counts {
+    samples: u32,
+    loaded: u32,
+    power: u32,
+    thermal: u32,
+    sync_boost: u32,
+    unconstrained: u32,
+    clock_mhz: Vec<u32>,
+    power_w: Vec<f64>,
+    temperature_c: Vec<u32>,
+}
+
+impl Counts {
+    fn add(&mut self, device: &Device) {
+        self.samples += 1;
+        let utilization = device.utilization_rates().map(|u| u.gpu).unwrap_or(0);
+        let reasons = device
+            .current_throttle_reasons()
+            .unwrap_or(ThrottleReasons::empty());
+        if utilization < MIN_UTILIZATION || reasons.contains(ThrottleReasons::GPU_IDLE) {
+            return;
+        }
+
+        self.loaded += 1;
+        let power = ThrottleReasons::SW_POWER_CAP | ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN;
+        let thermal = ThrottleReasons::SW_THERMAL_SLOWDOWN
+            | ThrottleReasons::HW_THERMAL_SLOWDOWN
+            | ThrottleReasons::HW_SLOWDOWN;
+        // A sample can be held back by several limits at once
+        if reasons.intersects(power) {
+            self.power += 1;
+        }
+        if reasons.intersects(thermal) {
+            self.thermal += 1;
+        }
+        if reasons.contains(ThrottleReasons::SYNC_BOOST) {
+            self.sync_boost += 1;
+        }
+        if !reasons.intersects(power | thermal | ThrottleReasons::SYNC_BOOST) {
+            self.unconstrained += 1;
+        }
+
+        if let Ok(clock) = device.clock_info(Clock::Graphics) {
+            self.clock_mhz.push(clock);
+        }
+        if let Ok(power) = device.power_usage() {
+            self.power_w.push(power as f64 / 1000.0);
+        }
+        if let Ok(temp) = device.temperature(TemperatureSensor::Gpu) {
+            self.temperature_c.push(temp);
+        }
+    }
+}
+
+/// Average and maximum of a reading under load
+#[derive(Serialize)]
+struct Range<T> {
+    average: f64,
+    max: T,
+}
+
+fn range<T: Copy + PartialOrd + Into<f64>>(values: &[T]) -> Option<Range<T>> {
+    let max = values
+        .iter()
+        .copied()
+        .reduce(|a, b| if b > a { b } else { a })?;
+    let sum: f64 = values.iter().map(|&v| v.into()).sum();
+    Some(Range {
+        average: sum / values.len() as f64,
+        max,
+    })
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    index: u32,
+    duration_seconds: f64,
+    samples: u32,
+    loaded_samples: u32,
+    /// Shares of the samples under load, from 0 to 1
+    power_limited: f64,
+    thermal_limited: f64,
+    sync_boost_limited: f64,
+    unconstrained: f64,
+    core_clock_mhz: Option<Range<u32>>,
+    power_w: Option<Range<f64>>,
+    temperature_c: Option<Range<u32>>,
+    recommendation: String,
+}
+
+/// Turns the shares into a tuning hint, thermal limits first as they cost
+/// the most clock speed
+fn recommend(report: &Report, device: &Device) -> String {
+    if report.loaded_samples == 0 {
+        return "The GPU was idle the whole time, start the workload before or during the analysis."
+            .to_string();
+    }
+    if report.thermal_limited >= THERMAL_LIMITED {
+        return "Thermally limited: improve cooling or use a more aggressive fan curve. \
+                Lowering the power limit or undervolting with locked clocks and a core \
+                offset also reduces heat."
+            .to_string();
+    }
+    if report.power_limited >= POWER_LIMITED {
+        let limit = device.power_management_limit().ok();
+        let max = device
+            .power_management_limit_constraints()
+            .ok()
+            .map(|c| c.max_limit);
+        return match (limit, max) {
+            (Some(limit), Some(max)) if limit < max => format!(
+                "Power limited: raise the power limit from {} towards the maximum of {}, \
+                 or undervolt for more clock per watt.",
+                format_watts(limit),
+                format_watts(max)
+            ),
+            _ => "Power limited at the maximum power limit: undervolt with locked clocks \
+                  and a core offset for more clock per watt."
+                .to_string(),
+        };
+    }
+    if report.unconstrained >= 0.5 {
+        return "Mostly unconstrained: there is headroom for a higher core or memory offset."
+            .to_string();
+    }
+    "No single limit dominates, the settings fit this workload.".to_string()
+}
+
+fn percent(share: f64) -> String {
+    format!("{:.0}%", share * 100.0)
+}
+
+/// Samples throttle reasons, clocks, power and temperature every `interval`
+/// for `duration` while a workload runs, then summarizes how much of the time
+/// under load each limit held the GPU back, with a recommendation.
+pub fn run(
+    index: u32,
+    device: &Device,
+    duration: Duration,
+    interval: Duration,
+    format: OutputFormat,
+) {
+    eprintln!(
+        "Analyzing GPU {} for {} s, run the workload now",
+        index,
+        duration.as_secs()
+    );
+    let mut counts = Counts::default();
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        counts.add(device);
+        thread::sleep(interval);
+    }
+
+    let share = |count: u32| {
+        if counts.loaded == 0 {
+            0.0
+        } else {
+            count as f64 / counts.loaded as f64
+        }
+    };
+    let mut report = Report {
+        index,
+        duration_seconds: started.elapsed().as_secs_f64(),
+        samples: counts.samples,
+        loaded_samples: counts.loaded,
+        power_limited: share(counts.power),
+        thermal_limited: share(counts.thermal),
+        sync_boost_limited: share(counts.sync_boost),
+        unconstrained: share(counts.unconstrained),
+        core_clock_mhz: range(&counts.clock_mhz),
+        power_w: range(&counts.power_w),
+        temperature_c: range(&counts.temperature_c),
+        recommendation: String::new(),
+    };
+    report.recommendation = recommend(&report, device);
+
+    if format != OutputFormat::Text {
+        output::print(format, &report);
+        return;
+    }
+
+    println!(
+        "GPU {}: under load for {} of {} samples",
+        index, report.loaded_samples, report.samples
+    );
+    if report.loaded_samples > 0 {
+        for (name, share) in [
+            ("power limited", report.power_limited),
+            ("thermally limited", report.thermal_limited),
+            ("sync boost", report.sync_boost_limited),
+            ("unconstrained", report.unconstrained),
+        ] {
+            println!("  {:<19}{}", format!("{}:", name), percent(share));
+        }
+    }
+    if let Some(clock) = &report.core_clock_mhz {
+        println!(
+            "  {:<19}{:.0} MHz average, {} MHz max",
+            "core clock:", clock.average, clock.max
+        );
+    }
+    if let Some(power) = &report.power_w {
+        println!(
+            "  {:<19}{:.1} W average, {:.1} W max",
+            "power:", power.average, power.max
+        );
+    }
+    if let Some(temp) = &report.temperature_c {
+        println!(
+            "  {:<19}{:.0} °C average, {} °C max",
+            "temperature:", temp.average, temp.max
+        );
+    }
+    println!();
+    println!("{}", report.recommendation);
+}
@@ -0,0 +1,51 @@
+use crate::Sets;
+use std::{fs, path::Path};
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+fn read(path: &Path, attribute: &str) -> Option<String> {
+    fs::read_to_string(path.join(attribute))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+/// Whether the system runs on battery, `None` without a battery as on
+/// desktops. USB-C chargers show up as `USB` supplies, barrel plugs as
+/// `Mains`.
+pub fn on_battery() -> Option<bool> {
+    let supplies: Vec<_> = fs::read_dir(POWER_SUPPLY_DIR)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    let has_battery = supplies
+        .iter()
+        .any(|supply| read(supply, "type").as_deref() == Some("Battery"));
+    if !has_battery {
+        return None;
+    }
+    let on_ac = supplies.iter().any(|supply| {
+        matches!(read(supply, "type").as_deref(), Some("Mains" | "USB"))
+            && read(supply, "online").as_deref() == Some("1")
+    });
+    Some(!on_ac)
+}
+
+/// A config entry with its `battery` settings on top, what `nvidia_oc daemon`
+/// applies while the system runs on battery
+pub fn battery_sets(sets: &Sets) -> Result<Sets, String> {
+    let Some(battery) = &sets.battery else {
+        return Ok(sets.clone());
+    };
+    let Ok(serde_json::Value::Object(mut settings)) = serde_json::to_value(sets) else {
+        return Err("failed to serialize settings".to_string());
+    };
+    settings.remove("battery");
+    settings.extend(
+        battery
+            .iter()
+            .filter(|(name, value)| *name != "battery" && !value.is_null())
+            .map(|(name, value)| (name.clone(), value.clone())),
+    );
+    serde_json::from_value(serde_json::Value::Object(settings))
+        .map_err(|e| format!("invalid battery settings: {}", e))
+}
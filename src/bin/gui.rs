@@ -1,9 +1,37 @@
-use eframe::{egui, epi};
+// The GUI only names pstates, parsing offsets is left to the CLI
+#[allow(dead_code)]
+#[path = "../pstate.rs"]
+mod pstate;
+// Only for formatting watts, parsing and applying limits is left to the CLI
+#[allow(dead_code)]
+#[path = "../power.rs"]
+mod power;
+#[path = "../store.rs"]
+mod store;
+
+use eframe::egui;
+use egui_plot::{Legend, MarkerShape, Plot, PlotPoints, Points};
 use nvml_wrapper::{Nvml, Device};
-use nvml_wrapper::enums::device::{GpuLockedClocksSetting, Clock};
+use nvml_wrapper::bitmasks::device::ThrottleReasons;
+use nvml_wrapper::enum_wrappers::device::{PerformanceState, TemperatureSensor};
+use nvml_wrapper::enums::device::GpuLockedClocksSetting;
+use nvml_wrapper::enum_wrappers::device::Clock;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use regex::Regex;
+use serde::Deserialize;
+use pstate::pstate_name;
 use std::path::PathBuf;
-use std::{fs::OpenOptions, io::Write};
-use std::process::Command;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
 
 fn documents_dir() -> PathBuf {
     let mut path = std::env::var("HOME").map(PathBuf::from).unwrap_or_default();
@@ -20,6 +48,722 @@ struct Record {
     max_clock: u32,
     score: f32,
     avg_power: f32,
+    /// Energy the GPU consumed during the scored benchmark, in joules
+    energy_j: f32,
+    telemetry: TrialTelemetry,
+}
+
+/// Clocks and temperature sampled while a trial's benchmark ran. The score
+/// alone doesn't show whether the offset was sustained or the card was held
+/// back by its limits.
+#[derive(Clone, Copy, Default)]
+struct TrialTelemetry {
+    avg_clock: f32,
+    peak_clock: u32,
+    max_temp: u32,
+    /// Share of the run spent power or thermal limited, from 0 to 1
+    throttled: f32,
+}
+
+/// Throttle reasons that mean the card couldn't hold the clocks it was given
+const LIMITED: ThrottleReasons = ThrottleReasons::SW_POWER_CAP
+    .union(ThrottleReasons::HW_SLOWDOWN)
+    .union(ThrottleReasons::SW_THERMAL_SLOWDOWN)
+    .union(ThrottleReasons::HW_THERMAL_SLOWDOWN)
+    .union(ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN);
+
+#[derive(Default)]
+struct TelemetrySampler {
+    samples: u32,
+    clock_sum: u64,
+    peak_clock: u32,
+    max_temp: u32,
+    throttled: u32,
+}
+
+impl TelemetrySampler {
+    fn sample(&mut self, device: &Device) {
+        self.samples += 1;
+        let clock = device.clock_info(Clock::Graphics).unwrap_or(0);
+        self.clock_sum += clock as u64;
+        self.peak_clock = self.peak_clock.max(clock);
+        if let Ok(temp) = device.temperature(TemperatureSensor::Gpu) {
+            self.max_temp = self.max_temp.max(temp);
+        }
+        if device.current_throttle_reasons().is_ok_and(|reasons| reasons.intersects(LIMITED)) {
+            self.throttled += 1;
+        }
+    }
+
+    fn finish(&self) -> TrialTelemetry {
+        if self.samples == 0 {
+            return TrialTelemetry::default();
+        }
+        TrialTelemetry {
+            avg_clock: self.clock_sum as f32 / self.samples as f32,
+            peak_clock: self.peak_clock,
+            max_temp: self.max_temp,
+            throttled: self.throttled as f32 / self.samples as f32,
+        }
+    }
+}
+
+/// What the search should maximise when picking the best record.
+#[derive(Clone, Copy, PartialEq)]
+enum Objective {
+    /// Raw benchmark score
+    Score,
+    /// Score per watt of average power draw
+    Efficiency,
+    /// Score per joule the benchmark run consumed, for rigs paying for every
+    /// joule around the clock
+    ScorePerJoule,
+}
+
+impl Objective {
+    const ALL: [Objective; 3] = [Objective::Score, Objective::Efficiency, Objective::ScorePerJoule];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Objective::Score => "Score",
+            Objective::Efficiency => "Efficiency (score/W)",
+            Objective::ScorePerJoule => "Score per joule",
+        }
+    }
+
+    fn value(&self, record: &Record) -> f32 {
+        match self {
+            Objective::Score => record.score,
+            Objective::Efficiency => record.efficiency(),
+            Objective::ScorePerJoule => record.score_per_joule(),
+        }
+    }
+}
+
+/// How to get a score out of a finished benchmark run
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum ScoreSource {
+    /// First capture group (or the whole match) of a regex over stdout
+    Regex { pattern: String },
+    /// JSON pointer, e.g. `/results/score`, into a file the benchmark writes
+    JsonPointer { file: PathBuf, pointer: String },
+    /// Only pass/fail by exit code, a pass scores 1
+    ExitCode,
+    /// MangoHud's CSV log of a real game played during the run, e.g. with
+    /// `sleep {duration}` as the command. The newest log in `dir` written
+    /// during the run is scored, so logging has to be started in the game or
+    /// with `autostart_log`.
+    MangoHud {
+        dir: PathBuf,
+        #[serde(default)]
+        metric: FpsMetric,
+    },
+}
+
+/// What a MangoHud log is scored by
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum FpsMetric {
+    #[default]
+    AverageFps,
+    /// Average FPS of the slowest 1% of frames, which shows stutter
+    OnePercentLow,
+}
+
+/// Frametimes in ms from a MangoHud log. The frame data follows a header row
+/// starting with `fps`, after the system info lines.
+fn mangohud_frametimes(contents: &str) -> Result<Vec<f64>, String> {
+    let mut lines = contents.lines();
+    let header = lines
+        .by_ref()
+        .find(|line| line.split(',').next() == Some("fps"))
+        .ok_or("no frame data header")?;
+    let column = header
+        .split(',')
+        .position(|name| name.trim() == "frametime")
+        .ok_or("no frametime column")?;
+    let frametimes: Vec<f64> = lines
+        .filter_map(|line| line.split(',').nth(column)?.trim().parse().ok())
+        .filter(|&frametime: &f64| frametime > 0.0)
+        .collect();
+    if frametimes.is_empty() {
+        return Err("no frames logged".to_string());
+    }
+    Ok(frametimes)
+}
+
+/// Newest MangoHud log in `dir` modified since `since`. MangoHud also writes
+/// `_summary.csv` files next to the logs, those are skipped.
+fn newest_mangohud_log(dir: &std::path::Path, since: SystemTime) -> Result<PathBuf, String> {
+    std::fs::read_dir(dir)
+        .map_err(|e| format!("{}: {}", dir.display(), e))?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?;
+            if !name.ends_with(".csv") || name.ends_with("_summary.csv") {
+                return None;
+            }
+            let modified = path.metadata().ok()?.modified().ok()?;
+            (modified >= since).then_some((modified, path))
+        })
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+        .ok_or_else(|| format!("no MangoHud log written to {} during the run", dir.display()))
+}
+
+impl FpsMetric {
+    fn score(&self, frametimes: &[f64]) -> f32 {
+        let average_fps = |frametimes: &[f64]| 1000.0 * frametimes.len() as f64 / frametimes.iter().sum::<f64>();
+        match self {
+            FpsMetric::AverageFps => average_fps(frametimes) as f32,
+            FpsMetric::OnePercentLow => {
+                let mut sorted = frametimes.to_vec();
+                sorted.sort_by(|a, b| b.total_cmp(a));
+                let slowest = &sorted[..(sorted.len() / 100).max(1)];
+                average_fps(slowest) as f32
+            }
+        }
+    }
+}
+
+/// An external benchmark, listed in `~/.config/nvidia_oc/benchmarks.json`
+#[derive(Clone, Deserialize)]
+struct Benchmark {
+    name: String,
+    /// Program and arguments
+    command: Vec<String>,
+    score: ScoreSource,
+    /// Regex over stdout that marks the run as failed even when the program
+    /// exits cleanly, e.g. the errors a VRAM test reports
+    #[serde(default)]
+    error_pattern: Option<String>,
+}
+
+fn benchmarks_path() -> PathBuf {
+    let mut path = std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| std::env::var("HOME").map(PathBuf::from).unwrap_or_default().join(".config"));
+    path.push("nvidia_oc");
+    path.push("benchmarks.json");
+    path
+}
+
+fn load_benchmarks() -> Vec<Benchmark> {
+    let Ok(contents) = std::fs::read_to_string(benchmarks_path()) else {
+        return Vec::new();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        eprintln!("Invalid {}: {}", benchmarks_path().display(), e);
+        Vec::new()
+    })
+}
+
+impl ScoreSource {
+    /// Scores a run that started at `started`
+    fn extract(&self, stdout: &str, started: SystemTime) -> Result<f32, String> {
+        match self {
+            ScoreSource::Regex { pattern } => {
+                let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+                let captures = regex.captures(stdout).ok_or("score pattern not found in output")?;
+                let score = captures.get(1).or_else(|| captures.get(0)).unwrap().as_str();
+                score.trim().parse().map_err(|_| format!("`{}` is not a number", score))
+            }
+            ScoreSource::JsonPointer { file, pointer } => {
+                let contents = std::fs::read_to_string(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+                let json: serde_json::Value = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+                let value = json.pointer(pointer).ok_or_else(|| format!("{} not found", pointer))?;
+                value
+                    .as_f64()
+                    .or_else(|| value.as_str().and_then(|s| s.trim().parse().ok()))
+                    .map(|score| score as f32)
+                    .ok_or_else(|| format!("{} is not a number", pointer))
+            }
+            ScoreSource::ExitCode => Ok(1.0),
+            ScoreSource::MangoHud { dir, metric } => {
+                let path = newest_mangohud_log(dir, started)?;
+                let contents = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                let frametimes = mangohud_frametimes(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+                Ok(metric.score(&frametimes))
+            }
+        }
+    }
+}
+
+/// How the tuner explores the offsets
+#[derive(Clone, Copy, PartialEq)]
+enum Strategy {
+    /// Every supported clock step in turn
+    Linear,
+    /// Binary search for the highest stable offsets, then a soak test
+    Bisection,
+    /// Gaussian process model of the objective and stability, trials picked
+    /// by expected improvement
+    Bayesian,
+    /// Raise only the memory offset until the benchmark, meant to be a VRAM
+    /// test such as memtest_vulkan, reports its first error
+    MemoryOnly,
+}
+
+impl Strategy {
+    fn label(&self) -> &'static str {
+        match self {
+            Strategy::Linear => "Linear",
+            Strategy::Bisection => "Bisection",
+            Strategy::Bayesian => "Bayesian",
+            Strategy::MemoryOnly => "Memory only",
+        }
+    }
+}
+
+fn default_power_step_w() -> u32 { 5 }
+fn default_offset_stride() -> usize { 1 }
+fn default_max_crashes() -> u32 { 2 }
+fn default_benchmark_seconds() -> u64 { 300 }
+fn default_soak_runs() -> u32 { 3 }
+fn default_bayesian_trials() -> usize { 25 }
+fn default_torture_seconds() -> u64 { 600 }
+fn default_mem_step_mhz() -> i32 { 50 }
+fn default_max_mem_offset() -> i32 { 3000 }
+
+/// Tunables of the search, read from `~/.config/nvidia_oc/tuner.json`, then
+/// overridden by command line flags and editable in the GUI
+#[derive(Clone, Deserialize, Parser)]
+#[serde(rename_all = "camelCase")]
+struct SearchLimits {
+    /// Watts the power limit moves per step
+    #[serde(default = "default_power_step_w")]
+    #[arg(long, default_value_t = default_power_step_w())]
+    power_step_w: u32,
+    /// Only try every Nth supported clock as an offset step
+    #[serde(default = "default_offset_stride")]
+    #[arg(long, default_value_t = default_offset_stride())]
+    offset_stride: usize,
+    /// Lowest power limit in W to try, 0 for the driver minimum
+    #[serde(default)]
+    #[arg(long, default_value_t = 0)]
+    min_power_w: u32,
+    /// Failed runs tolerated before the linear search gives up
+    #[serde(default = "default_max_crashes")]
+    #[arg(long, default_value_t = default_max_crashes())]
+    max_crashes: u32,
+    /// Seconds per benchmark run, substituted for `{duration}` in benchmark commands
+    #[serde(default = "default_benchmark_seconds")]
+    #[arg(long, default_value_t = default_benchmark_seconds())]
+    benchmark_seconds: u64,
+    /// Consecutive passes the bisection search needs to accept a result
+    #[serde(default = "default_soak_runs")]
+    #[arg(long, default_value_t = default_soak_runs())]
+    soak_runs: u32,
+    /// How long the torture stage runs after each passing benchmark
+    #[serde(default = "default_torture_seconds")]
+    #[arg(long, default_value_t = default_torture_seconds())]
+    torture_seconds: u64,
+    /// Benchmark runs the Bayesian search gets in total
+    #[serde(default = "default_bayesian_trials")]
+    #[arg(long, default_value_t = default_bayesian_trials())]
+    bayesian_trials: usize,
+    /// Memory offset increase per step of the memory only search, in MHz
+    #[serde(default = "default_mem_step_mhz")]
+    #[arg(long, default_value_t = default_mem_step_mhz())]
+    mem_step_mhz: i32,
+    /// Highest memory offset the memory only search tries, in MHz
+    #[serde(default = "default_max_mem_offset")]
+    #[arg(long, default_value_t = default_max_mem_offset())]
+    max_mem_offset: i32,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        serde_json::from_str("{}").unwrap()
+    }
+}
+
+fn tuner_config_path() -> PathBuf {
+    benchmarks_path().with_file_name("tuner.json")
+}
+
+/// Config file values, with any flag given on the command line taking precedence
+fn load_search_limits() -> SearchLimits {
+    let mut limits = match std::fs::read_to_string(tuner_config_path()) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Invalid {}: {}", tuner_config_path().display(), e);
+            SearchLimits::default()
+        }),
+        Err(_) => SearchLimits::default(),
+    };
+    let matches = SearchLimits::command().get_matches();
+    let flags = SearchLimits::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if given("power_step_w") { limits.power_step_w = flags.power_step_w; }
+    if given("offset_stride") { limits.offset_stride = flags.offset_stride; }
+    if given("min_power_w") { limits.min_power_w = flags.min_power_w; }
+    if given("max_crashes") { limits.max_crashes = flags.max_crashes; }
+    if given("benchmark_seconds") { limits.benchmark_seconds = flags.benchmark_seconds; }
+    if given("soak_runs") { limits.soak_runs = flags.soak_runs; }
+    if given("torture_seconds") { limits.torture_seconds = flags.torture_seconds; }
+    if given("bayesian_trials") { limits.bayesian_trials = flags.bayesian_trials; }
+    if given("mem_step_mhz") { limits.mem_step_mhz = flags.mem_step_mhz; }
+    if given("max_mem_offset") { limits.max_mem_offset = flags.max_mem_offset; }
+    limits
+}
+
+#[derive(Clone)]
+struct SearchParams {
+    limits: SearchLimits,
+    strategy: Strategy,
+    objective: Objective,
+    /// Records scoring below this are treated as unacceptable, not as crashes
+    min_score: f32,
+    /// Benchmark to score each step with, the placeholder when none is configured
+    benchmark: Option<Benchmark>,
+    /// Power virus such as gpu_burn that must also pass before a record counts
+    torture: Option<Benchmark>,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self { limits: SearchLimits::default(), strategy: Strategy::Linear, objective: Objective::Score, min_score: 0.0, benchmark: None, torture: None }
+    }
+}
+
+impl Record {
+    fn efficiency(&self) -> f32 {
+        if self.avg_power > 0.0 {
+            self.score / self.avg_power
+        } else {
+            0.0
+        }
+    }
+
+    fn score_per_joule(&self) -> f32 {
+        if self.energy_j > 0.0 {
+            self.score / self.energy_j
+        } else {
+            0.0
+        }
+    }
+
+    fn to_trial(&self, run_id: i64) -> store::Trial {
+        store::Trial {
+            run_id,
+            timestamp_ms: store::now_ms(),
+            power_limit_mw: self.power_limit,
+            freq_offset: self.freq_offset,
+            mem_offset: self.mem_offset,
+            min_clock: self.min_clock,
+            max_clock: self.max_clock,
+            score: self.score,
+            avg_power_w: self.avg_power,
+            energy_j: self.energy_j,
+            avg_clock_mhz: self.telemetry.avg_clock,
+            peak_clock_mhz: self.telemetry.peak_clock,
+            max_temp_c: self.telemetry.max_temp,
+            throttled: self.telemetry.throttled,
+        }
+    }
+
+    fn from_trial(trial: &store::Trial) -> Self {
+        Self {
+            power_limit: trial.power_limit_mw,
+            freq_offset: trial.freq_offset,
+            mem_offset: trial.mem_offset,
+            min_clock: trial.min_clock,
+            max_clock: trial.max_clock,
+            score: trial.score,
+            avg_power: trial.avg_power_w,
+            energy_j: trial.energy_j,
+            telemetry: TrialTelemetry {
+                avg_clock: trial.avg_clock_mhz,
+                peak_clock: trial.peak_clock_mhz,
+                max_temp: trial.max_temp_c,
+                throttled: trial.throttled,
+            },
+        }
+    }
+}
+
+/// Picks the record that maximises the objective among those meeting the
+/// minimum score. When optimising for efficiency ties are broken by score, so
+/// the returned record is always on the score/power Pareto frontier.
+fn best_record<'a>(records: &'a [Record], params: &SearchParams) -> Option<&'a Record> {
+    records
+        .iter()
+        .filter(|r| r.score >= params.min_score)
+        .max_by(|a, b| {
+            params
+                .objective
+                .value(a)
+                .total_cmp(&params.objective.value(b))
+                .then(a.score.total_cmp(&b.score))
+        })
+}
+
+/// Whether another record scores at least as high at no more average power,
+/// and is strictly better in one of the two
+fn is_dominated(record: &Record, records: &[Record]) -> bool {
+    records.iter().any(|other| {
+        other.score >= record.score
+            && other.avg_power <= record.avg_power
+            && (other.score > record.score || other.avg_power < record.avg_power)
+    })
+}
+
+/// Records no other record beats on both score and average power
+fn pareto_frontier(records: &[Record]) -> Vec<&Record> {
+    records.iter().filter(|r| !is_dominated(r, records)).collect()
+}
+
+/// Column the results table is sorted by
+#[derive(Clone, Copy, PartialEq)]
+enum SortColumn {
+    PowerLimit,
+    FreqOffset,
+    MemOffset,
+    Score,
+    AvgPower,
+    Efficiency,
+    Energy,
+    ScorePerJoule,
+    AvgClock,
+    Throttled,
+}
+
+impl SortColumn {
+    const ALL: [SortColumn; 10] = [
+        SortColumn::PowerLimit,
+        SortColumn::FreqOffset,
+        SortColumn::MemOffset,
+        SortColumn::Score,
+        SortColumn::AvgPower,
+        SortColumn::Efficiency,
+        SortColumn::Energy,
+        SortColumn::ScorePerJoule,
+        SortColumn::AvgClock,
+        SortColumn::Throttled,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            SortColumn::PowerLimit => "PL (W)",
+            SortColumn::FreqOffset => "Freq (MHz)",
+            SortColumn::MemOffset => "Mem (MHz)",
+            SortColumn::Score => "Score",
+            SortColumn::AvgPower => "Avg Power (W)",
+            SortColumn::Efficiency => "Score/W",
+            SortColumn::Energy => "Energy (J)",
+            SortColumn::ScorePerJoule => "Score/J",
+            SortColumn::AvgClock => "Avg Clock (MHz)",
+            SortColumn::Throttled => "Throttled",
+        }
+    }
+
+    fn key(&self, record: &Record) -> f32 {
+        match self {
+            SortColumn::PowerLimit => record.power_limit as f32,
+            SortColumn::FreqOffset => record.freq_offset as f32,
+            SortColumn::MemOffset => record.mem_offset as f32,
+            SortColumn::Score => record.score,
+            SortColumn::AvgPower => record.avg_power,
+            SortColumn::Efficiency => record.efficiency(),
+            SortColumn::Energy => record.energy_j,
+            SortColumn::ScorePerJoule => record.score_per_joule(),
+            SortColumn::AvgClock => record.telemetry.avg_clock,
+            SortColumn::Throttled => record.telemetry.throttled,
+        }
+    }
+}
+
+/// Data range of the score-vs-power plot, padded so points don't sit on the axes
+struct PlotBounds {
+    min_x: f64,
+    max_x: f64,
+    min_y: f64,
+    max_y: f64,
+}
+
+impl PlotBounds {
+    fn of(records: &[Record]) -> Self {
+        let xs = records.iter().map(|r| r.power_limit as f64 / 1000.0);
+        let ys = records.iter().map(|r| r.score as f64);
+        let (min_x, max_x) = xs.fold((f64::MAX, f64::MIN), |(lo, hi), x| (lo.min(x), hi.max(x)));
+        let (min_y, max_y) = ys.fold((f64::MAX, f64::MIN), |(lo, hi), y| (lo.min(y), hi.max(y)));
+        if records.is_empty() {
+            return Self { min_x: 0.0, max_x: 1.0, min_y: 0.0, max_y: 1.0 };
+        }
+        let pad_x = ((max_x - min_x) * 0.05).max(1.0);
+        let pad_y = ((max_y - min_y) * 0.05).max(1.0);
+        Self { min_x: min_x - pad_x, max_x: max_x + pad_x, min_y: min_y - pad_y, max_y: max_y + pad_y }
+    }
+
+    /// Maps a record to pixel coordinates inside a `width` x `height` image
+    /// with `margin` pixels reserved for the axes
+    fn project(&self, record: &Record, width: f64, height: f64, margin: f64) -> (f64, f64) {
+        let x = (record.power_limit as f64 / 1000.0 - self.min_x) / (self.max_x - self.min_x);
+        let y = (record.score as f64 - self.min_y) / (self.max_y - self.min_y);
+        (margin + x * (width - 2.0 * margin), height - margin - y * (height - 2.0 * margin))
+    }
+}
+
+const PLOT_WIDTH: f64 = 800.0;
+const PLOT_HEIGHT: f64 = 500.0;
+const PLOT_MARGIN: f64 = 60.0;
+
+/// Score-vs-power plot as a standalone SVG document
+fn plot_svg(records: &[Record]) -> String {
+    let bounds = PlotBounds::of(records);
+    let (w, h, m) = (PLOT_WIDTH, PLOT_HEIGHT, PLOT_MARGIN);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"12\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n\
+         <path d=\"M{m} {m} V{b} H{r}\" stroke=\"black\" fill=\"none\"/>\n",
+        b = h - m,
+        r = w - m
+    );
+    for i in 0..=4 {
+        let t = i as f64 / 4.0;
+        let x = m + t * (w - 2.0 * m);
+        let y = h - m - t * (h - 2.0 * m);
+        svg += &format!(
+            "<text x=\"{x:.0}\" y=\"{:.0}\" text-anchor=\"middle\">{:.0}</text>\n",
+            h - m + 18.0,
+            bounds.min_x + t * (bounds.max_x - bounds.min_x)
+        );
+        svg += &format!(
+            "<text x=\"{:.0}\" y=\"{y:.0}\" text-anchor=\"end\" dominant-baseline=\"middle\">{:.0}</text>\n",
+            m - 6.0,
+            bounds.min_y + t * (bounds.max_y - bounds.min_y)
+        );
+    }
+    svg += &format!(
+        "<text x=\"{:.0}\" y=\"{:.0}\" text-anchor=\"middle\">Power limit (W)</text>\n",
+        w / 2.0,
+        h - 15.0
+    );
+    svg += &format!(
+        "<text x=\"15\" y=\"{:.0}\" text-anchor=\"middle\" transform=\"rotate(-90 15 {:.0})\">Score</text>\n",
+        h / 2.0,
+        h / 2.0
+    );
+    for record in records {
+        let (x, y) = bounds.project(record, w, h, m);
+        let fill = if is_dominated(record, records) { "silver" } else { "seagreen" };
+        svg += &format!("<circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"4\" fill=\"{fill}\"/>\n");
+    }
+    svg + "</svg>\n"
+}
+
+/// Score-vs-power plot rendered to a PNG. tiny-skia has no text rendering, so
+/// unlike the SVG this only has axes, grid lines and points.
+fn save_plot_png(records: &[Record], path: &std::path::Path) -> Result<(), String> {
+    use tiny_skia::{Color, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+    let bounds = PlotBounds::of(records);
+    let (w, h, m) = (PLOT_WIDTH, PLOT_HEIGHT, PLOT_MARGIN);
+    let mut pixmap = Pixmap::new(w as u32, h as u32).ok_or("invalid image size")?;
+    pixmap.fill(Color::WHITE);
+
+    let mut grid = PathBuilder::new();
+    for i in 1..=4 {
+        let t = i as f32 / 4.0;
+        let x = m as f32 + t * (w - 2.0 * m) as f32;
+        let y = (h - m) as f32 - t * (h - 2.0 * m) as f32;
+        grid.move_to(x, m as f32);
+        grid.line_to(x, (h - m) as f32);
+        grid.move_to(m as f32, y);
+        grid.line_to((w - m) as f32, y);
+    }
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(220, 220, 220, 255);
+    if let Some(grid) = grid.finish() {
+        pixmap.stroke_path(&grid, &paint, &Stroke::default(), Transform::identity(), None);
+    }
+
+    let mut axes = PathBuilder::new();
+    axes.move_to(m as f32, m as f32);
+    axes.line_to(m as f32, (h - m) as f32);
+    axes.line_to((w - m) as f32, (h - m) as f32);
+    paint.set_color_rgba8(0, 0, 0, 255);
+    if let Some(axes) = axes.finish() {
+        pixmap.stroke_path(&axes, &paint, &Stroke::default(), Transform::identity(), None);
+    }
+
+    paint.anti_alias = true;
+    for record in records {
+        if is_dominated(record, records) {
+            paint.set_color_rgba8(192, 192, 192, 255);
+        } else {
+            paint.set_color_rgba8(46, 139, 87, 255);
+        }
+        let (x, y) = bounds.project(record, w, h, m);
+        if let Some(circle) = PathBuilder::from_circle(x as f32, y as f32, 4.0) {
+            pixmap.fill_path(&circle, &paint, tiny_skia::FillRule::Winding, Transform::identity(), None);
+        }
+    }
+
+    pixmap.save_png(path).map_err(|e| e.to_string())
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// The whole run as a single HTML file: summary, inline plot and all records
+fn html_report(records: &[Record], params: &SearchParams, gpu: &str) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>nvidia_oc tuning report</title>\n\
+         <style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+         td,th{{border:1px solid #ccc;padding:4px 8px;text-align:right}}</style></head><body>\n\
+         <h1>nvidia_oc tuning report</h1>\n<p>GPU: {}<br>Benchmark: {}<br>Records: {}</p>\n",
+        html_escape(gpu),
+        html_escape(params.benchmark.as_ref().map_or("placeholder", |b| b.name.as_str())),
+        records.len()
+    );
+    html += &format!(
+        "<p>{} record(s) on the Pareto frontier of score vs. average power, shown in green</p>\n",
+        pareto_frontier(records).len()
+    );
+
+    for objective in Objective::ALL {
+        let params = SearchParams { objective, ..params.clone() };
+        if let Some(best) = best_record(records, &params) {
+            html += &format!(
+                "<p>Best by {}: {}, core {:+} MHz, memory {:+} MHz, score {:.0}, {:.2} W average, {:.2} score/W, {:.0} J, {:.4} score/J</p>\n",
+                objective.label(),
+                power::format_watts(best.power_limit),
+                best.freq_offset,
+                best.mem_offset,
+                best.score,
+                best.avg_power,
+                best.efficiency(),
+                best.energy_j,
+                best.score_per_joule()
+            );
+        }
+    }
+
+    html += &plot_svg(records);
+    html += "<table>\n<tr><th>PL (W)</th><th>Freq (MHz)</th><th>Mem (MHz)</th><th>Clocks (MHz)</th><th>Score</th><th>Avg Power (W)</th><th>Score/W</th><th>Energy (J)</th><th>Score/J</th><th>Avg Clock (MHz)</th><th>Peak Clock (MHz)</th><th>Max Temp (°C)</th><th>Throttled</th></tr>\n";
+    for record in records {
+        html += &format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}-{}</td><td>{:.0}</td><td>{:.2}</td><td>{:.2}</td><td>{:.0}</td><td>{:.4}</td><td>{:.0}</td><td>{}</td><td>{}</td><td>{:.0}%</td></tr>\n",
+            record.power_limit as f64 / 1000.0,
+            record.freq_offset,
+            record.mem_offset,
+            record.min_clock,
+            record.max_clock,
+            record.score,
+            record.avg_power,
+            record.efficiency(),
+            record.energy_j,
+            record.score_per_joule(),
+            record.telemetry.avg_clock,
+            record.telemetry.peak_clock,
+            record.telemetry.max_temp,
+            record.telemetry.throttled * 100.0
+        );
+    }
+    html + "</table>\n</body></html>\n"
 }
 
 #[derive(Default, Clone)]
@@ -62,76 +806,829 @@ fn query_supported_clocks() -> Option<SupportedClocks> {
     Some(clocks)
 }
 
+/// One point of the V/F curve as the driver exposes it: the graphics clock
+/// range of a performance state and its offset. NVML has no per-voltage
+/// points, so the curve has one point per supported pstate.
+#[derive(Clone)]
+struct CurvePoint {
+    pstate: PerformanceState,
+    max_clock: u32,
+    offset: i32,
+    min_offset: i32,
+    max_offset: i32,
+}
+
+impl CurvePoint {
+    fn clock(&self) -> i32 {
+        self.max_clock as i32 + self.offset
+    }
+}
+
+/// Reads the curve of a GPU, ordered from the lowest to the highest clock
+fn read_curve(device: &Device) -> Result<Vec<CurvePoint>, String> {
+    let pstates = device.supported_performance_states().map_err(|e| format!("{:?}", e))?;
+    let mut points: Vec<CurvePoint> = pstates
+        .into_iter()
+        .filter_map(|pstate| {
+            let offset = device.clock_offset(Clock::Graphics, pstate).ok()?;
+            let (_, max_clock) = device.min_max_clock_of_pstate(Clock::Graphics, pstate).ok()?;
+            Some(CurvePoint {
+                pstate,
+                max_clock,
+                offset: offset.clock_offset_mhz,
+                min_offset: offset.min_clock_offset_mhz,
+                max_offset: offset.max_clock_offset_mhz,
+            })
+        })
+        .collect();
+    if points.is_empty() {
+        return Err("the driver reports no per-pstate offsets, they need driver 555 or newer".to_string());
+    }
+    points.sort_by_key(|point| point.max_clock);
+    Ok(points)
+}
+
+/// Writes the offsets of every curve point, through `pkexec nvidia_oc set
+/// --freq-offset-pstate` when not running as root, which also saves them to
+/// the system config when `save` is set
+fn apply_curve(device: &mut Device, points: &[CurvePoint], save: bool) -> Result<(), String> {
+    if !is_root() {
+        let offsets: Vec<String> = points.iter().map(|point| format!("{}:{}", pstate_name(point.pstate), point.offset)).collect();
+        return pkexec_set(&["--freq-offset-pstate".to_string(), offsets.join(",")], save);
+    }
+    for point in points {
+        device
+            .set_clock_offset(Clock::Graphics, point.pstate, point.offset)
+            .map_err(|e| format!("{}: {:?}", pstate_name(point.pstate), e))?;
+    }
+    Ok(())
+}
+
+/// The curve as last read or applied and as edited
+#[derive(Default)]
+struct CurveEditor {
+    stock: Vec<CurvePoint>,
+    points: Vec<CurvePoint>,
+    status: String,
+}
+
+const CURVE_HEIGHT: f32 = 260.0;
+const CURVE_MARGIN: f32 = 40.0;
+
+impl CurveEditor {
+    fn read(&mut self, nvml: Option<&Nvml>) {
+        let result = nvml
+            .ok_or_else(|| "NVML isn't available".to_string())
+            .and_then(|nvml| nvml.device_by_index(0).map_err(|e| format!("{:?}", e)))
+            .and_then(|device| read_curve(&device));
+        match result {
+            Ok(points) => {
+                self.status = format!("Read {} points", points.len());
+                self.stock = points.clone();
+                self.points = points;
+            }
+            Err(e) => self.status = format!("Failed to read the curve: {}", e),
+        }
+    }
+
+    /// Draws the curve with a point per pstate that can be dragged up and
+    /// down within the offset range the driver allows
+    fn plot(&mut self, ui: &mut egui::Ui) {
+        let width = ui.available_width().max(200.0);
+        let (response, painter) = ui.allocate_painter(egui::vec2(width, CURVE_HEIGHT), egui::Sense::hover());
+        let rect = response.rect;
+        painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, egui::Color32::DARK_GRAY));
+
+        let low = self.points.iter().map(|p| p.max_clock as i32 + p.min_offset).min().unwrap_or(0).max(0);
+        let high = self.points.iter().map(|p| p.max_clock as i32 + p.max_offset).max().unwrap_or(1).max(low + 1);
+        let mhz_per_pixel = (high - low) as f32 / (CURVE_HEIGHT - 2.0 * CURVE_MARGIN);
+        let y = |clock: i32| rect.bottom() - CURVE_MARGIN - (clock - low) as f32 / mhz_per_pixel;
+        let step = (width - 2.0 * CURVE_MARGIN) / (self.points.len().max(2) - 1) as f32;
+        let x = |index: usize| rect.left() + CURVE_MARGIN + index as f32 * step;
+
+        let stock: Vec<egui::Pos2> = self.stock.iter().enumerate().map(|(i, p)| egui::pos2(x(i), y(p.clock()))).collect();
+        painter.add(egui::Shape::line(stock, egui::Stroke::new(1.0, egui::Color32::GRAY)));
+
+        for index in 0..self.points.len() {
+            let center = egui::pos2(x(index), y(self.points[index].clock()));
+            let id = response.id.with(("vf_point", index));
+            let handle = ui.interact(egui::Rect::from_center_size(center, egui::vec2(14.0, 14.0)), id, egui::Sense::drag());
+            let point = &mut self.points[index];
+            if handle.dragged() {
+                let offset = point.offset as f32 - handle.drag_delta().y * mhz_per_pixel;
+                point.offset = (offset.round() as i32).clamp(point.min_offset, point.max_offset);
+            }
+        }
+
+        let edited: Vec<egui::Pos2> = self.points.iter().enumerate().map(|(i, p)| egui::pos2(x(i), y(p.clock()))).collect();
+        painter.add(egui::Shape::line(edited.clone(), egui::Stroke::new(2.0, egui::Color32::LIGHT_GREEN)));
+        let font = egui::FontId::proportional(12.0);
+        for (point, pos) in self.points.iter().zip(edited) {
+            painter.circle_filled(pos, 5.0, egui::Color32::LIGHT_GREEN);
+            let label = format!("{} {} MHz ({:+})", pstate_name(point.pstate), point.clock(), point.offset);
+            painter.text(pos - egui::vec2(0.0, 10.0), egui::Align2::CENTER_BOTTOM, label, font.clone(), egui::Color32::WHITE);
+        }
+        painter.text(rect.left_top() + egui::vec2(4.0, 4.0), egui::Align2::LEFT_TOP, format!("{} MHz", high), font.clone(), egui::Color32::GRAY);
+        painter.text(rect.left_bottom() + egui::vec2(4.0, -4.0), egui::Align2::LEFT_BOTTOM, format!("{} MHz", low), font, egui::Color32::GRAY);
+    }
+}
+
+/// Shared between the GUI and the search worker
+#[derive(Default)]
+struct SearchControl {
+    cancel: AtomicBool,
+    pause: AtomicBool,
+}
+
+impl SearchControl {
+    /// Blocks while paused, then returns whether the search should stop
+    fn should_stop(&self) -> bool {
+        while self.pause.load(Ordering::SeqCst) && !self.cancel.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        self.cancel.load(Ordering::SeqCst)
+    }
+}
+
+/// Progress sent from the search worker to the GUI
+enum SearchEvent {
+    Record(Record),
+    /// What the search concluded beyond its best record
+    Result(String),
+    Finished,
+}
+
 struct GuiApp {
     nvml: Option<Nvml>,
     records: Vec<Record>,
-    running: bool,
+    /// Controls and progress of the search while one runs on its worker thread
+    search: Option<(Arc<SearchControl>, Receiver<SearchEvent>)>,
     supported: Option<SupportedClocks>,
+    params: SearchParams,
+    benchmarks: Vec<Benchmark>,
+    sort: SortColumn,
+    sort_descending: bool,
+    /// Result of the last export, shown below the export buttons
+    export_status: String,
+    /// Also write applied records to this config file for `nvidia_oc` to apply at boot
+    save_to_config: bool,
+    config_path: String,
+    apply_status: String,
+    /// Results database shared with the CLI's `runs` and `trials` commands
+    store: Option<store::Store>,
+    /// Run in `store` the records of the current search belong to
+    run_id: Option<i64>,
+    /// Conclusion of the last search, e.g. the memory only search's highest
+    /// clean offset
+    search_result: String,
+    /// Records of earlier runs loaded from the database or a CSV of older
+    /// versions, overlaid in the plot by name
+    imported: Vec<(String, Vec<Record>)>,
+    import_path: String,
+    import_status: String,
+    curve: CurveEditor,
 }
 
 impl Default for GuiApp {
     fn default() -> Self {
-        Self { nvml: None, records: Vec::new(), running: false, supported: None }
+        Self {
+            nvml: None,
+            records: Vec::new(),
+            search: None,
+            supported: None,
+            params: SearchParams::default(),
+            benchmarks: Vec::new(),
+            sort: SortColumn::Score,
+            sort_descending: true,
+            export_status: String::new(),
+            save_to_config: false,
+            config_path: SYSTEM_CONFIG_PATH.to_string(),
+            apply_status: String::new(),
+            store: None,
+            run_id: None,
+            search_result: String::new(),
+            imported: Vec::new(),
+            import_path: String::new(),
+            import_status: String::new(),
+            curve: CurveEditor::default(),
+        }
     }
 }
 
-impl epi::App for GuiApp {
-    fn name(&self) -> &str { "NVIDIA Undervolt" }
+impl GuiApp {
+    fn search_controls(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        if let Some((control, events)) = &self.search {
+            let mut finished = false;
+            for event in events.try_iter() {
+                match event {
+                    SearchEvent::Record(record) => {
+                        self.save_trial(&record);
+                        self.records.push(record);
+                    }
+                    SearchEvent::Result(result) => {
+                        self.save_result(&result);
+                        self.search_result = result;
+                    }
+                    SearchEvent::Finished => finished = true,
+                }
+            }
+
+            let paused = control.pause.load(Ordering::SeqCst);
+            ui.horizontal(|ui| {
+                if control.cancel.load(Ordering::SeqCst) {
+                    ui.label("Cancelling, restoring defaults...");
+                } else if paused {
+                    ui.label("Paused after the current benchmark");
+                } else {
+                    ui.label("Benchmark running...");
+                }
+                if ui.button(if paused { "Resume" } else { "Pause" }).clicked() {
+                    control.pause.store(!paused, Ordering::SeqCst);
+                }
+                if ui.button("Cancel").clicked() {
+                    control.cancel.store(true, Ordering::SeqCst);
+                }
+            });
+
+            if finished {
+                self.search = None;
+            } else {
+                // Keep polling the worker even without input events
+                ctx.request_repaint_after(Duration::from_millis(500));
+            }
+        } else if !is_root() {
+            ui.label("The search needs root, restart the GUI with pkexec or sudo");
+        } else if ui.button("Start Undervolt Search").clicked() {
+            let control = Arc::new(SearchControl::default());
+            let (sender, receiver) = mpsc::channel();
+            let (supported, params) = (self.supported.clone(), self.params.clone());
+            let worker_control = control.clone();
+            self.records.clear();
+            self.search_result.clear();
+            self.start_run();
+            // NVML handles can't cross threads, so the worker opens its own
+            std::thread::spawn(move || {
+                SEARCHING.store(true, Ordering::SeqCst);
+                let searched = Nvml::init().and_then(|nvml| {
+                    let mut device = nvml.device_by_index(0)?;
+                    run_search(&mut device, &supported, &params, &worker_control, &sender);
+                    Ok(())
+                });
+                // Otherwise the search would just stop without a word
+                if let Err(e) = searched {
+                    let _ = sender.send(SearchEvent::Result(format!("Search failed, no GPU to tune: {:?}", e)));
+                }
+                SEARCHING.store(false, Ordering::SeqCst);
+                let _ = sender.send(SearchEvent::Finished);
+            });
+            self.search = Some((control, receiver));
+        }
+    }
+
+    /// Records a new run in the results database for the search about to start
+    fn start_run(&mut self) {
+        self.run_id = None;
+        let Some(store) = &self.store else {
+            return;
+        };
+        let device = self.nvml.as_ref().and_then(|nvml| nvml.device_by_index(0).ok());
+        let run = store::Run {
+            id: 0,
+            started_ms: store::now_ms(),
+            gpu_name: self.gpu_name(),
+            gpu_uuid: device.and_then(|device| device.uuid().ok()).unwrap_or_default(),
+            driver_version: self.nvml.as_ref().and_then(|nvml| nvml.sys_driver_version().ok()).unwrap_or_default(),
+            strategy: self.params.strategy.label().to_string(),
+            objective: self.params.objective.label().to_string(),
+            benchmark: self.params.benchmark.as_ref().map_or("Placeholder", |b| b.name.as_str()).to_string(),
+            result: None,
+            trials: 0,
+        };
+        match store.start_run(&run) {
+            Ok(id) => self.run_id = Some(id),
+            Err(e) => eprintln!("Failed to record the run in {}: {}", store::default_path().display(), e),
+        }
+    }
+
+    fn save_trial(&self, record: &Record) {
+        let (Some(store), Some(run_id)) = (&self.store, self.run_id) else {
+            return;
+        };
+        if let Err(e) = store.add_trial(&record.to_trial(run_id)) {
+            eprintln!("Failed to record the trial in {}: {}", store::default_path().display(), e);
+        }
+    }
+
+    fn save_result(&self, result: &str) {
+        let (Some(store), Some(run_id)) = (&self.store, self.run_id) else {
+            return;
+        };
+        if let Err(e) = store.finish_run(run_id, result) {
+            eprintln!("Failed to record the result in {}: {}", store::default_path().display(), e);
+        }
+    }
+
+    /// Records settings applied to GPU 0, under their config keys
+    fn save_applied(&self, uuid: &str, settings: serde_json::Value) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Err(e) = store.add_applied(uuid, &settings) {
+            eprintln!("Failed to record the applied settings in {}: {}", store::default_path().display(), e);
+        }
+    }
+
+    fn gpu_name(&self) -> String {
+        self.nvml
+            .as_ref()
+            .and_then(|nvml| nvml.device_by_index(0).ok())
+            .and_then(|device| device.name().ok())
+            .unwrap_or_else(|| "unknown GPU".to_string())
+    }
+
+    fn import_controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Compare with run or CSV");
+            ui.text_edit_singleline(&mut self.import_path);
+            if ui.button("Import").clicked() {
+                match self.load_import() {
+                    Ok((name, records)) => {
+                        self.import_status = format!("Loaded {} records from {}", records.len(), name);
+                        self.imported.push((name, records));
+                    }
+                    Err(e) => self.import_status = format!("Import failed: {}", e),
+                }
+            }
+            if !self.imported.is_empty() && ui.button("Clear imports").clicked() {
+                self.imported.clear();
+                self.import_status.clear();
+            }
+            ui.label(&self.import_status);
+        });
+    }
+
+    /// The records of a run id from `nvidia_oc runs`, or of a results CSV
+    /// written before runs were kept in the database
+    fn load_import(&self) -> Result<(String, Vec<Record>), String> {
+        let input = self.import_path.trim();
+        let Ok(run_id) = input.parse::<i64>() else {
+            let path = PathBuf::from(input);
+            let name = path.file_name().map_or(input.to_string(), |n| n.to_string_lossy().into_owned());
+            return Ok((name, load_records(&path)?));
+        };
+        let store = self.store.as_ref().ok_or("the results database isn't open")?;
+        let trials = store.run_trials(run_id).map_err(|e| e.to_string())?;
+        if trials.is_empty() {
+            return Err(format!("run {} has no trials", run_id));
+        }
+        Ok((format!("Run {}", run_id), trials.iter().map(Record::from_trial).collect()))
+    }
+
+    /// Reapplies exactly the settings of a record, and writes them to the CLI
+    /// config when asked to
+    fn apply_record(&mut self, record: &Record) {
+        let Some(mut device) = self.nvml.as_ref().and_then(|nvml| nvml.device_by_index(0).ok()) else {
+            self.apply_status = "No GPU available".to_string();
+            return;
+        };
+        let save_with_cli = self.save_with_cli();
+        if save_with_cli || !apply_settings(&mut device, record.power_limit, record.freq_offset, record.mem_offset, record.min_clock, record.max_clock) {
+            if let Err(e) = apply_with_pkexec(record, save_with_cli) {
+                self.apply_status = format!("Failed to apply the record: {}", e);
+                return;
+            }
+        }
+        self.apply_status = format!("Applied {}, {:+} MHz core, {:+} MHz memory", power::format_watts(record.power_limit), record.freq_offset, record.mem_offset);
+
+        let uuid = device.uuid().unwrap_or_else(|_| "0".to_string());
+        self.save_applied(&uuid, serde_json::json!({
+            "powerLimit": record.power_limit,
+            "freqOffset": record.freq_offset,
+            "memOffset": record.mem_offset,
+            "minClock": record.min_clock,
+            "maxClock": record.max_clock,
+        }));
+        if self.save_to_config {
+            let saved = if save_with_cli { Ok(()) } else { save_to_config(std::path::Path::new(&self.config_path), &uuid, record) };
+            match saved {
+                Ok(()) => self.apply_status += &format!(", saved to {}", self.config_path),
+                Err(e) => self.apply_status += &format!(", failed to save to {}: {}", self.config_path, e),
+            }
+        }
+    }
+
+    /// Whether the pkexec'd CLI saves what it applies. It only writes the
+    /// system config, which the GUI can't unless it's root; any other config
+    /// file the GUI writes itself.
+    fn save_with_cli(&self) -> bool {
+        self.save_to_config && !is_root() && self.config_path == SYSTEM_CONFIG_PATH
+    }
+
+    fn apply_controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.save_to_config, "Save applied records to");
+            ui.text_edit_singleline(&mut self.config_path);
+            ui.label(&self.apply_status);
+        });
+    }
+
+    /// The per-pstate V/F curve of GPU 0 with draggable points, applied
+    /// through the per-pstate offset API
+    fn curve_controls(&mut self, ui: &mut egui::Ui) {
+        let searching = self.search.is_some();
+        ui.horizontal(|ui| {
+            if ui.button("Read curve").clicked() {
+                self.curve.read(self.nvml.as_ref());
+            }
+            if ui.add_enabled(!self.curve.points.is_empty(), egui::Button::new("Zero offsets")).clicked() {
+                for point in &mut self.curve.points {
+                    point.offset = 0;
+                }
+            }
+            if ui.add_enabled(!searching && !self.curve.points.is_empty(), egui::Button::new("Apply curve")).clicked() {
+                self.apply_curve();
+            }
+            ui.label(&self.curve.status);
+        });
+        if !self.curve.points.is_empty() {
+            self.curve.plot(ui);
+            ui.label("Gray: curve as read, green: edited. Drag a point to change the offset of its pstate.");
+        }
+    }
+
+    fn apply_curve(&mut self) {
+        let Some(mut device) = self.nvml.as_ref().and_then(|nvml| nvml.device_by_index(0).ok()) else {
+            self.curve.status = "No GPU available".to_string();
+            return;
+        };
+        let save_with_cli = self.save_with_cli();
+        if let Err(e) = apply_curve(&mut device, &self.curve.points, save_with_cli) {
+            self.curve.status = format!("Failed to apply the curve: {}", e);
+            return;
+        }
+        self.curve.stock = self.curve.points.clone();
+        self.curve.status = "Applied the curve".to_string();
+
+        let uuid = device.uuid().unwrap_or_else(|_| "0".to_string());
+        let offsets: serde_json::Map<String, serde_json::Value> = self.curve.points.iter().map(|point| (pstate_name(point.pstate), point.offset.into())).collect();
+        self.save_applied(&uuid, serde_json::json!({ "freqOffsetPstate": offsets }));
+        if self.save_to_config {
+            let saved = if save_with_cli { Ok(()) } else { save_curve_to_config(std::path::Path::new(&self.config_path), &uuid, &self.curve.points) };
+            match saved {
+                Ok(()) => self.curve.status += &format!(", saved to {}", self.config_path),
+                Err(e) => self.curve.status += &format!(", failed to save to {}: {}", self.config_path, e),
+            }
+        }
+    }
+
+    /// Writes the plot or report to ~/Documents
+    fn export_buttons(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let mut path = documents_dir();
+            let result = if ui.button("Export PNG").clicked() {
+                path.push("nvidia_oc_plot.png");
+                Some(save_plot_png(&self.records, &path))
+            } else if ui.button("Export SVG").clicked() {
+                path.push("nvidia_oc_plot.svg");
+                Some(std::fs::write(&path, plot_svg(&self.records)).map_err(|e| e.to_string()))
+            } else if ui.button("Export HTML report").clicked() {
+                path.push("nvidia_oc_report.html");
+                let report = html_report(&self.records, &self.params, &self.gpu_name());
+                Some(std::fs::write(&path, report).map_err(|e| e.to_string()))
+            } else {
+                None
+            };
+            match result {
+                Some(Ok(())) => self.export_status = format!("Saved {}", path.display()),
+                Some(Err(e)) => self.export_status = format!("Export failed: {}", e),
+                None => {}
+            }
+            ui.label(&self.export_status);
+        });
+    }
+
+    /// All records, sortable by clicking a column header. The best records by
+    /// score and by efficiency are highlighted.
+    fn results_table(&mut self, ui: &mut egui::Ui) {
+        let score_params = SearchParams { objective: Objective::Score, ..self.params.clone() };
+        let efficiency_params = SearchParams { objective: Objective::Efficiency, ..self.params.clone() };
+        let best_score = best_record(&self.records, &score_params);
+        let best_efficiency = best_record(&self.records, &efficiency_params);
+
+        let mut rows: Vec<&Record> = self.records.iter().collect();
+        rows.sort_by(|a, b| self.sort.key(a).total_cmp(&self.sort.key(b)));
+        if self.sort_descending {
+            rows.reverse();
+        }
+
+        let mut clicked = None;
+        let mut apply = None;
+        let searching = self.search.is_some();
+        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            egui::Grid::new("results_table").striped(true).show(ui, |ui| {
+                for column in SortColumn::ALL {
+                    let arrow = match (column == self.sort, self.sort_descending) {
+                        (true, true) => " v",
+                        (true, false) => " ^",
+                        _ => "",
+                    };
+                    if ui.button(format!("{}{}", column.label(), arrow)).clicked() {
+                        clicked = Some(column);
+                    }
+                }
+                ui.label("Clocks (MHz)");
+                ui.label("");
+                ui.end_row();
+
+                for record in rows {
+                    let color = if best_efficiency.is_some_and(|best| std::ptr::eq(best, record)) {
+                        Some(egui::Color32::LIGHT_GREEN)
+                    } else if best_score.is_some_and(|best| std::ptr::eq(best, record)) {
+                        Some(egui::Color32::LIGHT_BLUE)
+                    } else {
+                        None
+                    };
+                    let cell = |ui: &mut egui::Ui, text: String| {
+                        let text = egui::RichText::new(text);
+                        ui.label(match color { Some(color) => text.color(color).strong(), None => text });
+                    };
+                    cell(ui, format!("{}", record.power_limit as f64 / 1000.0));
+                    cell(ui, format!("{}", record.freq_offset));
+                    cell(ui, format!("{}", record.mem_offset));
+                    cell(ui, format!("{:.0}", record.score));
+                    cell(ui, format!("{:.2}", record.avg_power));
+                    cell(ui, format!("{:.2}", record.efficiency()));
+                    cell(ui, format!("{:.0}", record.energy_j));
+                    cell(ui, format!("{:.4}", record.score_per_joule()));
+                    cell(ui, format!("{:.0}", record.telemetry.avg_clock));
+                    cell(ui, format!("{:.0}%", record.telemetry.throttled * 100.0));
+                    cell(ui, format!("{}-{}", record.min_clock, record.max_clock));
+                    if ui.add_enabled(!searching, egui::Button::new("Apply")).clicked() {
+                        apply = Some(record.clone());
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+        if let Some(record) = apply {
+            self.apply_record(&record);
+        }
+        if let Some(column) = clicked {
+            if column == self.sort {
+                self.sort_descending = !self.sort_descending;
+            } else {
+                self.sort = column;
+                self.sort_descending = true;
+            }
+        }
+        ui.label("Green: best efficiency, blue: best score");
+    }
+}
 
-    fn setup(&mut self, ctx: &eframe::CreationContext<'_>) {
+impl GuiApp {
+    fn setup(&mut self, ctx: &egui::Context) {
         if let Ok(nvml) = Nvml::init() {
             self.nvml = Some(nvml);
         }
         self.supported = query_supported_clocks();
-        if let Some(ref style) = ctx.egui_ctx.style().visuals.widgets.active {
-            let mut style = ctx.egui_ctx.style().clone();
-            style.visuals = egui::Visuals::dark();
-            ctx.egui_ctx.set_style(style);
+        match store::Store::open(&store::default_path()) {
+            Ok(store) => self.store = Some(store),
+            Err(e) => eprintln!("Failed to open {}, results won't be kept: {}", store::default_path().display(), e),
         }
+        self.curve.read(self.nvml.as_ref());
+        self.benchmarks = load_benchmarks();
+        self.params.benchmark = self.benchmarks.first().cloned();
+        ctx.set_visuals(egui::Visuals::dark());
     }
+}
 
-    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
+impl eframe::App for GuiApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
-            if self.running {
-                ui.label("Benchmark running...");
-            } else if ui.button("Start Undervolt Search").clicked() {
-                if let Some(ref nvml) = self.nvml {
-                    if let Ok(mut device) = nvml.device_by_index(0) {
-                        self.running = true;
-                        self.records.clear();
-                        run_search(&mut device, &self.supported, &mut self.records);
-                        self.running = false;
-                    }
-                }
-            }
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Strategy")
+                    .selected_text(self.params.strategy.label())
+                    .show_ui(ui, |ui| {
+                        for strategy in [Strategy::Linear, Strategy::Bisection, Strategy::Bayesian, Strategy::MemoryOnly] {
+                            ui.selectable_value(&mut self.params.strategy, strategy, strategy.label());
+                        }
+                    });
+                egui::ComboBox::from_label("Objective")
+                    .selected_text(self.params.objective.label())
+                    .show_ui(ui, |ui| {
+                        for objective in Objective::ALL {
+                            ui.selectable_value(&mut self.params.objective, objective, objective.label());
+                        }
+                    });
+                ui.label("Power step (W)");
+                ui.add(egui::DragValue::new(&mut self.params.limits.power_step_w).clamp_range(1..=100));
+                ui.label("Min power (W)");
+                ui.add(egui::DragValue::new(&mut self.params.limits.min_power_w));
+                ui.label("Offset stride");
+                ui.add(egui::DragValue::new(&mut self.params.limits.offset_stride).clamp_range(1..=20));
+                ui.label("Crash budget");
+                ui.add(egui::DragValue::new(&mut self.params.limits.max_crashes));
+                ui.label("Run (s)");
+                ui.add(egui::DragValue::new(&mut self.params.limits.benchmark_seconds).clamp_range(1..=3600));
+                ui.label("Soak runs");
+                ui.add(egui::DragValue::new(&mut self.params.limits.soak_runs).clamp_range(1..=20));
+                ui.label("Bayesian trials");
+                ui.add(egui::DragValue::new(&mut self.params.limits.bayesian_trials).clamp_range(1..=500));
+                ui.label("Mem step (MHz)");
+                ui.add(egui::DragValue::new(&mut self.params.limits.mem_step_mhz).clamp_range(1..=500));
+                ui.label("Max mem offset");
+                ui.add(egui::DragValue::new(&mut self.params.limits.max_mem_offset));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Min score");
+                ui.add(egui::DragValue::new(&mut self.params.min_score).clamp_range(0.0..=f32::MAX));
+                let selected = self.params.benchmark.as_ref().map_or("Placeholder", |b| b.name.as_str()).to_string();
+                egui::ComboBox::from_label("Benchmark")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for benchmark in &self.benchmarks {
+                            let checked = self.params.benchmark.as_ref().is_some_and(|b| b.name == benchmark.name);
+                            if ui.selectable_label(checked, &benchmark.name).clicked() {
+                                self.params.benchmark = Some(benchmark.clone());
+                            }
+                        }
+                    });
+                let selected = self.params.torture.as_ref().map_or("None", |b| b.name.as_str()).to_string();
+                egui::ComboBox::from_label("Torture")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        if ui.selectable_label(self.params.torture.is_none(), "None").clicked() {
+                            self.params.torture = None;
+                        }
+                        for benchmark in &self.benchmarks {
+                            let checked = self.params.torture.as_ref().is_some_and(|b| b.name == benchmark.name);
+                            if ui.selectable_label(checked, &benchmark.name).clicked() {
+                                self.params.torture = Some(benchmark.clone());
+                            }
+                        }
+                    });
+                ui.label("Torture (s)");
+                ui.add(egui::DragValue::new(&mut self.params.limits.torture_seconds).clamp_range(1..=7200));
+            });
+
+            self.search_controls(ctx, ui);
 
-            egui::plot::Plot::new("results").show(ui, |plot_ui| {
-                let points: Vec<_> = self.records.iter().map(|r| egui::plot::PlotPoint::new(r.power_limit as f64/1000.0, r.score as f64)).collect();
-                plot_ui.points(egui::plot::Points::new(points));
+            self.import_controls(ui);
+
+            let to_points = |records: &[Record]| -> PlotPoints { records.iter().map(|r| [r.power_limit as f64/1000.0, r.score as f64]).collect() };
+            Plot::new("results").legend(Legend::default()).show(ui, |plot_ui| {
+                let (frontier, dominated): (Vec<Record>, Vec<Record>) = self.records.iter().cloned().partition(|r| !is_dominated(r, &self.records));
+                plot_ui.points(Points::new(to_points(&dominated)).name("Dominated").color(egui::Color32::GRAY));
+                plot_ui.points(Points::new(to_points(&frontier)).name("Pareto frontier (score vs. avg power)").color(egui::Color32::LIGHT_GREEN).radius(4.0));
+                for (name, records) in &self.imported {
+                    plot_ui.points(Points::new(to_points(records)).name(name).shape(MarkerShape::Diamond));
+                }
             });
 
-            if let Some(record) = self.records.last() {
+            if let Some(best) = best_record(&self.records, &self.params) {
                 ui.label(format!(
-                    "Last result - PL: {}W, Freq: {} MHz, Mem: {} MHz, Clocks: {}-{} MHz, Score: {:.0}, Avg Power: {:.2}W",
-                    record.power_limit / 1000,
-                    record.freq_offset,
-                    record.mem_offset,
-                    record.min_clock,
-                    record.max_clock,
-                    record.score,
-                    record.avg_power
+                    "Best by {} - PL: {}, Freq: {} MHz, Mem: {} MHz, Score: {:.0}, Avg Power: {:.2}W, Efficiency: {:.2}/W, Energy: {:.0} J, {:.4}/J, Avg Clock: {:.0} MHz, Throttled: {:.0}%",
+                    self.params.objective.label(),
+                    power::format_watts(best.power_limit),
+                    best.freq_offset,
+                    best.mem_offset,
+                    best.score,
+                    best.avg_power,
+                    best.efficiency(),
+                    best.energy_j,
+                    best.score_per_joule(),
+                    best.telemetry.avg_clock,
+                    best.telemetry.throttled * 100.0
                 ));
+            } else if !self.records.is_empty() {
+                ui.label("No record reached the minimum score.");
             }
+            if !self.search_result.is_empty() {
+                ui.label(&self.search_result);
+            }
+
+            self.export_buttons(ui);
+            self.apply_controls(ui);
+            self.results_table(ui);
+            ui.collapsing("V/F curve", |ui| self.curve_controls(ui));
         });
     }
 }
 
-struct BenchResult { score: f32, avg_power: f32 }
+/// Energy a GPU consumed during a benchmark run, from the hardware counter of
+/// Volta and newer or, without it, from integrating the power draw
+struct EnergyMeter {
+    counter_start: Option<u64>,
+    integrated_j: f64,
+    last_sample: std::time::Instant,
+}
+
+impl EnergyMeter {
+    fn new(device: &Device) -> Self {
+        Self { counter_start: device.total_energy_consumption().ok(), integrated_j: 0.0, last_sample: std::time::Instant::now() }
+    }
+
+    fn sample(&mut self, device: &Device) {
+        let elapsed = self.last_sample.elapsed().as_secs_f64();
+        self.last_sample = std::time::Instant::now();
+        if let Ok(power) = device.power_usage() {
+            self.integrated_j += power as f64 / 1000.0 * elapsed;
+        }
+    }
 
-fn run_benchmark(_device: &mut Device) -> Option<BenchResult> {
-    // Placeholder: run your preferred benchmark here for ~5 minutes
-    // Return None if system becomes unstable
-    Some(BenchResult { score: 0.0, avg_power: 0.0 })
+    /// The joules consumed since the meter was created
+    fn finish(&mut self, device: &Device) -> f32 {
+        match (self.counter_start, device.total_energy_consumption()) {
+            (Some(start), Ok(end)) => end.saturating_sub(start) as f32 / 1000.0,
+            _ => {
+                self.sample(device);
+                self.integrated_j as f32
+            }
+        }
+    }
+}
+
+struct BenchResult { score: f32, avg_power: f32, energy_j: f32, telemetry: TrialTelemetry }
+
+fn run_benchmark(device: &mut Device, benchmark: Option<&Benchmark>, duration: Duration, control: &SearchControl) -> Option<BenchResult> {
+    let Some(benchmark) = benchmark else {
+        // Placeholder: run your preferred benchmark here for ~5 minutes
+        // Return None if system becomes unstable
+        return Some(BenchResult { score: 0.0, avg_power: 0.0, energy_j: 0.0, telemetry: TrialTelemetry::default() });
+    };
+    let (program, args) = benchmark.command.split_first()?;
+    let args = args.iter().map(|arg| arg.replace("{duration}", &duration.as_secs().to_string()));
+    let started = SystemTime::now();
+    let mut energy = EnergyMeter::new(device);
+    let mut child = Command::new(program).args(args).stdout(Stdio::piped()).spawn().ok()?;
+
+    // Drain stdout on a thread so a chatty benchmark can't fill the pipe
+    let mut stdout = child.stdout.take()?;
+    let reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        output
+    });
+
+    let mut power_samples = Vec::new();
+    let mut telemetry = TelemetrySampler::default();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if control.cancel.load(Ordering::SeqCst) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Ok(None) => {
+                if let Ok(power) = device.power_usage() {
+                    power_samples.push(power as f32 / 1000.0);
+                }
+                energy.sample(device);
+                telemetry.sample(device);
+                std::thread::sleep(Duration::from_millis(500));
+            }
+            Err(_) => return None,
+        }
+    };
+    let energy_j = energy.finish(device);
+    let output = reader.join().unwrap_or_default();
+    // A failing benchmark is treated like a crash: the settings are unstable
+    if !status.success() {
+        return None;
+    }
+    if let Some(pattern) = &benchmark.error_pattern {
+        match Regex::new(pattern) {
+            Ok(regex) if regex.is_match(&output) => {
+                eprintln!("{} reported errors", benchmark.name);
+                return None;
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Invalid error pattern for {}: {}", benchmark.name, e),
+        }
+    }
+
+    let score = match benchmark.score.extract(&output, started) {
+        Ok(score) => score,
+        Err(e) => {
+            eprintln!("Failed to get score from {}: {}", benchmark.name, e);
+            return None;
+        }
+    };
+    let avg_power = if power_samples.is_empty() { 0.0 } else { power_samples.iter().sum::<f32>() / power_samples.len() as f32 };
+    Some(BenchResult { score, avg_power, energy_j, telemetry: telemetry.finish() })
+}
+
+/// Runs the scored benchmark and, when it passes, the optional torture stage.
+/// Benchmarks that pass can still fall over under a sustained power virus,
+/// so a torture failure counts as a crash; its own score is ignored.
+fn run_stages(device: &mut Device, params: &SearchParams, control: &SearchControl) -> Option<BenchResult> {
+    let res = run_benchmark(device, params.benchmark.as_ref(), Duration::from_secs(params.limits.benchmark_seconds), control)?;
+    if let Some(torture) = &params.torture {
+        run_benchmark(device, Some(torture), Duration::from_secs(params.limits.torture_seconds), control)?;
+    }
+    Some(res)
 }
 
 fn apply_settings(
@@ -153,178 +1650,784 @@ fn apply_settings(
             .is_ok()
 }
 
+/// Applies a record through `pkexec nvidia_oc set` when the GUI isn't running
+/// as root, saving it to the system config too when `save` is set
+fn apply_with_pkexec(record: &Record, save: bool) -> Result<(), String> {
+    pkexec_set(&[
+        "--power-limit".to_string(), format!("{}mW", record.power_limit),
+        "--freq-offset".to_string(), record.freq_offset.to_string(),
+        "--mem-offset".to_string(), record.mem_offset.to_string(),
+        "--min-clock".to_string(), record.min_clock.to_string(),
+        "--max-clock".to_string(), record.max_clock.to_string(),
+    ], save)
+}
+
+/// Runs `pkexec nvidia_oc set` on GPU 0 with the given arguments, with
+/// `--save` into the system config if `save` is set. The GUI can't write
+/// /etc itself, and under pkexec the CLI only saves there. Without the
+/// policy from `nvidia_oc polkit --install` pkexec asks for the admin
+/// password every time.
+fn pkexec_set(args: &[String], save: bool) -> Result<(), String> {
+    // pkexec matches the policy by absolute path, so prefer the CLI installed
+    // next to the GUI
+    let cli = std::env::current_exe().ok().map(|exe| exe.with_file_name("nvidia_oc")).filter(|cli| cli.exists())
+        .or_else(|| which::which("nvidia_oc").ok())
+        .ok_or("nvidia_oc not found, are you running as root?")?;
+    let mut command = Command::new("pkexec");
+    command.arg(cli).args(["set", "--index", "0", "--no-escalate"]);
+    if save {
+        command.arg("--save");
+    }
+    let status = command
+        .args(args)
+        .status()
+        .map_err(|e| format!("failed to run pkexec: {}", e))?;
+    if !status.success() {
+        return Err(format!("pkexec nvidia_oc exited with {}", status));
+    }
+    Ok(())
+}
+
+/// What the search starts from and which offsets it may try
+struct SearchSpace {
+    default_limit: u32,
+    default_freq_offset: i32,
+    default_mem_offset: i32,
+    default_clock: u32,
+    /// Lowest power limit to try, never below what the driver accepts
+    min_limit: u32,
+    /// Power limit change per step in mW
+    step_power: u32,
+    /// Candidate core offsets in increasing order
+    freq_steps: Vec<i32>,
+    /// Candidate memory offsets in increasing order
+    mem_steps: Vec<i32>,
+}
+
+impl SearchSpace {
+    fn new(device: &Device, supported: &Option<SupportedClocks>, limits: &SearchLimits) -> Self {
+        let base_graphics = device.clock_info(Clock::Graphics).unwrap_or(0);
+        let base_memory = device.clock_info(Clock::Memory).unwrap_or(0);
+        let steps = |clocks: &[u32], base: u32| -> Vec<i32> {
+            clocks
+                .iter()
+                .rev()
+                .filter(|&&c| c <= base)
+                .step_by(limits.offset_stride.max(1))
+                .map(|&c| c as i32 - base as i32)
+                .collect()
+        };
+
+        Self {
+            default_limit: device.enforced_power_limit().unwrap_or(0),
+            default_freq_offset: device.gpc_clock_vf_offset().unwrap_or(0),
+            default_mem_offset: device.mem_clock_vf_offset().unwrap_or(0),
+            default_clock: device.max_clock_info(Clock::Graphics).unwrap_or(0),
+            min_limit: device
+                .power_management_limit_constraints()
+                .map_or(0, |c| c.min_limit)
+                .max(limits.min_power_w * 1000),
+            step_power: limits.power_step_w.max(1) * 1000,
+            freq_steps: supported.as_ref().map(|s| steps(&s.graphics, base_graphics)).unwrap_or_default(),
+            mem_steps: supported.as_ref().map(|s| steps(&s.memory, base_memory)).unwrap_or_default(),
+        }
+    }
+}
+
 fn run_search(
     device: &mut Device,
     supported: &Option<SupportedClocks>,
-    records: &mut Vec<Record>,
+    params: &SearchParams,
+    control: &SearchControl,
+    events: &Sender<SearchEvent>,
 ) {
-    let default_limit = device.enforced_power_limit().unwrap_or(0);
-    let default_freq_offset = device.gpc_clock_vf_offset().unwrap_or(0);
-    let default_mem_offset = device.mem_clock_vf_offset().unwrap_or(0);
-    let base_graphics = device.clock_info(Clock::Graphics).unwrap_or(0);
-    let base_memory = device.clock_info(Clock::Memory).unwrap_or(0);
-    let default_clock = device.max_clock_info(Clock::Graphics).unwrap_or(0);
-
-    let freq_steps: Vec<i32> = supported
-        .as_ref()
-        .map(|s| {
-            s.graphics
-                .iter()
-                .rev()
-                .filter(|&&c| c <= base_graphics)
-                .map(|&c| c as i32 - base_graphics as i32)
-                .collect()
-        })
-        .unwrap_or_default();
-    let mem_steps: Vec<i32> = supported
-        .as_ref()
-        .map(|s| {
-            s.memory
-                .iter()
-                .rev()
-                .filter(|&&c| c <= base_memory)
-                .map(|&c| c as i32 - base_memory as i32)
-                .collect()
-        })
-        .unwrap_or_default();
+    let space = SearchSpace::new(device, supported, &params.limits);
+    let min_clock = 0u32;
+
+    match params.strategy {
+        Strategy::Linear => linear_search(device, &space, params, control, events),
+        Strategy::Bisection => bisection_search(device, &space, params, control, events),
+        Strategy::Bayesian => bayesian_search(device, &space, params, control, events),
+        Strategy::MemoryOnly => memory_search(device, &space, params, control, events),
+    }
+
+    // Also reached on cancel, so a cancelled search never leaves the tuned values behind
+    let _ = apply_settings(
+        device,
+        space.default_limit,
+        space.default_freq_offset,
+        space.default_mem_offset,
+        min_clock,
+        space.default_clock,
+    );
+}
 
-    let mut limit = default_limit;
-    let mut freq = default_freq_offset;
-    let mut mem = default_mem_offset;
-    let mut max_clock = default_clock;
+/// Walks every offset step in turn: lowers the power limit until unstable,
+/// then raises core and memory offsets step by step, and repeats. A stable
+/// step is only taken when it doesn't lower the objective, so an efficiency
+/// search stops raising offsets once the extra score costs more power than
+/// it's worth.
+fn linear_search(
+    device: &mut Device,
+    space: &SearchSpace,
+    params: &SearchParams,
+    control: &SearchControl,
+    events: &Sender<SearchEvent>,
+) {
+    let mut limit = space.default_limit;
+    let mut freq = space.default_freq_offset;
+    let mut mem = space.default_mem_offset;
+    let max_clock = space.default_clock;
     let min_clock = 0u32;
 
-    let step_power = 5_000u32;
+    let step_power = space.step_power;
+    let max_crashes = params.limits.max_crashes;
     let mut crash_cycles = 0;
+    // Objective value of the settings the search currently stands on
+    let mut current = None;
 
-    while limit > step_power && crash_cycles <= 2 {
+    'search: while limit > step_power && crash_cycles <= max_crashes {
         // Lower power limit first
         loop {
-            if limit <= step_power {
+            if limit <= step_power || limit - step_power < space.min_limit {
                 break;
             }
+            if control.should_stop() {
+                break 'search;
+            }
             let new_limit = limit - step_power;
             if !apply_settings(device, new_limit, freq, mem, min_clock, max_clock)
             {
                 break;
             }
-            if let Some(res) = run_benchmark(device) {
-                limit = new_limit;
-                records.push(Record {
-                    power_limit: limit,
+            if let Some(res) = run_stages(device, params, control) {
+                if res.score < params.min_score {
+                    // Stable but too slow: this is the power floor, not a crash
+                    break;
+                }
+                let record = Record {
+                    power_limit: new_limit,
                     freq_offset: freq,
                     mem_offset: mem,
                     min_clock,
                     max_clock,
                     score: res.score,
                     avg_power: res.avg_power,
-                });
-                save_record(records.last().unwrap());
+                    energy_j: res.energy_j,
+                    telemetry: res.telemetry,
+                };
+                if !improves(params, current, &record) {
+                    // Stable but less efficient: the power floor as well
+                    break;
+                }
+                limit = new_limit;
+                current = Some(params.objective.value(&record));
+                report(events, record);
             } else {
                 crash_cycles += 1;
                 break;
             }
         }
-        if crash_cycles > 2 {
+        if crash_cycles > max_crashes {
             break;
         }
 
         // Increase frequency offset
-        for step in freq_steps.iter().skip(1) {
-            let new_freq = default_freq_offset + *step;
+        for step in space.freq_steps.iter().skip(1) {
+            if control.should_stop() {
+                break 'search;
+            }
+            let new_freq = space.default_freq_offset + *step;
             if !apply_settings(device, limit, new_freq, mem, min_clock, max_clock) {
                 break;
             }
-            if let Some(res) = run_benchmark(device) {
-                freq = new_freq;
-                records.push(Record {
+            if let Some(res) = run_stages(device, params, control) {
+                let record = Record {
                     power_limit: limit,
-                    freq_offset: freq,
+                    freq_offset: new_freq,
                     mem_offset: mem,
                     min_clock,
                     max_clock,
                     score: res.score,
                     avg_power: res.avg_power,
-                });
-                save_record(records.last().unwrap());
+                    energy_j: res.energy_j,
+                    telemetry: res.telemetry,
+                };
+                if !improves(params, current, &record) {
+                    break;
+                }
+                freq = new_freq;
+                current = Some(params.objective.value(&record));
+                report(events, record);
             } else {
                 crash_cycles += 1;
                 break;
             }
         }
-        if crash_cycles > 2 {
+        if crash_cycles > max_crashes {
             break;
         }
 
         // Increase memory offset
-        for step in mem_steps.iter().skip(1) {
-            let new_mem = default_mem_offset + *step;
+        for step in space.mem_steps.iter().skip(1) {
+            if control.should_stop() {
+                break 'search;
+            }
+            let new_mem = space.default_mem_offset + *step;
             if !apply_settings(device, limit, freq, new_mem, min_clock, max_clock) {
                 break;
             }
-            if let Some(res) = run_benchmark(device) {
-                mem = new_mem;
-                records.push(Record {
+            if let Some(res) = run_stages(device, params, control) {
+                let record = Record {
                     power_limit: limit,
                     freq_offset: freq,
-                    mem_offset: mem,
+                    mem_offset: new_mem,
                     min_clock,
                     max_clock,
                     score: res.score,
                     avg_power: res.avg_power,
-                });
-                save_record(records.last().unwrap());
+                    energy_j: res.energy_j,
+                    telemetry: res.telemetry,
+                };
+                if !improves(params, current, &record) {
+                    break;
+                }
+                mem = new_mem;
+                current = Some(params.objective.value(&record));
+                report(events, record);
             } else {
                 crash_cycles += 1;
                 break;
             }
         }
-        if crash_cycles > 2 {
+        if crash_cycles > max_crashes {
             break;
         }
 
         // Raise power limit slightly for next cycle
         let new_limit = limit + step_power;
-        if new_limit >= default_limit {
+        if new_limit >= space.default_limit {
             break;
         }
         limit = new_limit;
+        // The objective at the raised limit is unknown until it's benchmarked
+        current = None;
     }
+}
 
-    let _ = apply_settings(
-        device,
-        default_limit,
-        default_freq_offset,
-        default_mem_offset,
-        min_clock,
-        default_clock,
-    );
+/// Whether the search moves to a stable step. By raw score every stable step
+/// counts, the score only drops when the GPU falls over; by efficiency or
+/// score per joule a step must not lower the objective below `current`.
+fn improves(params: &SearchParams, current: Option<f32>, record: &Record) -> bool {
+    params.objective == Objective::Score || current.is_none_or(|current| params.objective.value(record) >= current)
 }
 
-fn save_record(record: &Record) {
-    let mut path = documents_dir();
-    path.push("nvidia_oc_results.csv");
-    let new_file = !path.exists();
-    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) {
-        if new_file {
-            let _ = writeln!(file, "power_limit_w,freq_offset,mem_offset,min_clock,max_clock,score,avg_power_w");
+/// Benchmarks one combination of settings, reporting it when it completes.
+/// Returns None when the settings couldn't be applied or the run failed.
+fn trial(
+    device: &mut Device,
+    params: &SearchParams,
+    control: &SearchControl,
+    events: &Sender<SearchEvent>,
+    (limit, freq, mem, max_clock): (u32, i32, i32, u32),
+) -> Option<BenchResult> {
+    if !apply_settings(device, limit, freq, mem, 0, max_clock) {
+        return None;
+    }
+    let res = run_stages(device, params, control)?;
+    report(events, Record {
+        power_limit: limit,
+        freq_offset: freq,
+        mem_offset: mem,
+        min_clock: 0,
+        max_clock,
+        score: res.score,
+        avg_power: res.avg_power,
+        energy_j: res.energy_j,
+        telemetry: res.telemetry,
+    });
+    Some(res)
+}
+
+/// Highest index into `steps` whose offset passes `stable`, in O(log n)
+/// trials. Assumes every offset below a stable one is stable as well.
+fn bisect(steps: &[i32], control: &SearchControl, mut stable: impl FnMut(i32) -> bool) -> Option<usize> {
+    // Invariant: everything up to `lo` passed, everything from `hi` failed
+    let (mut lo, mut hi) = (None::<usize>, steps.len());
+    loop {
+        let start = lo.map_or(0, |lo| lo + 1);
+        if start >= hi || control.should_stop() {
+            return lo;
         }
-        let _ = writeln!(
-            file,
-            "{},{},{},{},{},{:.0},{:.2}",
-            record.power_limit / 1000,
-            record.freq_offset,
-            record.mem_offset,
-            record.min_clock,
-            record.max_clock,
-            record.score,
-            record.avg_power
-        );
+        let mid = start + (hi - start) / 2;
+        if stable(steps[mid]) {
+            lo = Some(mid);
+        } else {
+            hi = mid;
+        }
+    }
+}
+
+/// For each power level from the default down, bisects the highest stable
+/// core offset and then memory offset, and confirms the pair with a soak of
+/// `soakRuns` consecutive benchmark runs, stepping down while it fails.
+fn bisection_search(
+    device: &mut Device,
+    space: &SearchSpace,
+    params: &SearchParams,
+    control: &SearchControl,
+    events: &Sender<SearchEvent>,
+) {
+    let max_clock = space.default_clock;
+    let passes = |res: Option<BenchResult>| res.is_some_and(|res| res.score >= params.min_score);
+
+    let mut limit = space.default_limit;
+    while limit >= space.min_limit.max(space.step_power) && !control.should_stop() {
+        let settings = (limit, space.default_freq_offset, space.default_mem_offset, max_clock);
+        if !passes(trial(device, params, control, events, settings)) {
+            // Not even stock offsets hold up at this power level
+            break;
+        }
+
+        let freq_steps: Vec<i32> = space.freq_steps.iter().map(|step| space.default_freq_offset + step).collect();
+        let freq_index = bisect(&freq_steps, control, |freq| {
+            passes(trial(device, params, control, events, (limit, freq, space.default_mem_offset, max_clock)))
+        });
+        let freq = freq_index.map_or(space.default_freq_offset, |i| freq_steps[i]);
+
+        let mem_steps: Vec<i32> = space.mem_steps.iter().map(|step| space.default_mem_offset + step).collect();
+        let mem_index = bisect(&mem_steps, control, |mem| {
+            passes(trial(device, params, control, events, (limit, freq, mem, max_clock)))
+        });
+
+        // Bisection trusts a single pass, the soak weeds out marginal results
+        let (mut freq_index, mut mem_index) = (freq_index, mem_index);
+        loop {
+            if control.should_stop() {
+                return;
+            }
+            let freq = freq_index.map_or(space.default_freq_offset, |i| freq_steps[i]);
+            let mem = mem_index.map_or(space.default_mem_offset, |i| mem_steps[i]);
+            let soaked = (0..params.limits.soak_runs).all(|_| passes(trial(device, params, control, events, (limit, freq, mem, max_clock))));
+            if soaked {
+                break;
+            }
+            // Back off memory first, it is the more common culprit
+            if let Some(i) = mem_index {
+                mem_index = i.checked_sub(1);
+            } else if let Some(i) = freq_index {
+                freq_index = i.checked_sub(1);
+            } else {
+                break;
+            }
+        }
+
+        limit -= space.step_power;
+    }
+}
+
+/// Random trials before the model is trusted to pick
+const BAYESIAN_INITIAL_TRIALS: usize = 5;
+
+/// Candidates scored by the acquisition function per trial
+const BAYESIAN_CANDIDATES: usize = 500;
+
+/// Raises the memory offset in `memStepMhz` steps at stock power limit and
+/// core offset until the first failed run. GDDR6X corrects errors well past
+/// the point where things still run, so a crash overstates the stable offset;
+/// this is meant to run a VRAM test that reports errors via `errorPattern`.
+fn memory_search(
+    device: &mut Device,
+    space: &SearchSpace,
+    params: &SearchParams,
+    control: &SearchControl,
+    events: &Sender<SearchEvent>,
+) {
+    let step = params.limits.mem_step_mhz.max(1);
+    let mut last_clean = None;
+    let mut mem = space.default_mem_offset;
+    while mem <= params.limits.max_mem_offset && !control.should_stop() {
+        let settings = (space.default_limit, space.default_freq_offset, mem, space.default_clock);
+        if trial(device, params, control, events, settings).is_none() {
+            break;
+        }
+        last_clean = Some(mem);
+        mem += step;
+    }
+
+    let result = match last_clean {
+        Some(mem) => format!("Highest memory offset without errors: {} MHz", mem),
+        None => "Errors even at the current memory offset".to_string(),
+    };
+    let _ = events.send(SearchEvent::Result(result));
+}
+
+/// Xorshift, good enough to spread candidates without pulling in a crate
+struct Rng(u64);
+
+impl Rng {
+    fn seeded() -> Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Rng(seed | 1)
     }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+}
+
+/// Squared exponential kernel over points normalised to the unit cube
+fn rbf(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    const LENGTH_SCALE: f64 = 0.3;
+    let d2: f64 = a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum();
+    (-d2 / (2.0 * LENGTH_SCALE * LENGTH_SCALE)).exp()
+}
+
+/// Gaussian process regression with a fixed kernel, small enough to refit
+/// from scratch after every trial
+struct GaussianProcess {
+    points: Vec<[f64; 3]>,
+    /// Lower triangular Cholesky factor of the kernel matrix
+    chol: Vec<Vec<f64>>,
+    alpha: Vec<f64>,
+    mean: f64,
+    scale: f64,
+}
+
+impl GaussianProcess {
+    fn fit(points: &[[f64; 3]], values: &[f64]) -> Self {
+        let n = points.len();
+        let mean = values.iter().sum::<f64>() / n.max(1) as f64;
+        let var = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / n.max(1) as f64;
+        let scale = var.sqrt().max(1e-9);
+        let y: Vec<f64> = values.iter().map(|v| (v - mean) / scale).collect();
+
+        let mut chol = vec![vec![0.0; n]; n];
+        for i in 0..n {
+            for j in 0..=i {
+                let noise = if i == j { 1e-4 } else { 0.0 };
+                let sum: f64 = (0..j).map(|k| chol[i][k] * chol[j][k]).sum();
+                let k = rbf(&points[i], &points[j]) + noise - sum;
+                chol[i][j] = if i == j { k.max(1e-12).sqrt() } else { k / chol[j][j] };
+            }
+        }
+        let alpha = solve_upper(&chol, &solve_lower(&chol, &y));
+        Self { points: points.to_vec(), chol, alpha, mean, scale }
+    }
+
+    /// Predicted mean and standard deviation at `x`
+    fn predict(&self, x: &[f64; 3]) -> (f64, f64) {
+        let k: Vec<f64> = self.points.iter().map(|p| rbf(p, x)).collect();
+        let mu: f64 = k.iter().zip(&self.alpha).map(|(k, a)| k * a).sum();
+        let v = solve_lower(&self.chol, &k);
+        let var = (1.0 - v.iter().map(|v| v * v).sum::<f64>()).max(1e-12);
+        (self.mean + mu * self.scale, var.sqrt() * self.scale)
+    }
+}
+
+fn solve_lower(l: &[Vec<f64>], b: &[f64]) -> Vec<f64> {
+    let mut x = vec![0.0; b.len()];
+    for i in 0..b.len() {
+        let sum: f64 = (0..i).map(|k| l[i][k] * x[k]).sum();
+        x[i] = (b[i] - sum) / l[i][i];
+    }
+    x
+}
+
+/// Solves `Lᵀx = b` for the lower triangular `l`
+fn solve_upper(l: &[Vec<f64>], b: &[f64]) -> Vec<f64> {
+    let n = b.len();
+    let mut x = vec![0.0; n];
+    for i in (0..n).rev() {
+        let sum: f64 = (i + 1..n).map(|k| l[k][i] * x[k]).sum();
+        x[i] = (b[i] - sum) / l[i][i];
+    }
+    x
+}
+
+fn normal_pdf(z: f64) -> f64 {
+    (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt()
+}
+
+/// Abramowitz and Stegun 7.1.26, accurate to about 1e-7
+fn normal_cdf(z: f64) -> f64 {
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - poly * (-x * x).exp();
+    if z >= 0.0 { 0.5 * (1.0 + erf) } else { 0.5 * (1.0 - erf) }
+}
+
+fn expected_improvement(mu: f64, sigma: f64, best: f64) -> f64 {
+    let z = (mu - best) / sigma;
+    (mu - best) * normal_cdf(z) + sigma * normal_pdf(z)
+}
+
+/// Models the objective over (power limit, core offset, memory offset) with a
+/// Gaussian process and the chance of a trial passing with a kernel weighted
+/// pass rate, and runs the candidate with the best expected improvement
+/// weighted by that chance.
+fn bayesian_search(
+    device: &mut Device,
+    space: &SearchSpace,
+    params: &SearchParams,
+    control: &SearchControl,
+    events: &Sender<SearchEvent>,
+) {
+    let limits: Vec<u32> = (0..)
+        .map(|i| space.default_limit.saturating_sub(i * space.step_power))
+        .take_while(|&limit| limit >= space.min_limit.max(space.step_power))
+        .collect();
+    let freqs: Vec<i32> = std::iter::once(0).chain(space.freq_steps.iter().copied()).map(|step| space.default_freq_offset + step).collect();
+    let mems: Vec<i32> = std::iter::once(0).chain(space.mem_steps.iter().copied()).map(|step| space.default_mem_offset + step).collect();
+    if limits.is_empty() {
+        return;
+    }
+
+    // Indices into the three axes, normalised to [0, 1] for the kernel
+    let normalise = |(l, f, m): (usize, usize, usize)| {
+        let axis = |i: usize, n: usize| if n > 1 { i as f64 / (n - 1) as f64 } else { 0.0 };
+        [axis(l, limits.len()), axis(f, freqs.len()), axis(m, mems.len())]
+    };
+
+    let mut rng = Rng::seeded();
+    // Stock settings first, so there's always one known good point
+    let mut tried: Vec<(usize, usize, usize)> = vec![(0, 0, 0)];
+    let mut outcomes: Vec<Option<f64>> = Vec::new();
+
+    for n in 0..params.limits.bayesian_trials {
+        if control.should_stop() {
+            return;
+        }
+        if n > 0 {
+            let candidates = (0..BAYESIAN_CANDIDATES)
+                .map(|_| (rng.below(limits.len()), rng.below(freqs.len()), rng.below(mems.len())))
+                .filter(|c| !tried.contains(c));
+            let next = if n < BAYESIAN_INITIAL_TRIALS {
+                candidates.take(1).next()
+            } else {
+                let passed: Vec<([f64; 3], f64)> = tried
+                    .iter()
+                    .zip(&outcomes)
+                    .filter_map(|(&t, o)| o.map(|v| (normalise(t), v)))
+                    .collect();
+                let points: Vec<[f64; 3]> = passed.iter().map(|(p, _)| *p).collect();
+                let values: Vec<f64> = passed.iter().map(|(_, v)| *v).collect();
+                let best = values.iter().cloned().fold(f64::MIN, f64::max);
+                let model = GaussianProcess::fit(&points, &values);
+                let all: Vec<[f64; 3]> = tried.iter().map(|&t| normalise(t)).collect();
+
+                candidates
+                    .map(|c| {
+                        let x = normalise(c);
+                        // Pass rate of nearby trials, with a weak prior of one pass and one failure
+                        let (mut pass, mut total) = (1.0, 2.0);
+                        for (p, o) in all.iter().zip(&outcomes) {
+                            let w = rbf(p, &x);
+                            total += w;
+                            if o.is_some() {
+                                pass += w;
+                            }
+                        }
+                        let (mu, sigma) = model.predict(&x);
+                        (c, expected_improvement(mu, sigma, best) * pass / total)
+                    })
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(c, _)| c)
+            };
+            let Some(next) = next else {
+                // Every sampled candidate has been tried already
+                return;
+            };
+            tried.push(next);
+        }
+
+        let (l, f, m) = tried[n];
+        let settings = (limits[l], freqs[f], mems[m], space.default_clock);
+        let outcome = trial(device, params, control, events, settings)
+            .filter(|res| res.score >= params.min_score)
+            .map(|res| {
+                let record = Record {
+                    power_limit: settings.0,
+                    freq_offset: settings.1,
+                    mem_offset: settings.2,
+                    min_clock: 0,
+                    max_clock: settings.3,
+                    score: res.score,
+                    avg_power: res.avg_power,
+                    energy_j: res.energy_j,
+                    telemetry: res.telemetry,
+                };
+                params.objective.value(&record) as f64
+            });
+        outcomes.push(outcome);
+    }
+}
+
+/// The CLI's system wide config, the only one it saves to under pkexec
+const SYSTEM_CONFIG_PATH: &str = "/etc/nvidia_oc.json";
+
+/// Merges a record into the CLI config file. An existing entry for the GPU,
+/// keyed by index 0 or its UUID, is updated in place, other settings in it
+/// such as fan curves are kept.
+fn save_to_config(path: &std::path::Path, uuid: &str, record: &Record) -> Result<(), String> {
+    update_config(path, uuid, |entry| {
+        entry.insert("powerLimit".to_string(), record.power_limit.into());
+        entry.insert("freqOffset".to_string(), record.freq_offset.into());
+        entry.insert("memOffset".to_string(), record.mem_offset.into());
+        entry.insert("minClock".to_string(), record.min_clock.into());
+        entry.insert("maxClock".to_string(), record.max_clock.into());
+    })
+}
+
+/// Merges the curve's offsets into the CLI config file as `freqOffsetPstate`
+fn save_curve_to_config(path: &std::path::Path, uuid: &str, points: &[CurvePoint]) -> Result<(), String> {
+    let offsets: serde_json::Map<String, serde_json::Value> = points.iter().map(|point| (pstate_name(point.pstate), point.offset.into())).collect();
+    update_config(path, uuid, |entry| {
+        entry.insert("freqOffsetPstate".to_string(), offsets.into());
+    })
+}
+
+/// Changes the config entry of GPU 0 and writes the config back, the
+/// system config only when running as root, see [`pkexec_set`] otherwise
+fn update_config(
+    path: &std::path::Path,
+    uuid: &str,
+    update: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>),
+) -> Result<(), String> {
+    let mut config: serde_json::Value = match std::fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(|e| e.to_string())?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => serde_json::json!({ "sets": {} }),
+        Err(e) => return Err(e.to_string()),
+    };
+    let sets = config
+        .as_object_mut()
+        .ok_or("config is not a JSON object")?
+        .entry("sets")
+        .or_insert_with(|| serde_json::json!({}))
+        .as_object_mut()
+        .ok_or("`sets` is not a JSON object")?;
+
+    let key = if sets.contains_key("0") { "0" } else { uuid };
+    let entry = sets
+        .entry(key.to_string())
+        .or_insert_with(|| serde_json::json!({}))
+        .as_object_mut()
+        .ok_or("GPU entry is not a JSON object")?;
+    update(entry);
+
+    let contents = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    std::fs::write(path, contents + "\n").map_err(|e| e.to_string())
+}
+
+/// Reads a results CSV written by versions before the results database,
+/// skipping malformed rows
+fn load_records(path: &std::path::Path) -> Result<Vec<Record>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let records: Vec<Record> = contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            // Files from before trial telemetry was recorded have 7 columns,
+            // from before energy was recorded 11
+            if ![7, 11, 12].contains(&fields.len()) {
+                return None;
+            }
+            let telemetry = match fields.get(7..11) {
+                Some([avg_clock, peak_clock, max_temp, throttled]) => TrialTelemetry {
+                    avg_clock: avg_clock.parse().ok()?,
+                    peak_clock: peak_clock.parse().ok()?,
+                    max_temp: max_temp.parse().ok()?,
+                    throttled: throttled.parse::<f32>().ok()? / 100.0,
+                },
+                _ => TrialTelemetry::default(),
+            };
+            Some(Record {
+                power_limit: fields[0].parse::<u32>().ok()? * 1000,
+                freq_offset: fields[1].parse().ok()?,
+                mem_offset: fields[2].parse().ok()?,
+                min_clock: fields[3].parse().ok()?,
+                max_clock: fields[4].parse().ok()?,
+                score: fields[5].parse().ok()?,
+                avg_power: fields[6].parse().ok()?,
+                energy_j: match fields.get(11) {
+                    Some(energy) => energy.parse().ok()?,
+                    None => 0.0,
+                },
+                telemetry,
+            })
+        })
+        .collect();
+    if records.is_empty() {
+        return Err("no records found".to_string());
+    }
+    Ok(records)
+}
+
+/// Hands a record to the GUI, which saves it to the results database
+fn report(events: &Sender<SearchEvent>, record: Record) {
+    let _ = events.send(SearchEvent::Record(record));
+}
+
+/// Set while a search may have the GPU at trial settings
+static SEARCHING: AtomicBool = AtomicBool::new(false);
+
+/// Resets power limit, offsets, locked clocks and fans of the searched GPU to
+/// the driver defaults, best effort
+fn reset_to_stock() {
+    let Ok(nvml) = Nvml::init() else {
+        return;
+    };
+    let Ok(mut device) = nvml.device_by_index(0) else {
+        return;
+    };
+    if let Ok(default) = device.power_management_limit_default() {
+        let _ = device.set_power_management_limit(default);
+    }
+    let _ = device.set_gpc_clock_vf_offset(0);
+    let _ = device.set_mem_clock_vf_offset(0);
+    let _ = device.reset_gpu_locked_clocks();
+    for fan in 0..device.num_fans().unwrap_or(0) {
+        let _ = device.set_default_fan_speed(fan);
+    }
+}
+
+/// A panic in the search thread skips restoring the settings the search
+/// started from, and one in the GUI thread takes the search down with it.
+/// Either would leave the GPU at the most aggressive settings tried.
+fn reset_on_panic() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        if SEARCHING.swap(false, Ordering::SeqCst) {
+            reset_to_stock();
+            eprintln!("Reset GPU 0 to stock settings after the crash.");
+        }
+    }));
 }
 
 fn main() {
+    reset_on_panic();
     let options = eframe::NativeOptions::default();
-    eframe::run_native(Box::new(GuiApp::default()), options);
+    let mut app = GuiApp::default();
+    app.params.limits = load_search_limits();
+    let result = eframe::run_native("NVIDIA Undervolt", options, Box::new(|cc| {
+        app.setup(&cc.egui_ctx);
+        Box::new(app)
+    }));
+    if let Err(e) = result {
+        eprintln!("Failed to start the GUI: {}", e);
+        std::process::exit(1);
+    }
 }
 
@@ -0,0 +1,198 @@
+use crate::color::{pad, paint, Severity};
+use crate::output::{self, OutputFormat};
+use nvml_wrapper::enum_wrappers::device::{Clock, PerformanceState};
+use nvml_wrapper::enums::device::DeviceArchitecture;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::{Device, Nvml};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Capability {
+    name: &'static str,
+    read: String,
+    write: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GpuCaps {
+    index: u32,
+    name: String,
+    architecture: Option<String>,
+    capabilities: Vec<Capability>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Caps {
+    driver_version: Option<String>,
+    nvml_version: Option<String>,
+    gpus: Vec<GpuCaps>,
+}
+
+fn status<T>(result: &Result<T, NvmlError>) -> String {
+    match result {
+        Ok(_) => "supported".to_string(),
+        Err(NvmlError::NotSupported) => "not supported".to_string(),
+        Err(NvmlError::NoPermission) => "needs root".to_string(),
+        Err(e) => format!("error ({:?})", e),
+    }
+}
+
+/// Colors a status for the terminal
+fn show(status: &str) -> String {
+    if status == "supported" {
+        paint(status, Severity::Good)
+    } else if status == "needs root" {
+        paint(status, Severity::Warning)
+    } else if status.starts_with("error") {
+        paint(status, Severity::Bad)
+    } else {
+        status.to_string()
+    }
+}
+
+/// Reads a parameter and, as root, writes the value read back, so the probe
+/// leaves the GPU as it was. NVML refuses writes without root.
+fn probe<T: Copy>(
+    name: &'static str,
+    read: Result<T, NvmlError>,
+    root: bool,
+    write: impl FnOnce(T) -> Result<(), NvmlError>,
+) -> Capability {
+    let write = match read {
+        Ok(value) if root => status(&write(value)),
+        Ok(_) => "not probed, needs root".to_string(),
+        Err(_) => "n/a".to_string(),
+    };
+    Capability {
+        name,
+        read: status(&read),
+        write,
+    }
+}
+
+fn row(name: &'static str, read: &str, write: &str) -> Capability {
+    Capability {
+        name,
+        read: read.to_string(),
+        write: write.to_string(),
+    }
+}
+
+fn probe_device(index: u32, device: &mut Device, root: bool) -> GpuCaps {
+    let name = device.name().unwrap_or_else(|_| "unknown".to_string());
+    let architecture = device.architecture();
+    let mut capabilities = vec![
+        probe(
+            "core clock offset",
+            device.gpc_clock_vf_offset(),
+            root,
+            |offset| device.set_gpc_clock_vf_offset(offset),
+        ),
+        probe(
+            "memory clock offset",
+            device.mem_clock_vf_offset(),
+            root,
+            |offset| device.set_mem_clock_vf_offset(offset),
+        ),
+        probe(
+            "per-pstate offsets",
+            device
+                .clock_offset(Clock::Graphics, PerformanceState::Zero)
+                .map(|offset| offset.clock_offset_mhz),
+            root,
+            |offset| device.set_clock_offset(Clock::Graphics, PerformanceState::Zero, offset),
+        ),
+        probe(
+            "power limit",
+            device.power_management_limit(),
+            root,
+            |limit| device.set_power_management_limit(limit),
+        ),
+    ];
+
+    // NVML can't read locked clocks back, and probing by setting them would
+    // drop any that are active, so this goes by architecture
+    let locked = match &architecture {
+        Ok(
+            DeviceArchitecture::Kepler | DeviceArchitecture::Maxwell | DeviceArchitecture::Pascal,
+        ) => "not supported, needs Volta or newer",
+        Ok(_) => "expected, Volta or newer",
+        Err(_) => "unknown",
+    };
+    capabilities.push(row("locked clocks", "n/a", locked));
+
+    capabilities.push(match device.num_fans() {
+        Ok(0) => row("fan control", "no fans", "n/a"),
+        Ok(_) => probe(
+            "fan control",
+            device.fan_control_policy(0),
+            root,
+            |policy| device.set_fan_control_policy(0, policy),
+        ),
+        Err(e) => row("fan control", &status::<()>(&Err(e)), "n/a"),
+    });
+
+    GpuCaps {
+        index,
+        name,
+        architecture: architecture
+            .ok()
+            .map(|architecture| format!("{:?}", architecture)),
+        capabilities,
+    }
+}
+
+/// Prints which of the operations this tool uses each GPU supports
+pub fn run(nvml: &Nvml, root: bool, format: OutputFormat) {
+    let driver_version = nvml
+        .sys_driver_version()
+        .map_err(|e| eprintln!("Failed to get driver version: {:?}", e))
+        .ok();
+    let nvml_version = nvml
+        .sys_nvml_version()
+        .map_err(|e| eprintln!("Failed to get NVML version: {:?}", e))
+        .ok();
+
+    let count = nvml.device_count().expect("Failed to get GPU count");
+    let mut gpus = Vec::new();
+    for index in 0..count {
+        match nvml.device_by_index(index) {
+            Ok(mut device) => gpus.push(probe_device(index, &mut device, root)),
+            Err(e) => eprintln!("Failed to get GPU {}: {:?}", index, e),
+        }
+    }
+    let caps = Caps {
+        driver_version,
+        nvml_version,
+        gpus,
+    };
+
+    if format != OutputFormat::Text {
+        output::print(format, &caps);
+        return;
+    }
+
+    if let Some(version) = &caps.driver_version {
+        println!("Driver version: {}", version);
+    }
+    if let Some(version) = &caps.nvml_version {
+        println!("NVML version: {}", version);
+    }
+    for gpu in &caps.gpus {
+        println!();
+        match &gpu.architecture {
+            Some(architecture) => println!("GPU {}: {} ({})", gpu.index, gpu.name, architecture),
+            None => println!("GPU {}: {}", gpu.index, gpu.name),
+        }
+        for capability in &gpu.capabilities {
+            println!(
+                "  {:<20} read: {} write: {}",
+                capability.name,
+                pad(&show(&capability.read), 16),
+                show(&capability.write)
+            );
+        }
+    }
+}
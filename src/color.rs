@@ -0,0 +1,71 @@
+use clap::ValueEnum;
+use std::{
+    env,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and NO_COLOR isn't set
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// Matches the config or works
+    Good,
+    /// Drift or a soft limit
+    Warning,
+    /// Errors and thermal or hardware throttling
+    Bad,
+}
+
+/// Decides once whether output is colored, see https://no-color.org
+pub fn init(choice: ColorChoice) {
+    let enabled = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1
+        }
+    };
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Wraps text in the ANSI color for the severity when color is enabled
+pub fn paint(text: &str, severity: Severity) -> String {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    let code = match severity {
+        Severity::Good => 32,
+        Severity::Warning => 33,
+        Severity::Bad => 31,
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+/// Width of text on the terminal, ignoring color escapes
+pub fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+/// Left aligns text that may be colored in a column of `width`
+pub fn pad(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(visible_width(text));
+    format!("{}{}", text, " ".repeat(padding))
+}
@@ -0,0 +1,319 @@
+use crate::history::format_timestamp;
+use crate::output::{self, OutputFormat};
+use crate::power::format_watts;
+use crate::results;
+use crate::store::{Filter, Run, Trial};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+
+/// Settings a trial ran at. Trials repeated at the same point are samples of
+/// the same score.
+type Point = (u32, i32, i32, u32, u32);
+
+fn point(trial: &Trial) -> Point {
+    (
+        trial.power_limit_mw,
+        trial.freq_offset,
+        trial.mem_offset,
+        trial.min_clock,
+        trial.max_clock,
+    )
+}
+
+/// Two sided 95% critical values of Student's t for 1 to 10 degrees of
+/// freedom
+const T_CRITICAL: [f64; 10] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+];
+
+fn t_critical(degrees_of_freedom: f64) -> f64 {
+    match degrees_of_freedom.floor() as usize {
+        0 => T_CRITICAL[0],
+        df @ 1..=10 => T_CRITICAL[df - 1],
+        11..=30 => 2.1,
+        _ => 1.96,
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Sample {
+    trials: usize,
+    mean: f64,
+    /// Sample standard deviation, `None` from a single trial
+    std_dev: Option<f64>,
+}
+
+impl Sample {
+    fn of(values: &[f64]) -> Sample {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let std_dev = (values.len() > 1).then(|| {
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            variance.sqrt()
+        });
+        Sample {
+            trials: values.len(),
+            mean,
+            std_dev,
+        }
+    }
+
+    /// Squared standard error of the mean
+    fn error(&self) -> Option<f64> {
+        self.std_dev.map(|sd| sd * sd / self.trials as f64)
+    }
+}
+
+#[derive(Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+enum Verdict {
+    /// Run B differs from run A by more than the trial to trial noise
+    Significant,
+    WithinNoise,
+    /// A run has a single trial at the point, so its noise is unknown
+    TooFewTrials,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Delta {
+    a: Sample,
+    b: Sample,
+    /// Mean of run B minus mean of run A
+    delta: f64,
+    delta_percent: Option<f64>,
+    verdict: Verdict,
+}
+
+impl Delta {
+    /// Welch's t-test of the difference of the means at 95% confidence
+    fn of(a: &[f64], b: &[f64]) -> Delta {
+        let (a, b) = (Sample::of(a), Sample::of(b));
+        let delta = b.mean - a.mean;
+        let verdict = match (a.error(), b.error()) {
+            (Some(error_a), Some(error_b)) => {
+                let error = error_a + error_b;
+                if error == 0.0 {
+                    if delta == 0.0 {
+                        Verdict::WithinNoise
+                    } else {
+                        Verdict::Significant
+                    }
+                } else {
+                    let t = delta.abs() / error.sqrt();
+                    let df = error.powi(2)
+                        / (error_a.powi(2) / (a.trials as f64 - 1.0)
+                            + error_b.powi(2) / (b.trials as f64 - 1.0));
+                    if t > t_critical(df) {
+                        Verdict::Significant
+                    } else {
+                        Verdict::WithinNoise
+                    }
+                }
+            }
+            _ => Verdict::TooFewTrials,
+        };
+        Delta {
+            delta_percent: (a.mean != 0.0).then(|| delta / a.mean * 100.0),
+            a,
+            b,
+            delta,
+            verdict,
+        }
+    }
+
+    fn marker(&self) -> &'static str {
+        match self.verdict {
+            Verdict::Significant => "*",
+            Verdict::WithinNoise => "~",
+            Verdict::TooFewTrials => "?",
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PointComparison {
+    power_limit_mw: u32,
+    freq_offset: i32,
+    mem_offset: i32,
+    min_clock: u32,
+    max_clock: u32,
+    score: Delta,
+    avg_power_w: Delta,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Comparison {
+    run_a: Run,
+    run_b: Run,
+    /// Settings both runs tried, in increasing order
+    points: Vec<PointComparison>,
+    only_in_a: usize,
+    only_in_b: usize,
+}
+
+fn group(trials: Vec<Trial>) -> BTreeMap<Point, Vec<Trial>> {
+    let mut points: BTreeMap<Point, Vec<Trial>> = BTreeMap::new();
+    for trial in trials {
+        points.entry(point(&trial)).or_default().push(trial);
+    }
+    points
+}
+
+fn describe(label: &str, run: &Run) -> String {
+    format!(
+        "Run {} ({}): {}, driver {}, {} UTC, {} {} with {}",
+        label,
+        run.id,
+        run.gpu_name,
+        run.driver_version,
+        format_timestamp(run.started_ms),
+        run.strategy,
+        run.objective,
+        run.benchmark
+    )
+}
+
+fn column(sample: &Sample, precision: usize) -> String {
+    match sample.std_dev {
+        Some(sd) => format!(
+            "{:.*} ±{:.*} ({})",
+            precision, sample.mean, precision, sd, sample.trials
+        ),
+        None => format!("{:.*} (1)", precision, sample.mean),
+    }
+}
+
+fn change(delta: &Delta) -> String {
+    match delta.delta_percent {
+        Some(percent) => format!("{:+.1}% {}", percent, delta.marker()),
+        None => format!("{:+.1} {}", delta.delta, delta.marker()),
+    }
+}
+
+/// Compares two tuning runs at the settings both tried. Trials repeated at a
+/// point give its noise, and a difference only counts when Welch's t-test
+/// puts it outside that noise.
+pub fn run(db: Option<&Path>, run_a: i64, run_b: i64, format: OutputFormat) {
+    let store = results::open(db);
+    let load = |id: i64| -> (Run, Vec<Trial>) {
+        let filter = Filter {
+            run: Some(id),
+            ..Filter::default()
+        };
+        let run = store
+            .runs(&filter, 1)
+            .expect("Failed to query runs")
+            .pop()
+            .unwrap_or_else(|| panic!("No run {}, `nvidia_oc runs` lists them", id));
+        let trials = store.run_trials(id).expect("Failed to query trials");
+        (run, trials)
+    };
+    let (run_a, trials_a) = load(run_a);
+    let (run_b, trials_b) = load(run_b);
+    let (points_a, points_b) = (group(trials_a), group(trials_b));
+
+    let scores = |trials: &[Trial]| -> Vec<f64> { trials.iter().map(|t| t.score as f64).collect() };
+    let powers =
+        |trials: &[Trial]| -> Vec<f64> { trials.iter().map(|t| t.avg_power_w as f64).collect() };
+    let points: Vec<PointComparison> = points_a
+        .iter()
+        .filter_map(|(point, a)| {
+            let b = points_b.get(point)?;
+            Some(PointComparison {
+                power_limit_mw: point.0,
+                freq_offset: point.1,
+                mem_offset: point.2,
+                min_clock: point.3,
+                max_clock: point.4,
+                score: Delta::of(&scores(a), &scores(b)),
+                avg_power_w: Delta::of(&powers(a), &powers(b)),
+            })
+        })
+        .collect();
+    let comparison = Comparison {
+        only_in_a: points_a.len() - points.len(),
+        only_in_b: points_b.len() - points.len(),
+        run_a,
+        run_b,
+        points,
+    };
+
+    if format != OutputFormat::Text {
+        output::print(format, &comparison);
+        return;
+    }
+
+    println!("{}", describe("A", &comparison.run_a));
+    println!("{}", describe("B", &comparison.run_b));
+    if comparison.run_a.gpu_uuid != comparison.run_b.gpu_uuid {
+        println!(
+            "Note: the runs are on different GPUs, even of one model results vary between cards."
+        );
+    }
+    if comparison.run_a.benchmark != comparison.run_b.benchmark {
+        println!("Note: the runs used different benchmarks, their scores aren't comparable.");
+    }
+    println!();
+
+    if comparison.points.is_empty() {
+        println!("The runs have no settings in common to compare.");
+        return;
+    }
+
+    println!(
+        "{:>9}  {:>6}  {:>6}  {:>22}  {:>22}  {:>10}  {:>20}  {:>20}  {:>10}",
+        "POWER", "CORE", "MEM", "SCORE A", "SCORE B", "CHANGE", "AVG W A", "AVG W B", "CHANGE"
+    );
+    for point in &comparison.points {
+        println!(
+            "{:>9}  {:>+6}  {:>+6}  {:>22}  {:>22}  {:>10}  {:>20}  {:>20}  {:>10}",
+            format_watts(point.power_limit_mw),
+            point.freq_offset,
+            point.mem_offset,
+            column(&point.score.a, 0),
+            column(&point.score.b, 0),
+            change(&point.score),
+            column(&point.avg_power_w.a, 2),
+            column(&point.avg_power_w.b, 2),
+            change(&point.avg_power_w)
+        );
+    }
+    println!();
+    println!("Mean ±standard deviation (trials). *: outside the noise at 95% confidence, ~: within the noise, ?: needs repeated trials in both runs.");
+
+    let count = |verdict: Verdict, faster: bool| {
+        comparison
+            .points
+            .iter()
+            .filter(|p| p.score.verdict == verdict && (p.score.delta > 0.0) == faster)
+            .count()
+    };
+    let noise = comparison
+        .points
+        .iter()
+        .filter(|p| p.score.verdict == Verdict::WithinNoise)
+        .count();
+    let unknown = comparison
+        .points
+        .iter()
+        .filter(|p| p.score.verdict == Verdict::TooFewTrials)
+        .count();
+    println!(
+        "Score of B at {} common settings: {} faster, {} slower, {} within noise, {} with too few trials.",
+        comparison.points.len(),
+        count(Verdict::Significant, true),
+        count(Verdict::Significant, false),
+        noise,
+        unknown
+    );
+    if comparison.only_in_a + comparison.only_in_b > 0 {
+        println!(
+            "Not compared: {} setting(s) only in A, {} only in B.",
+            comparison.only_in_a, comparison.only_in_b
+        );
+    }
+}
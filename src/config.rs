@@ -0,0 +1,750 @@
+use crate::battery::battery_sets;
+use crate::hooks::{self, Hook};
+use crate::retry::retry;
+use crate::Sets;
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::{Device, Nvml};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    env,
+    ffi::OsString,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// System-wide config location, used when nothing more specific exists
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/nvidia_oc.json";
+
+/// Resolves the config file to use, in order of precedence: `--file`, the
+/// `NVIDIA_OC_CONFIG` environment variable, the per-user
+/// `$XDG_CONFIG_HOME/nvidia_oc/config.json` (if it exists) and finally
+/// `/etc/nvidia_oc.json`.
+pub fn config_path(file: Option<&str>) -> PathBuf {
+    if let Some(file) = file {
+        return PathBuf::from(file);
+    }
+
+    if let Some(file) = env::var_os("NVIDIA_OC_CONFIG").filter(|f| !f.is_empty()) {
+        return PathBuf::from(file);
+    }
+
+    let user_config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(dir) = user_config_dir {
+        let user_config = dir.join("nvidia_oc").join("config.json");
+        if user_config.is_file() {
+            return user_config;
+        }
+    }
+
+    PathBuf::from(SYSTEM_CONFIG_PATH)
+}
+
+/// The `defaults` block is merged under every entry in `sets` and also
+/// applies on its own to GPUs no entry matches, under [`ConfigKey::Defaults`].
+#[derive(Deserialize)]
+#[serde(try_from = "RawConfig")]
+pub struct Config {
+    pub sets: HashMap<ConfigKey, Sets>,
+    /// Whether `nvidia_oc daemon` resets the GPUs to stock when it stops
+    pub revert_on_exit: bool,
+}
+
+type Settings = serde_json::Map<String, serde_json::Value>;
+
+#[derive(Deserialize)]
+struct RawConfig {
+    #[serde(default)]
+    sets: HashMap<ConfigKey, Settings>,
+    defaults: Option<Settings>,
+    #[serde(default, rename = "revertOnExit")]
+    revert_on_exit: bool,
+    /// Run before the per-GPU `preApply` of every GPU
+    #[serde(rename = "preApply")]
+    pre_apply: Option<Hook>,
+    /// Run after the per-GPU `postApply` of every GPU
+    #[serde(rename = "postApply")]
+    post_apply: Option<Hook>,
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = String;
+
+    fn try_from(raw: RawConfig) -> Result<Self, Self::Error> {
+        let parse = |key: &ConfigKey, settings: Settings| {
+            let mut sets = serde_json::from_value::<Sets>(serde_json::Value::Object(settings))
+                .map_err(|e| format!("invalid settings for GPU {}: {}", key, e))?;
+            sets.pre_apply = hooks::wrap(raw.pre_apply.as_ref(), sets.pre_apply.take(), true);
+            sets.post_apply = hooks::wrap(raw.post_apply.as_ref(), sets.post_apply.take(), false);
+            Ok::<_, String>(sets)
+        };
+
+        let mut sets = HashMap::new();
+        for (key, entry) in raw.sets {
+            let mut settings = raw.defaults.clone().unwrap_or_default();
+            settings.extend(entry.into_iter().filter(|(_, value)| !value.is_null()));
+            let entry = parse(&key, settings)?;
+            battery_sets(&entry).map_err(|e| format!("GPU {}: {}", key, e))?;
+            sets.insert(key, entry);
+        }
+        if let Some(defaults) = raw.defaults {
+            let entry = parse(&ConfigKey::Defaults, defaults)?;
+            battery_sets(&entry).map_err(|e| format!("GPU {}: {}", ConfigKey::Defaults, e))?;
+            sets.insert(ConfigKey::Defaults, entry);
+        }
+        Ok(Config {
+            sets,
+            revert_on_exit: raw.revert_on_exit,
+        })
+    }
+}
+
+/// The drop-in directory next to a config file, e.g. `/etc/nvidia_oc.d` for
+/// `/etc/nvidia_oc.json`
+pub fn dropin_dir(path: &Path) -> PathBuf {
+    path.with_extension("d")
+}
+
+/// The `*.json` fragments in the drop-in directory, in the order they apply
+fn dropin_files(path: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dropin_dir(path)) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|file| file.extension().is_some_and(|ext| ext == "json") && file.is_file())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Merges a config fragment into `config`. `sets` entries and `defaults` are
+/// merged setting by setting, anything else is replaced.
+fn merge(config: &mut Settings, fragment: Settings) {
+    for (key, value) in fragment {
+        match (key.as_str(), config.get_mut(&key), value) {
+            ("sets", Some(serde_json::Value::Object(sets)), serde_json::Value::Object(new)) => {
+                for (gpu, value) in new {
+                    match (sets.get_mut(&gpu), value) {
+                        (
+                            Some(serde_json::Value::Object(entry)),
+                            serde_json::Value::Object(new),
+                        ) => entry.extend(new),
+                        (_, value) => {
+                            sets.insert(gpu, value);
+                        }
+                    }
+                }
+            }
+            (
+                "defaults",
+                Some(serde_json::Value::Object(defaults)),
+                serde_json::Value::Object(new),
+            ) => defaults.extend(new),
+            (_, _, value) => {
+                config.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Merges settings into a GPU's entry in the config file at `path`, which is
+/// created if missing. An entry keyed by the GPU's index is updated in place,
+/// otherwise the one keyed by its UUID. Other settings in the entry, such as
+/// fan curves, are kept. Drop-in fragments are left alone.
+pub fn save_entry(path: &Path, index: u32, uuid: &str, settings: Settings) -> Result<(), String> {
+    let mut config: Settings = match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid configuration file {}: {}", path.display(), e))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Settings::new(),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    let serde_json::Value::Object(sets) = config
+        .entry("sets")
+        .or_insert_with(|| serde_json::Value::Object(Settings::new()))
+    else {
+        return Err(format!("`sets` in {} is not an object", path.display()));
+    };
+    let key = if sets.contains_key(&index.to_string()) {
+        index.to_string()
+    } else {
+        uuid.to_string()
+    };
+    let serde_json::Value::Object(entry) = sets
+        .entry(key)
+        .or_insert_with(|| serde_json::Value::Object(Settings::new()))
+    else {
+        return Err(format!(
+            "GPU {} in {} is not an object",
+            index,
+            path.display()
+        ));
+    };
+    entry.extend(settings);
+
+    let contents = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    fs::write(path, contents + "\n")
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+impl Config {
+    /// Whether there is anything to load at `path`, the file itself or
+    /// fragments in its drop-in directory
+    pub fn exists(path: &Path) -> bool {
+        path.is_file() || !dropin_files(path).is_empty()
+    }
+
+    /// Loads the config file merged with the fragments in its drop-in
+    /// directory, see [`dropin_dir`], which apply in file name order. Either
+    /// may be missing, but not both.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let mut files = dropin_files(path);
+        if path.is_file() || files.is_empty() {
+            files.insert(0, path.to_path_buf());
+        }
+
+        let mut config = Settings::new();
+        for file in files {
+            let contents = fs::read_to_string(&file)
+                .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+            let fragment: Settings = serde_json::from_str(&contents)
+                .map_err(|e| format!("Invalid configuration file {}: {}", file.display(), e))?;
+            merge(&mut config, fragment);
+        }
+        serde_json::from_value(serde_json::Value::Object(config))
+            .map_err(|e| format!("Invalid configuration in {}: {}", path.display(), e))
+    }
+
+    /// The entry for a GPU with the environment overrides applied, see
+    /// [`env_overrides`]. When several keys match, only the most specific
+    /// one applies: a UUID, then an index, then the longest name glob, then
+    /// the defaults.
+    pub fn entry(&self, index: u32, uuid: &str, name: &str) -> Option<(&ConfigKey, Sets)> {
+        self.sets
+            .iter()
+            .filter(|(key, _)| key.matches(index, uuid, name))
+            .max_by(|(a, _), (b, _)| {
+                a.specificity()
+                    .cmp(&b.specificity())
+                    // Equally specific globs, pick one independent of map order
+                    .then_with(|| b.to_string().cmp(&a.to_string()))
+            })
+            .map(|(key, sets)| (key, with_env_overrides(sets, index)))
+    }
+
+    /// Every present GPU with the entry that applies to it. Entries matching
+    /// no GPU are reported, GPUs NVML fails to open are skipped.
+    pub fn devices<'a, 'nvml>(
+        &'a self,
+        nvml: &'nvml Nvml,
+    ) -> Result<Vec<(Device<'nvml>, &'a ConfigKey, Sets)>, NvmlError> {
+        let mut devices = Vec::new();
+        let mut matched = HashSet::new();
+        for index in 0..retry(|| nvml.device_count())? {
+            let device = match retry(|| nvml.device_by_index(index)) {
+                Ok(device) => device,
+                Err(e) => {
+                    eprintln!("Failed to get GPU {}: {:?}", index, e);
+                    continue;
+                }
+            };
+            let uuid = device.uuid().unwrap_or_default();
+            let name = device.name().unwrap_or_default();
+            matched.extend(
+                self.sets
+                    .keys()
+                    .filter(|key| key.matches(index, &uuid, &name)),
+            );
+            if let Some((key, sets)) = self.entry(index, &uuid, &name) {
+                devices.push((device, key, sets));
+            }
+        }
+        for key in self.sets.keys().filter(|key| !matched.contains(key)) {
+            eprintln!("No GPU matches the config entry {}", key);
+        }
+        Ok(devices)
+    }
+
+    /// Describes every setting that differs between two configs, one line
+    /// per GPU and parameter, e.g. `GPU 0 powerLimit: 250000 -> 230000`
+    pub fn diff(&self, new: &Config) -> Vec<String> {
+        let keys: BTreeSet<String> = self
+            .sets
+            .keys()
+            .chain(new.sets.keys())
+            .map(ConfigKey::to_string)
+            .collect();
+
+        let settings = |config: &Config, key: &str| {
+            config
+                .sets
+                .iter()
+                .find(|(k, _)| k.to_string() == key)
+                .and_then(|(_, sets)| serde_json::to_value(sets).ok())
+                .and_then(|value| value.as_object().cloned())
+                .unwrap_or_default()
+        };
+
+        let mut changes = Vec::new();
+        for key in keys {
+            let old = settings(self, &key);
+            let new = settings(new, &key);
+            let fields: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+            for field in fields {
+                let old = old.get(field).filter(|v| !v.is_null());
+                let new = new.get(field).filter(|v| !v.is_null());
+                if old == new {
+                    continue;
+                }
+                let show = |v: Option<&serde_json::Value>| {
+                    v.map(|v| v.to_string())
+                        .unwrap_or_else(|| "unset".to_string())
+                };
+                changes.push(format!(
+                    "GPU {} {}: {} -> {}",
+                    key,
+                    field,
+                    show(old),
+                    show(new)
+                ));
+            }
+        }
+        changes
+    }
+}
+
+const ENV_PREFIX: &str = "NVIDIA_OC_";
+
+/// Variables with the prefix that aren't settings, including the ones alert
+/// commands get
+const ENV_NOT_SETTINGS: [&str; 6] = [
+    "CONFIG",
+    "NO_ESCALATE",
+    "GPU",
+    "METRIC",
+    "VALUE",
+    "THRESHOLD",
+];
+
+/// `POWER_LIMIT` to `powerLimit`
+fn env_setting_name(name: &str) -> String {
+    let mut words = name.split('_').map(str::to_lowercase);
+    let mut setting = words.next().unwrap_or_default();
+    for word in words {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            setting.extend(first.to_uppercase());
+            setting.push_str(chars.as_str());
+        }
+    }
+    setting
+}
+
+/// Settings for GPU `index` from `NVIDIA_OC_<SETTING>` variables and the
+/// per-GPU `NVIDIA_OC_GPU<index>_<SETTING>` ones, which take precedence, e.g.
+/// `NVIDIA_OC_POWER_LIMIT=250W` or `NVIDIA_OC_GPU1_MEM_OFFSET=800`, with
+/// their unparsed values. The `NVIDIA_OC_HOOK_` variables an apply hook runs
+/// with are not overrides.
+pub fn env_overrides(index: u32) -> HashMap<String, String> {
+    let gpu_prefix = format!("GPU{}_", index);
+    let mut global = HashMap::new();
+    let mut per_gpu = HashMap::new();
+    for (name, value) in env::vars() {
+        if name.starts_with(hooks::ENV_PREFIX) {
+            continue;
+        }
+        let Some(name) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        if ENV_NOT_SETTINGS.contains(&name) || value.is_empty() {
+            continue;
+        }
+        if let Some(setting) = name.strip_prefix(&gpu_prefix) {
+            per_gpu.insert(env_setting_name(setting), value);
+        } else if !is_per_gpu(name) {
+            global.insert(env_setting_name(name), value);
+        }
+    }
+    global.extend(per_gpu);
+    global
+}
+
+/// Whether a variable name, without the prefix, is a `GPU<index>_` one
+fn is_per_gpu(name: &str) -> bool {
+    name.strip_prefix("GPU").is_some_and(|rest| {
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        digits > 0 && rest[digits..].starts_with('_')
+    })
+}
+
+/// Applies the environment overrides for GPU `index` to a config entry.
+/// Values parse like the flag of the same name, so `NVIDIA_OC_POWER_LIMIT=250`
+/// is 250 W as with `--power-limit 250`. Settings without a flag, such as
+/// `fanCurve`, are read as JSON, or as a string if they aren't valid JSON. A
+/// variable that names no setting or doesn't parse is reported and ignored.
+fn with_env_overrides(sets: &Sets, index: u32) -> Sets {
+    let overrides = env_overrides(index);
+    if overrides.is_empty() {
+        return sets.clone();
+    }
+    let Ok(serde_json::Value::Object(mut settings)) = serde_json::to_value(sets) else {
+        return sets.clone();
+    };
+    for (setting, value) in overrides {
+        // Every setting serializes, unset ones as null
+        if !settings.contains_key(&setting) {
+            eprintln!(
+                "Ignoring environment override for unknown setting {}",
+                setting
+            );
+            continue;
+        }
+        let value = match Sets::parse_flag(&setting, &value) {
+            Some(Ok(value)) => value,
+            Some(Err(e)) => {
+                eprintln!("Ignoring environment override for {}: {}", setting, e);
+                continue;
+            }
+            None => serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value)),
+        };
+        let mut candidate = settings.clone();
+        candidate.insert(setting.clone(), value);
+        match serde_json::from_value::<Sets>(serde_json::Value::Object(candidate.clone())) {
+            Ok(_) => settings = candidate,
+            Err(e) => eprintln!("Ignoring environment override for {}: {}", setting, e),
+        }
+    }
+    serde_json::from_value(serde_json::Value::Object(settings)).unwrap_or_else(|_| sets.clone())
+}
+
+/// Waits for the config file to be written, coalescing the burst of events
+/// editors produce on save into a single notification. Changes in the
+/// drop-in directory count too if it existed when watching started.
+pub struct ConfigWatcher {
+    inotify: Inotify,
+    file_name: OsString,
+    /// Watch on the drop-in directory, where any change counts
+    dropin: Option<WatchDescriptor>,
+    debounce: Duration,
+    changed_at: Option<Instant>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &Path, debounce: Duration) -> io::Result<ConfigWatcher> {
+        let inotify = Inotify::init()?;
+        // Watch the directory, most editors save by renaming a new file over
+        // the old one, which would orphan a watch on the file itself
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        inotify.watches().add(
+            dir,
+            WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE,
+        )?;
+
+        let dropin = inotify
+            .watches()
+            .add(
+                dropin_dir(path),
+                WatchMask::CLOSE_WRITE
+                    | WatchMask::MOVED_TO
+                    | WatchMask::MOVED_FROM
+                    | WatchMask::CREATE
+                    | WatchMask::DELETE,
+            )
+            .ok();
+
+        Ok(ConfigWatcher {
+            inotify,
+            file_name: path.file_name().unwrap_or_default().to_os_string(),
+            dropin,
+            debounce,
+            changed_at: None,
+        })
+    }
+
+    /// Returns true once the file has changed and then stayed untouched for
+    /// the debounce period. Never blocks.
+    pub fn poll(&mut self) -> bool {
+        let mut buffer = [0; 4096];
+        while let Ok(events) = self.inotify.read_events(&mut buffer) {
+            let mut any = false;
+            for event in events {
+                any = true;
+                if event.name == Some(self.file_name.as_os_str())
+                    || self.dropin.as_ref() == Some(&event.wd)
+                {
+                    self.changed_at = Some(Instant::now());
+                }
+            }
+            if !any {
+                break;
+            }
+        }
+
+        match self.changed_at {
+            Some(changed_at) if changed_at.elapsed() >= self.debounce => {
+                self.changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Identifies the GPUs a `sets` entry belongs to: a numeric NVML index, a
+/// device UUID like `GPU-2b6f1d9e-...`, which stays stable across slots, or a
+/// name glob like `RTX 3080*`, see [`name_matches`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(from = "String")]
+pub enum ConfigKey {
+    Index(u32),
+    Uuid(String),
+    Name(String),
+    /// The top-level `defaults` block, matches every GPU
+    Defaults,
+}
+
+impl From<String> for ConfigKey {
+    fn from(key: String) -> Self {
+        if let Ok(index) = key.parse::<u32>() {
+            return ConfigKey::Index(index);
+        }
+        let upper = key.to_uppercase();
+        if upper.starts_with("GPU-") || upper.starts_with("MIG-") {
+            ConfigKey::Uuid(key)
+        } else {
+            ConfigKey::Name(key)
+        }
+    }
+}
+
+impl fmt::Display for ConfigKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigKey::Index(index) => write!(f, "{}", index),
+            ConfigKey::Uuid(uuid) => write!(f, "{}", uuid),
+            ConfigKey::Name(pattern) => write!(f, "{}", pattern),
+            ConfigKey::Defaults => write!(f, "defaults"),
+        }
+    }
+}
+
+impl ConfigKey {
+    /// The first GPU the key matches
+    pub fn device<'nvml>(&self, nvml: &'nvml Nvml) -> Result<Device<'nvml>, NvmlError> {
+        match self {
+            ConfigKey::Index(index) => nvml.device_by_index(*index),
+            ConfigKey::Uuid(uuid) => nvml.device_by_uuid(uuid.as_str()),
+            ConfigKey::Name(pattern) => (0..nvml.device_count()?)
+                .filter_map(|index| nvml.device_by_index(index).ok())
+                .find(|device| device.name().is_ok_and(|name| name_matches(pattern, &name)))
+                .ok_or(NvmlError::NotFound),
+            ConfigKey::Defaults => nvml.device_by_index(0),
+        }
+    }
+
+    pub fn matches(&self, index: u32, uuid: &str, name: &str) -> bool {
+        match self {
+            ConfigKey::Index(i) => *i == index,
+            ConfigKey::Uuid(u) => u.eq_ignore_ascii_case(uuid),
+            ConfigKey::Name(pattern) => name_matches(pattern, name),
+            ConfigKey::Defaults => true,
+        }
+    }
+
+    /// Ranks keys matching the same GPU: a UUID beats an index, which beats
+    /// any name glob, and longer globs beat shorter ones. The defaults come
+    /// last.
+    fn specificity(&self) -> (u8, usize) {
+        match self {
+            ConfigKey::Uuid(_) => (3, 0),
+            ConfigKey::Index(_) => (2, 0),
+            ConfigKey::Name(pattern) => (1, pattern.len()),
+            ConfigKey::Defaults => (0, 0),
+        }
+    }
+}
+
+/// Matches a GPU name against a glob with `*` and `?`, ignoring case. The
+/// pattern may start at any word of the name, so `RTX 3080*` matches
+/// "NVIDIA GeForce RTX 3080 Ti" without spelling out the vendor prefix.
+pub fn name_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    (0..name.len())
+        .filter(|&start| start == 0 || name[start - 1] == ' ')
+        .any(|start| glob_matches(&pattern, &name[start..]))
+}
+
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| glob_matches(rest, &text[skip..])),
+        Some((&c, rest)) => text
+            .split_first()
+            .is_some_and(|(&t, text)| (c == '?' || c == t) && glob_matches(rest, text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        glob_matches(&pattern, &text)
+    }
+
+    #[test]
+    fn globs_match_the_whole_text() {
+        assert!(glob("RTX 3080", "RTX 3080"));
+        assert!(glob("RTX 30?0", "RTX 3070"));
+        assert!(glob("RTX*", "RTX 3080 Ti"));
+        assert!(glob("*Ti", "RTX 3080 Ti"));
+        assert!(glob("R*0*i", "RTX 3080 Ti"));
+        assert!(glob("*", ""));
+        assert!(glob("", ""));
+        assert!(!glob("RTX 3080", "RTX 3080 Ti"));
+        assert!(!glob("RTX 30?0", "RTX 300"));
+        assert!(!glob("?", ""));
+        assert!(!glob("", "RTX"));
+    }
+
+    #[test]
+    fn names_match_from_any_word_ignoring_case() {
+        let name = "NVIDIA GeForce RTX 3080 Ti";
+        assert!(name_matches("RTX 3080*", name));
+        assert!(name_matches("rtx 3080 ti", name));
+        assert!(name_matches("NVIDIA*", name));
+        assert!(name_matches("GeForce RTX 30?0 Ti", name));
+        assert!(name_matches("*3080*", name));
+        assert!(!name_matches("TX 3080*", name));
+        assert!(!name_matches("RTX 3080", name));
+        assert!(!name_matches("RTX 4090*", name));
+        assert!(!name_matches("*", ""));
+    }
+
+    #[test]
+    fn classifies_config_keys() {
+        assert_eq!(ConfigKey::from("1".to_string()), ConfigKey::Index(1));
+        assert_eq!(
+            ConfigKey::from("GPU-1234".to_string()),
+            ConfigKey::Uuid("GPU-1234".to_string())
+        );
+        assert_eq!(
+            ConfigKey::from("mig-1234".to_string()),
+            ConfigKey::Uuid("mig-1234".to_string())
+        );
+        assert_eq!(
+            ConfigKey::from("RTX 3080*".to_string()),
+            ConfigKey::Name("RTX 3080*".to_string())
+        );
+        // Too large for an index, so it can only be a name
+        assert_eq!(
+            ConfigKey::from("4294967296".to_string()),
+            ConfigKey::Name("4294967296".to_string())
+        );
+    }
+
+    #[test]
+    fn keys_match_by_index_uuid_or_name() {
+        let (index, uuid, name) = (1, "GPU-abcd", "NVIDIA GeForce RTX 3080 Ti");
+        for key in ["1", "gpu-ABCD", "RTX 3080*"] {
+            assert!(
+                ConfigKey::from(key.to_string()).matches(index, uuid, name),
+                "{}",
+                key
+            );
+        }
+        for key in ["0", "GPU-abcde", "RTX 4090*"] {
+            assert!(
+                !ConfigKey::from(key.to_string()).matches(index, uuid, name),
+                "{}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn the_most_specific_key_wins() {
+        let key = |key: &str| ConfigKey::from(key.to_string());
+        assert!(key("GPU-abcd").specificity() > key("1").specificity());
+        assert!(key("1").specificity() > key("NVIDIA GeForce RTX 3080 Ti").specificity());
+        assert!(key("RTX 3080 Ti").specificity() > key("RTX 3080*").specificity());
+    }
+
+    #[test]
+    fn env_values_parse_like_flags() {
+        assert_eq!(
+            Sets::parse_flag("powerLimit", "250"),
+            Some(Ok(json!(250000)))
+        );
+        assert_eq!(
+            Sets::parse_flag("powerLimit", "80%"),
+            Some(Ok(json!("80%")))
+        );
+        assert_eq!(
+            Sets::parse_flag("freqOffset", "-100"),
+            Some(Ok(json!(-100)))
+        );
+        assert_eq!(
+            Sets::parse_flag("memOffset", "+15"),
+            Some(Ok(json!("+=15")))
+        );
+        // Requires --max-clock on the command line, not here
+        assert_eq!(Sets::parse_flag("minClock", "210"), Some(Ok(json!(210))));
+    }
+
+    #[test]
+    fn invalid_env_values_are_errors() {
+        assert!(matches!(
+            Sets::parse_flag("powerLimit", "250000"),
+            Some(Err(e)) if e.contains("Did you mean 250000mW / 250W")
+        ));
+        assert!(matches!(Sets::parse_flag("tempLimit", "hot"), Some(Err(_))));
+    }
+
+    #[test]
+    fn save_entry_merges_into_the_gpu_entry() {
+        let path = env::temp_dir().join(format!("nvidia_oc-save-{}.json", std::process::id()));
+        fs::write(&path, r#"{"sets": {"1": {"fan": 60}}}"#).unwrap();
+
+        let settings = |json: serde_json::Value| match json {
+            serde_json::Value::Object(settings) => settings,
+            _ => unreachable!(),
+        };
+        save_entry(&path, 1, "GPU-a", settings(json!({ "memOffset": 800 }))).unwrap();
+        save_entry(&path, 0, "GPU-b", settings(json!({ "powerLimit": 250000 }))).unwrap();
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            saved,
+            json!({ "sets": {
+                "1": { "fan": 60, "memOffset": 800 },
+                "GPU-b": { "powerLimit": 250000 },
+            }})
+        );
+    }
+
+    #[test]
+    fn settings_without_a_value_flag_are_not_parsed() {
+        assert_eq!(Sets::parse_flag("fanCurve", "[]"), None);
+        assert_eq!(Sets::parse_flag("legacyFallback", "true"), None);
+        assert_eq!(Sets::parse_flag("noSuchSetting", "1"), None);
+    }
+}
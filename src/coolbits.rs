@@ -0,0 +1,115 @@
+use regex::Regex;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Where `nvidia_oc coolbits --install` writes its snippet when no Xorg
+/// config sets Coolbits yet
+pub const SNIPPET_PATH: &str = "/etc/X11/xorg.conf.d/20-nvidia-coolbits.conf";
+
+/// Manual fan control, clock offsets and overvoltage
+pub const DEFAULT_VALUE: u32 = 28;
+
+const BITS: [(u32, &str); 4] = [
+    (2, "SLI with GPUs of different memory sizes"),
+    (4, "manual fan control"),
+    (8, "clock offsets"),
+    (16, "overvoltage"),
+];
+
+fn option_regex() -> Regex {
+    Regex::new(r#"(?i)^(\s*Option\s+"Coolbits"\s+")(\d+)(".*)$"#).expect("Invalid regex")
+}
+
+/// Xorg config files in the order Xorg reads them
+fn config_files() -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from("/etc/X11/xorg.conf")];
+    for dir in ["/etc/X11/xorg.conf.d", "/usr/share/X11/xorg.conf.d"] {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut entries: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "conf"))
+            .collect();
+        entries.sort();
+        files.extend(entries);
+    }
+    files
+}
+
+/// The Xorg config file setting Coolbits and its value
+pub fn current() -> Option<(PathBuf, u32)> {
+    let regex = option_regex();
+    config_files().into_iter().find_map(|file| {
+        let content = fs::read_to_string(&file).ok()?;
+        let value = content.lines().find_map(|line| {
+            let captures = regex.captures(line)?;
+            captures[2].parse().ok()
+        })?;
+        Some((file, value))
+    })
+}
+
+/// What the set bits of a Coolbits value unlock
+pub fn describe(value: u32) -> Vec<&'static str> {
+    BITS.iter()
+        .filter(|(bit, _)| value & bit != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+pub fn snippet(value: u32) -> String {
+    format!(
+        "# Generated by nvidia_oc, unlocks {}\n\
+         Section \"Device\"\n    \
+             Identifier \"nvidia_oc\"\n    \
+             Driver \"nvidia\"\n    \
+             Option \"Coolbits\" \"{}\"\n\
+         EndSection\n",
+        describe(value).join(", "),
+        value
+    )
+}
+
+/// Copies a file next to itself before it's changed
+fn backup(path: &Path) -> io::Result<PathBuf> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".nvidia_oc.bak");
+    let backup = PathBuf::from(backup);
+    fs::copy(path, &backup)?;
+    Ok(backup)
+}
+
+/// Sets Coolbits to `value`, in place in the config file that already sets
+/// it, or in a new snippet otherwise. Changed files are backed up first.
+/// Returns the written file and the backup, if one was made.
+pub fn install(value: u32) -> io::Result<(PathBuf, Option<PathBuf>)> {
+    if let Some((path, _)) = current() {
+        let backup = backup(&path)?;
+        let regex = option_regex();
+        let content: Vec<String> = fs::read_to_string(&path)?
+            .lines()
+            .map(|line| {
+                regex
+                    .replace(line, format!("${{1}}{}${{3}}", value))
+                    .into_owned()
+            })
+            .collect();
+        fs::write(&path, content.join("\n") + "\n")?;
+        return Ok((path, Some(backup)));
+    }
+
+    let path = PathBuf::from(SNIPPET_PATH);
+    let backup = if path.exists() {
+        Some(backup(&path)?)
+    } else {
+        None
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, snippet(value))?;
+    Ok((path, backup))
+}
@@ -0,0 +1,493 @@
+use crate::alert::AlertMonitor;
+use crate::battery;
+use crate::config::{Config, ConfigWatcher};
+use crate::drift;
+use crate::events::EventMonitor;
+use crate::fan::{FanController, FanPolicy};
+use crate::governor::GovernorState;
+use crate::hint::{self, ExpectHint};
+use crate::history::Source;
+use crate::hwmon;
+use crate::idle::IdleState;
+use crate::lock::ApplyLock;
+use crate::retry::retry;
+use crate::signal;
+use crate::watchdog;
+use crate::Sets;
+use nvml_wrapper::Nvml;
+use std::{
+    collections::HashSet,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often to look for newly attached GPUs, e.g. a Thunderbolt eGPU
+const HOTPLUG_SCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the NVML handle is replaced while its GPU count stays the same.
+/// NVML doesn't document whether a handle sees GPUs attached after it was
+/// initialized, a new one enumerates them for sure.
+const NVML_REINIT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long the config file has to stay untouched before it is reloaded
+const RELOAD_DEBOUNCE: Duration = Duration::from_secs(1);
+
+struct Daemon {
+    config: Config,
+    /// UUIDs of the GPUs the config has already been applied to
+    known: HashSet<String>,
+    controllers: Vec<(String, FanController)>,
+    monitors: Vec<(String, AlertMonitor)>,
+    governors: Vec<(String, GovernorState)>,
+    idle: Vec<(String, IdleState)>,
+    /// Whether entries' `battery` settings apply
+    on_battery: bool,
+    /// How often drifted settings are reapplied, see [`Daemon::reapply_drifted`]
+    reapply_interval: Option<Duration>,
+    last_reapply: Instant,
+    /// Whether readings are published for fan control tools, see [`hwmon`]
+    hwmon: bool,
+}
+
+impl Daemon {
+    /// Applies the matching config entry to every GPU not seen before and
+    /// forgets GPUs that have gone away, so replugging them reapplies too.
+    fn apply_new_devices(&mut self, nvml: &Nvml) {
+        let count = match nvml.device_count() {
+            Ok(count) => count,
+            Err(e) => {
+                eprintln!("Failed to get GPU count: {:?}", e);
+                return;
+            }
+        };
+
+        let mut present = HashSet::new();
+        for index in 0..count {
+            let mut device = match nvml.device_by_index(index) {
+                Ok(device) => device,
+                Err(e) => {
+                    eprintln!("Failed to get GPU {}: {:?}", index, e);
+                    continue;
+                }
+            };
+            let Ok(uuid) = device.uuid() else {
+                continue;
+            };
+            present.insert(uuid.clone());
+            if self.known.contains(&uuid) {
+                continue;
+            }
+
+            let name = device.name().unwrap_or_default();
+            if let Some(sets) = self.entry(index, &uuid, &name) {
+                let applied = {
+                    let _lock = ApplyLock::acquire().expect("Failed to acquire apply lock");
+                    sets.apply(&mut device, Source::Daemon)
+                };
+                // Not retried every scan, the hook would run every few seconds
+                if let Err(e) = applied {
+                    eprintln!("GPU {} ({}): {}", index, uuid, e);
+                    self.known.insert(uuid);
+                    continue;
+                }
+                println!("Successfully set GPU {} ({}) parameters.", index, uuid);
+
+                if let Some(curves) = &sets.fan_curve {
+                    for controller in curves.clone().into_controllers() {
+                        self.controllers.push((uuid.clone(), controller));
+                    }
+                }
+                for alert in sets.alerts.iter().flatten() {
+                    self.monitors
+                        .push((uuid.clone(), AlertMonitor::new(alert.clone())));
+                }
+                if let Some(governor) = &sets.power_governor {
+                    self.governors
+                        .push((uuid.clone(), GovernorState::new(governor.clone())));
+                }
+                if let Some(profile) = &sets.idle_profile {
+                    let locked_clocks = sets.min_clock.zip(sets.max_clock);
+                    self.idle
+                        .push((uuid.clone(), IdleState::new(profile.clone(), locked_clocks)));
+                }
+            }
+            self.known.insert(uuid);
+        }
+
+        self.known.retain(|uuid| present.contains(uuid));
+        self.controllers.retain(|(uuid, _)| present.contains(uuid));
+        self.monitors.retain(|(uuid, _)| present.contains(uuid));
+        self.governors.retain(|(uuid, _)| present.contains(uuid));
+        self.idle.retain(|(uuid, _)| present.contains(uuid));
+    }
+
+    /// Switches to a new config and reapplies it to every GPU. The old config
+    /// stays active if the new one doesn't parse.
+    fn reload(&mut self, path: &Path, nvml: &Nvml) {
+        let config = match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Not reloading config: {}", e);
+                return;
+            }
+        };
+
+        let changes = self.config.diff(&config);
+        if changes.is_empty() {
+            println!("Config file changed, but no settings differ.");
+            return;
+        }
+        println!("Reloading config:");
+        for change in changes {
+            println!("  {}", change);
+        }
+
+        self.config = config;
+        self.known.clear();
+        self.controllers.clear();
+        self.monitors.clear();
+        self.governors.clear();
+        self.idle.clear();
+        self.apply_new_devices(nvml);
+    }
+
+    /// The settings for a GPU, with its `battery` settings on top while on
+    /// battery
+    fn entry(&self, index: u32, uuid: &str, name: &str) -> Option<Sets> {
+        let (_, sets) = self.config.entry(index, uuid, name)?;
+        if self.on_battery {
+            // Checked when the config was loaded
+            Some(battery::battery_sets(&sets).unwrap_or(sets))
+        } else {
+            Some(sets)
+        }
+    }
+
+    /// Reapplies settings that drifted from the config, e.g. offsets a
+    /// driver event reset, logging each correction. Power limits a governor
+    /// or an active idle profile manage are left to them. Hooks don't run.
+    fn reapply_drifted(&mut self, nvml: &Nvml) {
+        let count = nvml.device_count().unwrap_or(0);
+        for index in 0..count {
+            let Ok(mut device) = nvml.device_by_index(index) else {
+                continue;
+            };
+            let uuid = device.uuid().unwrap_or_default();
+            if !self.known.contains(&uuid) {
+                continue;
+            }
+            let name = device.name().unwrap_or_default();
+            let Some(sets) = self.entry(index, &uuid, &name) else {
+                continue;
+            };
+
+            let managed = self.governors.iter().any(|(u, _)| *u == uuid)
+                || self
+                    .idle
+                    .iter()
+                    .any(|(u, idle)| *u == uuid && idle.is_active());
+            let mut drifts = drift::drift(&sets, &device);
+            // Reapplying can't fix a value NVML can't read
+            drifts.retain(|drift| !drift.actual.starts_with("unreadable"));
+            if managed {
+                drifts.retain(|drift| drift.setting != "powerLimit");
+            }
+            if drifts.is_empty() {
+                continue;
+            }
+
+            for drift in &drifts {
+                println!(
+                    "GPU {} {} drifted to {}, reapplying {}.",
+                    index, drift.parameter, drift.actual, drift.configured
+                );
+            }
+            let _lock = ApplyLock::acquire().expect("Failed to acquire apply lock");
+            // A correction, not an apply the hooks should hear about
+            drift::only_drifted(&sets, &drifts).apply_settings(&mut device, Source::Daemon);
+        }
+    }
+
+    /// Forgets everything about a GPU, so the next scan applies its entry
+    /// again
+    fn forget(&mut self, uuid: &str) {
+        self.known.remove(uuid);
+        self.controllers.retain(|(u, _)| u != uuid);
+        self.monitors.retain(|(u, _)| u != uuid);
+        self.governors.retain(|(u, _)| u != uuid);
+        self.idle.retain(|(u, _)| u != uuid);
+    }
+
+    /// Switches GPUs whose entry has `battery` settings between them and the
+    /// entry itself when the system goes on or off battery. Going back to AC
+    /// resets those GPUs to stock first, so settings only the battery
+    /// profile had don't linger.
+    fn check_power_source(&mut self, nvml: &Nvml) {
+        let Some(on_battery) = battery::on_battery() else {
+            return;
+        };
+        if on_battery == self.on_battery {
+            return;
+        }
+        self.on_battery = on_battery;
+        println!(
+            "Running on {}, switching profiles.",
+            if on_battery { "battery" } else { "AC power" }
+        );
+
+        let count = nvml.device_count().unwrap_or(0);
+        for index in 0..count {
+            let Ok(mut device) = nvml.device_by_index(index) else {
+                continue;
+            };
+            let uuid = device.uuid().unwrap_or_default();
+            let name = device.name().unwrap_or_default();
+            let Some((_, sets)) = self.config.entry(index, &uuid, &name) else {
+                continue;
+            };
+            if sets.battery.is_none() {
+                continue;
+            }
+            if !on_battery {
+                let _lock = ApplyLock::acquire().expect("Failed to acquire apply lock");
+                watchdog::reset_to_stock(&mut device, Source::Daemon);
+            }
+            self.forget(&uuid);
+        }
+        self.apply_new_devices(nvml);
+    }
+
+    fn check_alerts(&mut self, nvml: &Nvml) {
+        for (uuid, monitor) in self.monitors.iter_mut() {
+            let device = match nvml.device_by_uuid(uuid.as_str()) {
+                Ok(device) => device,
+                Err(e) => {
+                    eprintln!("Failed to get GPU {}: {:?}", uuid, e);
+                    continue;
+                }
+            };
+            if let Err(e) = monitor.check(uuid, &device) {
+                eprintln!("Failed to check alert for GPU {}: {:?}", uuid, e);
+            }
+        }
+    }
+
+    fn update_governors(&mut self, nvml: &Nvml) {
+        for (uuid, governor) in self.governors.iter_mut() {
+            let mut device = match nvml.device_by_uuid(uuid.as_str()) {
+                Ok(device) => device,
+                Err(e) => {
+                    eprintln!("Failed to get GPU {}: {:?}", uuid, e);
+                    continue;
+                }
+            };
+            if let Err(e) = governor.update(uuid, &mut device) {
+                eprintln!("Failed to update power limit of GPU {}: {:?}", uuid, e);
+            }
+        }
+    }
+
+    fn update_idle(&mut self, nvml: &Nvml) {
+        for (uuid, idle) in self.idle.iter_mut() {
+            let mut device = match nvml.device_by_uuid(uuid.as_str()) {
+                Ok(device) => device,
+                Err(e) => {
+                    eprintln!("Failed to get GPU {}: {:?}", uuid, e);
+                    continue;
+                }
+            };
+            if let Err(e) = idle.update(uuid, &mut device) {
+                eprintln!("Failed to update idle profile of GPU {}: {:?}", uuid, e);
+            }
+        }
+    }
+
+    /// Runs the periodic work every `interval` until the next hotplug scan is
+    /// due. Events are subscribed to anew each time, so GPUs attached since
+    /// the last scan are included.
+    fn run_until_rescan(
+        &mut self,
+        nvml: &Nvml,
+        path: &Path,
+        watcher: &mut Option<ConfigWatcher>,
+        interval: Duration,
+    ) {
+        let events = match EventMonitor::all(nvml) {
+            Ok(events) => Some(events),
+            Err(e) => {
+                eprintln!("Failed to subscribe to GPU events: {:?}", e);
+                None
+            }
+        };
+
+        let started = Instant::now();
+        while started.elapsed() < HOTPLUG_SCAN_INTERVAL && !signal::interrupted() {
+            if watcher.as_mut().is_some_and(ConfigWatcher::poll) {
+                self.reload(path, nvml);
+            }
+            self.check_power_source(nvml);
+            if self
+                .reapply_interval
+                .is_some_and(|every| self.last_reapply.elapsed() >= every)
+            {
+                self.last_reapply = Instant::now();
+                self.reapply_drifted(nvml);
+            }
+            self.update_fans(nvml);
+            self.check_alerts(nvml);
+            self.update_governors(nvml);
+            self.update_idle(nvml);
+            if self.hwmon {
+                if let Err(e) = hwmon::publish(nvml) {
+                    eprintln!("Failed to publish hwmon readings: {}", e);
+                }
+            }
+
+            match &events {
+                Some(events) => events.wait(interval, |event| println!("{}", event)),
+                None => thread::sleep(interval),
+            }
+        }
+    }
+
+    /// Leaves the GPUs safe when the daemon stops: reset to stock with
+    /// `revertOnExit`, otherwise fans driven by a fan curve go back to the
+    /// driver so they don't stay at the last speed set
+    fn shutdown(&self, nvml: &Nvml) {
+        let _lock = ApplyLock::acquire().expect("Failed to acquire apply lock");
+        for uuid in &self.known {
+            let Ok(mut device) = nvml.device_by_uuid(uuid.as_str()) else {
+                continue;
+            };
+            // GPUs without an entry were never touched
+            let index = device.index().unwrap_or_default();
+            let name = device.name().unwrap_or_default();
+            if self.config.entry(index, uuid, &name).is_none() {
+                continue;
+            }
+            if self.config.revert_on_exit {
+                watchdog::reset_to_stock(&mut device, Source::Daemon);
+                println!("Reset GPU {} to stock settings.", uuid);
+            } else if self.controllers.iter().any(|(u, _)| u == uuid) {
+                for fan in 0..device.num_fans().unwrap_or(0) {
+                    if let Err(e) = FanPolicy::Auto.apply(&mut device, fan) {
+                        eprintln!(
+                            "Failed to hand fan {} of GPU {} back to the driver: {:?}",
+                            fan, uuid, e
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn update_fans(&mut self, nvml: &Nvml) {
+        for (uuid, controller) in self.controllers.iter_mut() {
+            let mut device = match nvml.device_by_uuid(uuid.as_str()) {
+                Ok(device) => device,
+                Err(e) => {
+                    eprintln!("Failed to get GPU {}: {:?}", uuid, e);
+                    continue;
+                }
+            };
+            if let Err(e) = controller.update(&mut device) {
+                eprintln!("Failed to update fan speed of GPU {}: {:?}", uuid, e);
+            }
+        }
+    }
+}
+
+/// Runs until SIGINT or SIGTERM, see [`Daemon::shutdown`] for what happens
+/// then. Meanwhile it:
+///
+/// - applies the config to every matching GPU, and to GPUs attached later on
+/// - keeps the configured fan curves, alerts, power governors and idle
+///   profiles running
+/// - reloads the config whenever the file changes
+/// - logs NVML events such as Xid errors as they arrive
+/// - switches to entries' `battery` settings while on battery
+/// - reapplies drifted settings every `reapply_interval`, if given
+/// - publishes readings for fan control tools with `hwmon`
+pub fn run(
+    path: &Path,
+    config: Config,
+    interval: Duration,
+    reapply_interval: Option<Duration>,
+    hwmon: bool,
+) {
+    let mut daemon = Daemon {
+        config,
+        known: HashSet::new(),
+        controllers: Vec::new(),
+        monitors: Vec::new(),
+        governors: Vec::new(),
+        idle: Vec::new(),
+        on_battery: battery::on_battery().unwrap_or(false),
+        reapply_interval,
+        last_reapply: Instant::now(),
+        hwmon,
+    };
+    if hwmon {
+        hwmon::clear();
+    }
+
+    let nvml = retry(Nvml::init).expect_hint("Failed to initialize NVML");
+    // GPUs reverted to stock keep their stock settings until the config is
+    // reloaded or they are replugged
+    daemon.known.extend(watchdog::revert_unconfirmed(&nvml));
+    let mut nvml = Some(nvml);
+    let mut initialized = Instant::now();
+    let mut last_count = None;
+
+    let mut watcher = match ConfigWatcher::new(path, RELOAD_DEBOUNCE) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            eprintln!("Failed to watch config file, changes need a restart: {}", e);
+            None
+        }
+    };
+
+    signal::catch_termination();
+    while !signal::interrupted() {
+        let count = match &nvml {
+            Some(nvml) => {
+                daemon.apply_new_devices(nvml);
+                daemon.run_until_rescan(nvml, path, &mut watcher, interval);
+                nvml.device_count().ok()
+            }
+            None => {
+                thread::sleep(HOTPLUG_SCAN_INTERVAL);
+                None
+            }
+        };
+        // A changed count shows the handle keeps up with hotplugs
+        if nvml.is_some() && count != last_count {
+            last_count = count;
+            initialized = Instant::now();
+            continue;
+        }
+        if nvml.is_some() && initialized.elapsed() < NVML_REINIT_INTERVAL {
+            continue;
+        }
+
+        // The old handle has to be shut down before a new one is initialized
+        drop(nvml.take());
+        nvml = match Nvml::init() {
+            Ok(nvml) => Some(nvml),
+            Err(e) => {
+                eprintln!("Failed to reinitialize NVML: {}", hint::describe(&e));
+                None
+            }
+        };
+        initialized = Instant::now();
+    }
+
+    println!("Stopping.");
+    if let Some(nvml) = &nvml {
+        daemon.shutdown(nvml);
+    }
+    if hwmon {
+        hwmon::clear();
+    }
+}
@@ -0,0 +1,455 @@
+use crate::color::{paint, Severity};
+use crate::coolbits;
+use crate::hint;
+use crate::output::{self, OutputFormat};
+use crate::polkit;
+use crate::power;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Nvml;
+use serde::Serialize;
+use std::{collections::HashMap, fs, path::Path, process::Command};
+
+/// First driver with NVML clock offset and per-pstate offset support
+const MIN_DRIVER: u32 = 555;
+
+/// Tools that set clocks, power limits or fans themselves and undo what this
+/// tool applies, by process name
+const CONFLICTING_TOOLS: [(&str, &str); 3] = [
+    ("gwe", "GreenWithEnvy"),
+    ("lactd", "LACT"),
+    ("coolercontrold", "CoolerControl"),
+];
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+}
+
+fn check(name: &'static str, status: Status, detail: impl Into<String>) -> Check {
+    Check {
+        name,
+        status,
+        detail: detail.into(),
+    }
+}
+
+fn kernel_module() -> Check {
+    match fs::read_to_string("/sys/module/nvidia/version") {
+        Ok(version) => check(
+            "kernel module",
+            Status::Pass,
+            format!("nvidia {} loaded", version.trim()),
+        ),
+        Err(_) if Path::new("/sys/module/nouveau").exists() => check(
+            "kernel module",
+            Status::Fail,
+            "nouveau is loaded instead of the NVIDIA driver",
+        ),
+        Err(_) => check(
+            "kernel module",
+            Status::Fail,
+            "nvidia isn't loaded, check `modprobe nvidia` and dmesg",
+        ),
+    }
+}
+
+fn driver_version(nvml: &Nvml) -> Check {
+    let version = match nvml.sys_driver_version() {
+        Ok(version) => version,
+        Err(e) => return check("driver version", Status::Fail, hint::describe(&e)),
+    };
+    let major = version
+        .split('.')
+        .next()
+        .and_then(|major| major.parse::<u32>().ok());
+    match major {
+        Some(major) if major >= MIN_DRIVER => check("driver version", Status::Pass, version),
+        Some(_) => check(
+            "driver version",
+            Status::Warn,
+            format!(
+                "{}, clock offsets through NVML need {} or newer",
+                version, MIN_DRIVER
+            ),
+        ),
+        None => check(
+            "driver version",
+            Status::Warn,
+            format!("{}, unknown version scheme", version),
+        ),
+    }
+}
+
+fn privileges(root: bool) -> Check {
+    if root {
+        return check("privileges", Status::Pass, "running as root");
+    }
+    if polkit::installed() && which::which("pkexec").is_ok() {
+        return check(
+            "privileges",
+            Status::Pass,
+            "not root, pkexec escalates through the installed polkit policy",
+        );
+    }
+    match ["sudo", "doas", "pkexec"]
+        .into_iter()
+        .find(|tool| which::which(tool).is_ok())
+    {
+        Some(tool) => check(
+            "privileges",
+            Status::Pass,
+            format!("not root, setting parameters escalates through {}", tool),
+        ),
+        None => check(
+            "privileges",
+            Status::Fail,
+            "not root and neither sudo, doas nor pkexec is installed",
+        ),
+    }
+}
+
+fn systemd_active(unit: &str) -> bool {
+    Command::new("systemctl")
+        .args(["is-active", "--quiet", unit])
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Without persistence the driver unloads once no client holds the GPU, and
+/// applied settings go with it
+fn persistence(nvml: &Nvml) -> Check {
+    if systemd_active("nvidia-persistenced") {
+        return check(
+            "persistence",
+            Status::Pass,
+            "nvidia-persistenced is running",
+        );
+    }
+    let count = nvml.device_count().unwrap_or(0);
+    let persistent = (0..count).all(|index| {
+        nvml.device_by_index(index)
+            .and_then(|device| device.is_in_persistent_mode())
+            .unwrap_or(false)
+    });
+    if count > 0 && persistent {
+        check("persistence", Status::Pass, "persistence mode is enabled")
+    } else {
+        check(
+            "persistence",
+            Status::Warn,
+            "nvidia-persistenced isn't running, settings can reset when no \
+             program uses the GPU: systemctl enable --now nvidia-persistenced",
+        )
+    }
+}
+
+/// Only relevant for GPUs NVML refuses offsets on, which fall back to
+/// nvidia-settings with `--legacy-fallback`
+fn coolbits(nvml: &Nvml) -> Check {
+    let count = nvml.device_count().unwrap_or(0);
+    let legacy = (0..count).any(|index| {
+        nvml.device_by_index(index).is_ok_and(|device| {
+            matches!(device.gpc_clock_vf_offset(), Err(NvmlError::NotSupported))
+        })
+    });
+    if !legacy {
+        return check("coolbits", Status::Pass, "not needed, NVML sets offsets");
+    }
+    if which::which("nvidia-settings").is_err() {
+        return check(
+            "coolbits",
+            Status::Warn,
+            "NVML can't set offsets on this GPU and nvidia-settings isn't installed \
+             for --legacy-fallback",
+        );
+    }
+    match coolbits::current() {
+        Some((path, value)) if value & 8 != 0 => check(
+            "coolbits",
+            Status::Pass,
+            format!(
+                "{} in {} for --legacy-fallback through nvidia-settings",
+                value,
+                path.display()
+            ),
+        ),
+        _ => check(
+            "coolbits",
+            Status::Warn,
+            "NVML can't set offsets on this GPU, enable Coolbits for \
+             --legacy-fallback with `nvidia_oc coolbits --install`",
+        ),
+    }
+}
+
+/// Names and command lines of running processes, from /proc
+fn process_names() -> Vec<String> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            // Python tools like GWE show up as python, their script name is
+            // in the command line
+            let cmdline = fs::read(entry.path().join("cmdline")).ok()?;
+            let comm = fs::read_to_string(entry.path().join("comm")).ok()?;
+            let args = String::from_utf8_lossy(&cmdline).replace('\0', " ");
+            Some(format!("{} {}", comm.trim(), args))
+        })
+        .collect()
+}
+
+fn conflicting_tools() -> Check {
+    let processes = process_names();
+    let mut running: Vec<&str> = CONFLICTING_TOOLS
+        .iter()
+        .filter(|(process, _)| {
+            processes.iter().any(|line| {
+                line.split_whitespace()
+                    .any(|word| Path::new(word).file_name() == Some(process.as_ref()))
+            })
+        })
+        .map(|(_, name)| *name)
+        .collect();
+    if power::dynamic_boost_active() {
+        running.push("nvidia-powerd (Dynamic Boost moves the power limit)");
+    }
+    if running.is_empty() {
+        check("conflicting tools", Status::Pass, "none running")
+    } else {
+        check(
+            "conflicting tools",
+            Status::Warn,
+            format!("{} can override these settings", running.join(", ")),
+        )
+    }
+}
+
+/// Options the nvidia module was loaded with, from /proc/driver/nvidia/params
+/// where they are listed without the `NVreg_` prefix
+fn module_params() -> HashMap<String, String> {
+    let Ok(params) = fs::read_to_string("/proc/driver/nvidia/params") else {
+        return HashMap::new();
+    };
+    params
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| {
+            (
+                name.trim().to_string(),
+                value.trim().trim_matches('"').to_string(),
+            )
+        })
+        .collect()
+}
+
+fn driver_major() -> Option<u32> {
+    let version = fs::read_to_string("/sys/module/nvidia/version").ok()?;
+    version.trim().split('.').next()?.parse().ok()
+}
+
+/// `NVreg_RegistryDwords` keys that pin the performance level, after which
+/// the driver accepts offset writes but never applies them
+fn registry_dwords(params: &HashMap<String, String>) -> Check {
+    let dwords = params.get("RegistryDwords").map_or("", String::as_str);
+    let pinning: Vec<&str> = dwords
+        .split(';')
+        .filter_map(|dword| {
+            let (key, value) = dword.split_once('=')?;
+            let key = key.trim();
+            let value = value.trim();
+            let pins = match key {
+                "PowerMizerEnable" => value == "0" || value == "0x0",
+                "PerfLevelSrc"
+                | "PowerMizerLevel"
+                | "PowerMizerDefault"
+                | "PowerMizerDefaultAC" => true,
+                _ => false,
+            };
+            pins.then_some(key)
+        })
+        .collect();
+    if pinning.is_empty() {
+        check(
+            "NVreg_RegistryDwords",
+            Status::Pass,
+            "no PowerMizer overrides",
+        )
+    } else {
+        check(
+            "NVreg_RegistryDwords",
+            Status::Warn,
+            format!(
+                "{} pin the performance level, offsets and locked clocks are \
+                 accepted but have no effect. Remove them from the modprobe config.",
+                pinning.join(", ")
+            ),
+        )
+    }
+}
+
+fn profiling(params: &HashMap<String, String>, root: bool) -> Check {
+    match params
+        .get("RestrictProfilingToAdminUsers")
+        .map(String::as_str)
+    {
+        Some("1") if !root => check(
+            "NVreg_RestrictProfilingToAdminUsers",
+            Status::Warn,
+            "GPU performance counters are restricted to root, run as root or \
+             load the module with NVreg_RestrictProfilingToAdminUsers=0",
+        ),
+        Some(value) => check(
+            "NVreg_RestrictProfilingToAdminUsers",
+            Status::Pass,
+            format!("{}, performance counters are readable", value),
+        ),
+        None => check(
+            "NVreg_RestrictProfilingToAdminUsers",
+            Status::Pass,
+            "not set",
+        ),
+    }
+}
+
+/// Runtime power management powers idle GPUs off, which drops everything
+/// applied to them
+fn runtime_power_management(params: &HashMap<String, String>) -> Check {
+    let mode = params
+        .get("DynamicPowerManagement")
+        .map_or("0", String::as_str);
+    let suspending: Vec<String> = fs::read_dir("/sys/bus/pci/drivers/nvidia")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            fs::read_to_string(entry.path().join("power/control"))
+                .is_ok_and(|control| control.trim() == "auto")
+        })
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    if mode == "0" || suspending.is_empty() {
+        check(
+            "NVreg_DynamicPowerManagement",
+            Status::Pass,
+            "GPUs stay powered",
+        )
+    } else {
+        check(
+            "NVreg_DynamicPowerManagement",
+            Status::Warn,
+            format!(
+                "{} can power off when idle and lose applied settings, run \
+                 `nvidia_oc daemon` or set NVreg_DynamicPowerManagement=0",
+                suspending.join(", ")
+            ),
+        )
+    }
+}
+
+/// Whether any GPU runs the GSP firmware, /proc lists its version or N/A
+fn gsp_active() -> bool {
+    let Ok(gpus) = fs::read_dir("/proc/driver/nvidia/gpus") else {
+        return false;
+    };
+    gpus.filter_map(|gpu| gpu.ok()).any(|gpu| {
+        fs::read_to_string(gpu.path().join("information")).is_ok_and(|information| {
+            information.lines().any(|line| {
+                line.strip_prefix("GPU Firmware:")
+                    .is_some_and(|version| version.trim() != "N/A")
+            })
+        })
+    })
+}
+
+fn gsp_firmware(params: &HashMap<String, String>) -> Check {
+    if !gsp_active() {
+        let detail = match params.get("EnableGpuFirmware") {
+            Some(value) => format!("not running, NVreg_EnableGpuFirmware={}", value),
+            None => "not running".to_string(),
+        };
+        return check("GSP firmware", Status::Pass, detail);
+    }
+    match driver_major() {
+        Some(major) if major < MIN_DRIVER => check(
+            "GSP firmware",
+            Status::Warn,
+            format!(
+                "running on driver {}, where clock offset writes can be ignored \
+                 with GSP. Update the driver or load the module with \
+                 NVreg_EnableGpuFirmware=0.",
+                major
+            ),
+        ),
+        _ => check("GSP firmware", Status::Pass, "running"),
+    }
+}
+
+/// Module options and driver states that silently block overclocking
+fn module_checks(root: bool) -> Vec<Check> {
+    let params = module_params();
+    if params.is_empty() {
+        return Vec::new();
+    }
+    vec![
+        registry_dwords(&params),
+        profiling(&params, root),
+        runtime_power_management(&params),
+        gsp_firmware(&params),
+    ]
+}
+
+/// Warns about the module options and driver states `doctor` flags, before
+/// settings are applied that they would make ineffective
+pub fn warn_blockers(root: bool) {
+    for check in module_checks(root) {
+        if check.status != Status::Pass {
+            eprintln!("Warning: {}: {}", check.name, check.detail);
+        }
+    }
+}
+
+/// Checks the prerequisites of this tool and prints a pass/fail checklist.
+/// Returns whether every check passed or only warned.
+pub fn run(root: bool, format: OutputFormat) -> bool {
+    let mut checks = vec![kernel_module()];
+    match Nvml::init() {
+        Ok(nvml) => {
+            checks.push(check("NVML", Status::Pass, "libnvidia-ml loaded"));
+            checks.push(driver_version(&nvml));
+            checks.push(persistence(&nvml));
+            checks.push(coolbits(&nvml));
+        }
+        Err(e) => checks.push(check("NVML", Status::Fail, hint::describe(&e))),
+    }
+    checks.extend(module_checks(root));
+    checks.push(privileges(root));
+    checks.push(conflicting_tools());
+
+    let passed = checks.iter().all(|check| check.status != Status::Fail);
+    if format != OutputFormat::Text {
+        output::print(format, &checks);
+        return passed;
+    }
+
+    for check in &checks {
+        let status = match check.status {
+            Status::Pass => paint("PASS", Severity::Good),
+            Status::Warn => paint("WARN", Severity::Warning),
+            Status::Fail => paint("FAIL", Severity::Bad),
+        };
+        println!("[{}] {}: {}", status, check.name, check.detail);
+    }
+    passed
+}
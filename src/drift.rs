@@ -0,0 +1,174 @@
+use crate::offset::Offset;
+use crate::power::PowerLimit;
+use crate::pstate::{pstate_name, PstateOffsets};
+use crate::{fan::FanSpeeds, offset, power, thermal, Sets};
+use nvml_wrapper::enum_wrappers::device::{Clock, ComputeMode, PerformanceState};
+use nvml_wrapper::Device;
+use serde::Serialize;
+
+/// A parameter whose live value differs from the configured one
+#[derive(Serialize)]
+pub struct Drift {
+    /// Config key of the setting, e.g. `powerLimit`
+    #[serde(skip)]
+    pub setting: &'static str,
+    pub parameter: String,
+    pub configured: String,
+    pub actual: String,
+}
+
+fn check<T: PartialEq>(
+    drifts: &mut Vec<Drift>,
+    setting: &'static str,
+    parameter: impl Into<String>,
+    configured: T,
+    actual: Result<T, impl std::fmt::Debug>,
+    show: impl Fn(&T) -> String,
+) {
+    let actual = match actual {
+        Ok(actual) if actual == configured => return,
+        Ok(actual) => show(&actual),
+        Err(e) => format!("unreadable ({:?})", e),
+    };
+    drifts.push(Drift {
+        setting,
+        parameter: parameter.into(),
+        configured: show(&configured),
+        actual,
+    });
+}
+
+/// Compares the settings NVML can read back against the configured ones,
+/// power limits and offsets clamped to the device's range as applying them
+/// does. Locked clocks have no NVML getter and relative offsets have no
+/// target, so neither is checked.
+pub fn drift(sets: &Sets, device: &Device) -> Vec<Drift> {
+    let mut drifts = Vec::new();
+    let mhz = |v: &i32| format!("{} MHz", v);
+
+    if let Some(limit) = sets
+        .power_limit
+        .filter(|limit| !matches!(limit, PowerLimit::Relative(_)))
+        .and_then(|limit| limit.resolve(device).ok())
+        .map(|limit| power::in_range(device, limit))
+    {
+        check(
+            &mut drifts,
+            "powerLimit",
+            "power limit",
+            limit,
+            device.power_management_limit(),
+            |mw| power::format_watts(*mw),
+        );
+    }
+
+    if let Some(limit) = sets.temp_limit {
+        check(
+            &mut drifts,
+            "tempLimit",
+            "target temperature",
+            Some(limit),
+            thermal::temp_limit(device),
+            |c| c.map_or("none".to_string(), |c| format!("{} °C", c)),
+        );
+    }
+
+    if let Some(Offset::Absolute(offset)) = sets.freq_offset {
+        check(
+            &mut drifts,
+            "freqOffset",
+            "core clock offset",
+            offset::in_range(device, Clock::Graphics, PerformanceState::Zero, offset),
+            device.gpc_clock_vf_offset(),
+            mhz,
+        );
+    }
+
+    if let Some(Offset::Absolute(offset)) = sets.mem_offset {
+        check(
+            &mut drifts,
+            "memOffset",
+            "memory clock offset",
+            offset::in_range(device, Clock::Memory, PerformanceState::Zero, offset),
+            device.mem_clock_vf_offset(),
+            mhz,
+        );
+    }
+
+    if let Some(PstateOffsets(offsets)) = &sets.freq_offset_pstate {
+        for (pstate, offset) in offsets {
+            check(
+                &mut drifts,
+                "freqOffsetPstate",
+                format!("core clock offset {}", pstate_name(*pstate)),
+                offset::in_range(device, Clock::Graphics, *pstate, *offset),
+                device
+                    .clock_offset(Clock::Graphics, *pstate)
+                    .map(|o| o.clock_offset_mhz),
+                mhz,
+            );
+        }
+    }
+
+    if let Some(ecc) = sets.ecc {
+        // Compare the pending state, a toggle only takes effect after reboot
+        check(
+            &mut drifts,
+            "ecc",
+            "ECC mode",
+            ecc.enabled(),
+            device.is_ecc_enabled().map(|ecc| ecc.pending_enabled),
+            |enabled| if *enabled { "on" } else { "off" }.to_string(),
+        );
+    }
+
+    if let Some(mig) = sets.mig {
+        check(
+            &mut drifts,
+            "mig",
+            "MIG mode",
+            mig.enabled(),
+            device.mig_mode().map(|mode| mode.pending != 0),
+            |enabled| if *enabled { "on" } else { "off" }.to_string(),
+        );
+    }
+
+    if let Some(mode) = sets.compute_mode {
+        check(
+            &mut drifts,
+            "computeMode",
+            "compute mode",
+            ComputeMode::from(mode),
+            device.compute_mode(),
+            |mode| format!("{:?}", mode),
+        );
+    }
+
+    if let Some(FanSpeeds(speeds)) = &sets.fan {
+        for (fan, speed) in speeds {
+            check(
+                &mut drifts,
+                "fan",
+                format!("fan {} speed", fan),
+                *speed,
+                device.fan_speed(*fan),
+                |speed| format!("{}%", speed),
+            );
+        }
+    }
+
+    drifts
+}
+
+/// Only the settings of `sets` that drifted, so reapplying them leaves the
+/// others alone
+pub fn only_drifted(sets: &Sets, drifts: &[Drift]) -> Sets {
+    let Ok(serde_json::Value::Object(mut settings)) = serde_json::to_value(sets) else {
+        panic!("Failed to serialize settings");
+    };
+    settings.retain(|key, _| {
+        key == "legacyFallback" || drifts.iter().any(|drift| drift.setting == key)
+    });
+    serde_json::from_value(serde_json::Value::Object(settings))
+        .expect("Failed to deserialize settings")
+}
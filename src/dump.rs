@@ -0,0 +1,289 @@
+use crate::output::{self, OutputFormat};
+use crate::pstate::pstate_name;
+use crate::throttle::{throttle_reason_names, violation_times};
+use nvml_wrapper::enum_wrappers::device::{
+    Clock, ClockId, EccCounter, MemoryError, PcieUtilCounter, TemperatureSensor,
+    TemperatureThreshold,
+};
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::{Device, Nvml};
+use serde_json::{json, Map, Value};
+
+const CLOCKS: [(Clock, &str); 4] = [
+    (Clock::Graphics, "graphics"),
+    (Clock::SM, "sm"),
+    (Clock::Memory, "memory"),
+    (Clock::Video, "video"),
+];
+
+const CLOCK_IDS: [(ClockId, &str); 4] = [
+    (ClockId::Current, "current"),
+    (ClockId::TargetAppClock, "targetApplication"),
+    (ClockId::DefaultAppClock, "defaultApplication"),
+    (ClockId::CustomerMaxBoost, "customerMaxBoost"),
+];
+
+const THRESHOLDS: [(TemperatureThreshold, &str); 4] = [
+    (TemperatureThreshold::Shutdown, "shutdown"),
+    (TemperatureThreshold::Slowdown, "slowdown"),
+    (TemperatureThreshold::MemoryMax, "memoryMax"),
+    (TemperatureThreshold::GpuMax, "gpuMax"),
+];
+
+/// A reading, or the error NVML returned for it, so a report shows what the
+/// GPU doesn't support instead of leaving it out
+fn reading<T: Into<Value>>(result: Result<T, NvmlError>) -> Value {
+    match result {
+        Ok(value) => value.into(),
+        Err(e) => json!({ "error": format!("{:?}", e) }),
+    }
+}
+
+fn debug<T: std::fmt::Debug>(result: Result<T, NvmlError>) -> Value {
+    reading(result.map(|value| format!("{:?}", value)))
+}
+
+fn clocks(device: &Device) -> Value {
+    let clocks: Map<_, _> = CLOCKS
+        .iter()
+        .map(|&(clock, name)| {
+            let mut readings: Map<_, _> = CLOCK_IDS
+                .iter()
+                .map(|&(id, id_name)| (id_name.to_string(), reading(device.clock(clock, id))))
+                .collect();
+            readings.insert("max".to_string(), reading(device.max_clock_info(clock)));
+            readings.insert(
+                "maxCustomerBoost".to_string(),
+                reading(device.max_customer_boost_clock(clock)),
+            );
+            (name.to_string(), readings.into())
+        })
+        .collect();
+    clocks.into()
+}
+
+/// Clock ranges and offset limits of every supported performance state
+fn pstates(device: &Device) -> Value {
+    let pstates = match device.supported_performance_states() {
+        Ok(pstates) => pstates,
+        Err(e) => return reading(Err::<u32, _>(e)),
+    };
+    let pstates: Map<_, _> = pstates
+        .into_iter()
+        .map(|pstate| {
+            let clocks: Map<_, _> = [(Clock::Graphics, "graphics"), (Clock::Memory, "memory")]
+                .iter()
+                .map(|&(clock, name)| {
+                    let range = device
+                        .min_max_clock_of_pstate(clock, pstate)
+                        .map(|(min, max)| json!({ "minMhz": min, "maxMhz": max }));
+                    let offset = device.clock_offset(clock, pstate).map(|offset| {
+                        json!({
+                            "offsetMhz": offset.clock_offset_mhz,
+                            "minOffsetMhz": offset.min_clock_offset_mhz,
+                            "maxOffsetMhz": offset.max_clock_offset_mhz,
+                        })
+                    });
+                    let value = json!({ "range": reading(range), "offset": reading(offset) });
+                    (name.to_string(), value)
+                })
+                .collect();
+            (pstate_name(pstate), clocks.into())
+        })
+        .collect();
+    pstates.into()
+}
+
+/// Graphics clocks supported with each memory clock, which can be hundreds
+fn supported_clocks(device: &Device) -> Value {
+    let memory_clocks = match device.supported_memory_clocks() {
+        Ok(clocks) => clocks,
+        Err(e) => return reading(Err::<u32, _>(e)),
+    };
+    let clocks: Map<_, _> = memory_clocks
+        .into_iter()
+        .map(|memory| {
+            let graphics = device.supported_graphics_clocks(memory);
+            (memory.to_string(), reading(graphics))
+        })
+        .collect();
+    clocks.into()
+}
+
+fn fans(device: &Device) -> Value {
+    let count = match device.num_fans() {
+        Ok(count) => count,
+        Err(e) => return reading(Err::<u32, _>(e)),
+    };
+    (0..count)
+        .map(|fan| {
+            json!({
+                "speedPercent": reading(device.fan_speed(fan)),
+                "rpm": reading(device.fan_speed_rpm(fan)),
+                "policy": debug(device.fan_control_policy(fan)),
+            })
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+fn ecc_errors(device: &Device) -> Value {
+    let mut errors = Map::new();
+    for (error, error_name) in [
+        (MemoryError::Corrected, "corrected"),
+        (MemoryError::Uncorrected, "uncorrected"),
+    ] {
+        for (counter, counter_name) in [
+            (EccCounter::Volatile, "Volatile"),
+            (EccCounter::Aggregate, "Aggregate"),
+        ] {
+            let total = device.total_ecc_errors(error, counter);
+            errors.insert(format!("{}{}", error_name, counter_name), reading(total));
+        }
+    }
+    errors.into()
+}
+
+fn device_dump(index: u32, device: &Device) -> Value {
+    let pci = device.pci_info().map(|pci| {
+        json!({
+            "busId": pci.bus_id,
+            "deviceId": format!("{:#010x}", pci.pci_device_id),
+            "subsystemId": pci.pci_sub_system_id.map(|id| format!("{:#010x}", id)),
+        })
+    });
+    let constraints = device
+        .power_management_limit_constraints()
+        .map(|c| json!({ "minMw": c.min_limit, "maxMw": c.max_limit }));
+    let memory = device.memory_info().map(|memory| {
+        json!({
+            "totalBytes": memory.total,
+            "usedBytes": memory.used,
+            "freeBytes": memory.free,
+            "reservedBytes": memory.reserved,
+        })
+    });
+    let utilization = device
+        .utilization_rates()
+        .map(|u| json!({ "gpuPercent": u.gpu, "memoryPercent": u.memory }));
+    let thresholds: Map<_, _> = THRESHOLDS
+        .iter()
+        .map(|&(threshold, name)| {
+            let value = device.temperature_threshold(threshold);
+            (name.to_string(), reading(value))
+        })
+        .collect();
+    let violations: Map<_, _> = violation_times(device)
+        .into_iter()
+        .map(|(name, time)| {
+            let value = json!({
+                "referenceTimeUs": time.reference_time,
+                "violationTimeNs": time.violation_time,
+            });
+            (name.to_string(), value)
+        })
+        .collect();
+    let ecc = device
+        .is_ecc_enabled()
+        .map(|ecc| json!({ "current": ecc.currently_enabled, "pending": ecc.pending_enabled }));
+
+    json!({
+        "index": index,
+        "identity": {
+            "name": reading(device.name()),
+            "uuid": reading(device.uuid()),
+            "serial": reading(device.serial()),
+            "boardPartNumber": reading(device.board_part_number()),
+            "boardId": reading(device.board_id()),
+            "vbiosVersion": reading(device.vbios_version()),
+            "infoRomImageVersion": reading(device.info_rom_image_version()),
+            "architecture": debug(device.architecture()),
+            "brand": debug(device.brand()),
+            "minorNumber": reading(device.minor_number()),
+            "cudaCores": reading(device.num_cores()),
+            "memoryBusWidth": reading(device.memory_bus_width()),
+            "multiGpuBoard": reading(device.is_multi_gpu_board()),
+        },
+        "pci": reading(pci),
+        "pcie": {
+            "linkGen": reading(device.current_pcie_link_gen()),
+            "maxLinkGen": reading(device.max_pcie_link_gen()),
+            "linkWidth": reading(device.current_pcie_link_width()),
+            "maxLinkWidth": reading(device.max_pcie_link_width()),
+            "maxLinkSpeed": debug(device.max_pcie_link_speed()),
+            "replayCounter": reading(device.pcie_replay_counter()),
+            "txKbPerSecond": reading(device.pcie_throughput(PcieUtilCounter::Send)),
+            "rxKbPerSecond": reading(device.pcie_throughput(PcieUtilCounter::Receive)),
+        },
+        "power": {
+            "usageMw": reading(device.power_usage()),
+            "limitMw": reading(device.power_management_limit()),
+            "enforcedLimitMw": reading(device.enforced_power_limit()),
+            "defaultLimitMw": reading(device.power_management_limit_default()),
+            "constraints": reading(constraints),
+            "totalEnergyMj": reading(device.total_energy_consumption()),
+            "powerSource": debug(device.power_source()),
+        },
+        "performanceState": reading(device.performance_state().map(pstate_name)),
+        "clocks": clocks(device),
+        "clockOffsets": {
+            "coreMhz": reading(device.gpc_clock_vf_offset()),
+            "memoryMhz": reading(device.mem_clock_vf_offset()),
+        },
+        "pstates": pstates(device),
+        "supportedClocksMhz": supported_clocks(device),
+        "autoBoostedClocks": debug(device.auto_boosted_clocks_enabled()),
+        "temperatureC": reading(device.temperature(TemperatureSensor::Gpu)),
+        "temperatureThresholdsC": thresholds,
+        "fans": fans(device),
+        "fanSpeedRange": reading(device.min_max_fan_speed().map(|(min, max)| json!([min, max]))),
+        "memory": reading(memory),
+        "utilization": reading(utilization),
+        "encoderUtilization": debug(device.encoder_utilization()),
+        "decoderUtilization": debug(device.decoder_utilization()),
+        "throttleReasons": reading(device.current_throttle_reasons().map(throttle_reason_names)),
+        "supportedThrottleReasons": reading(
+            device.supported_throttle_reasons().map(throttle_reason_names)
+        ),
+        "violations": violations,
+        "ecc": reading(ecc),
+        "eccErrors": ecc_errors(device),
+        "retiredPagesPending": reading(device.are_pages_pending_retired()),
+        "persistenceMode": reading(device.is_in_persistent_mode()),
+        "computeMode": debug(device.compute_mode()),
+        "displayActive": reading(device.is_display_active()),
+        "displayConnected": reading(device.is_display_connected()),
+        "migMode": debug(device.mig_mode()),
+        "accountingEnabled": reading(device.is_accounting_enabled()),
+        "computeProcesses": reading(device.running_compute_processes_count()),
+        "graphicsProcesses": reading(device.running_graphics_processes_count()),
+    })
+}
+
+/// Prints everything NVML reports about the GPUs, every reading with its
+/// value or the error NVML returned, for bug reports. JSON unless
+/// `--format yaml` is given.
+pub fn run(nvml: &Nvml, index: Option<u32>, format: OutputFormat) {
+    let indices = match index {
+        Some(index) => vec![index],
+        None => (0..nvml.device_count().expect("Failed to get GPU count")).collect(),
+    };
+    let gpus: Vec<Value> = indices
+        .into_iter()
+        .map(|index| {
+            let device = nvml.device_by_index(index).expect("Failed to get GPU");
+            device_dump(index, &device)
+        })
+        .collect();
+    let report = json!({
+        "driverVersion": reading(nvml.sys_driver_version()),
+        "nvmlVersion": reading(nvml.sys_nvml_version()),
+        "cudaDriverVersion": reading(nvml.sys_cuda_driver_version()),
+        "gpus": gpus,
+    });
+    let format = match format {
+        OutputFormat::Text => OutputFormat::Json,
+        format => format,
+    };
+    output::print(format, &report);
+}
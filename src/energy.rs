@@ -0,0 +1,107 @@
+use nvml_wrapper::{Device, Nvml};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Tracks the energy a single GPU consumed since the meter was created.
+///
+/// Uses the hardware energy counter (Volta and newer) when available and
+/// falls back to integrating the instantaneous power draw between samples.
+pub struct EnergyMeter {
+    counter_start: Option<u64>,
+    integrated_mj: f64,
+    last_sample: Instant,
+    started: Instant,
+}
+
+impl EnergyMeter {
+    pub fn new(device: &Device) -> Self {
+        let now = Instant::now();
+        Self {
+            counter_start: device.total_energy_consumption().ok(),
+            integrated_mj: 0.0,
+            last_sample: now,
+            started: now,
+        }
+    }
+
+    /// Whether the meter reads the hardware counter rather than estimating
+    pub fn uses_counter(&self) -> bool {
+        self.counter_start.is_some()
+    }
+
+    /// Takes a sample and returns the energy consumed so far in joules
+    pub fn sample(&mut self, device: &Device) -> f64 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_sample).as_secs_f64();
+        self.last_sample = now;
+
+        if let Some(start) = self.counter_start {
+            if let Ok(total) = device.total_energy_consumption() {
+                return total.saturating_sub(start) as f64 / 1000.0;
+            }
+        }
+
+        if let Ok(power) = device.power_usage() {
+            self.integrated_mj += power as f64 * elapsed;
+        }
+        self.integrated_mj / 1000.0
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.last_sample.duration_since(self.started)
+    }
+}
+
+/// Samples energy consumption every `interval` and prints consumed energy and
+/// the projected kWh per day and week, until `duration` elapses (or forever).
+pub fn run(nvml: &Nvml, indices: &[u32], interval: Duration, duration: Option<Duration>) {
+    let devices: Vec<(u32, Device)> = indices
+        .iter()
+        .map(|&index| {
+            (
+                index,
+                nvml.device_by_index(index).expect("Failed to get GPU"),
+            )
+        })
+        .collect();
+    let mut meters: Vec<EnergyMeter> = devices
+        .iter()
+        .map(|(_, device)| EnergyMeter::new(device))
+        .collect();
+
+    for ((index, _), meter) in devices.iter().zip(&meters) {
+        let source = if meter.uses_counter() {
+            "energy counter"
+        } else {
+            "integrated power draw"
+        };
+        println!("GPU {}: measuring via {}", index, source);
+    }
+
+    let started = Instant::now();
+    loop {
+        thread::sleep(interval);
+
+        for ((index, device), meter) in devices.iter().zip(meters.iter_mut()) {
+            let joules = meter.sample(device);
+            let seconds = meter.elapsed().as_secs_f64().max(f64::EPSILON);
+            let avg_watts = joules / seconds;
+            let kwh_per_day = avg_watts * 24.0 / 1000.0;
+            println!(
+                "GPU {}: {:.4} kWh in {:.0}s, avg {:.1} W, projected {:.2} kWh/day, {:.2} kWh/week",
+                index,
+                joules / 3_600_000.0,
+                seconds,
+                avg_watts,
+                kwh_per_day,
+                kwh_per_day * 7.0
+            );
+        }
+
+        if duration.is_some_and(|duration| started.elapsed() >= duration) {
+            break;
+        }
+    }
+}
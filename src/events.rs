@@ -0,0 +1,162 @@
+use nvml_wrapper::bitmasks::event::EventTypes;
+use nvml_wrapper::enum_wrappers::device::{Clock, PerformanceState};
+use nvml_wrapper::enums::event::XidError;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::{Device, EventSet, Nvml};
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+/// Event types worth reporting, each GPU is subscribed to the subset it supports
+fn wanted_events() -> EventTypes {
+    EventTypes::CRITICAL_XID_ERROR
+        | EventTypes::CLOCK_CHANGE
+        | EventTypes::PSTATE_CHANGE
+        | EventTypes::POWER_SOURCE_CHANGE
+        | EventTypes::SINGLE_BIT_ECC_ERROR
+        | EventTypes::DOUBLE_BIT_ECC_ERROR
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    /// Critical Xid error, e.g. 79 when the GPU has fallen off the bus. NVML
+    /// only keeps the most recent code, so bursts all report the last one.
+    Xid(Option<u64>),
+    /// Graphics clock at the time the event was handled
+    ClockChange(Option<u32>),
+    PstateChange(Option<PerformanceState>),
+    /// Switch between AC and battery power
+    PowerSourceChange,
+    EccError {
+        double_bit: bool,
+    },
+}
+
+/// An NVML event on one GPU
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub uuid: String,
+    pub kind: EventKind,
+}
+
+impl Event {
+    fn from_data(device: &Device, event_type: EventTypes, xid: Option<XidError>) -> Event {
+        let kind = if event_type.contains(EventTypes::CRITICAL_XID_ERROR) {
+            EventKind::Xid(match xid {
+                Some(XidError::Value(code)) => Some(code),
+                _ => None,
+            })
+        } else if event_type.contains(EventTypes::DOUBLE_BIT_ECC_ERROR) {
+            EventKind::EccError { double_bit: true }
+        } else if event_type.contains(EventTypes::SINGLE_BIT_ECC_ERROR) {
+            EventKind::EccError { double_bit: false }
+        } else if event_type.contains(EventTypes::POWER_SOURCE_CHANGE) {
+            EventKind::PowerSourceChange
+        } else if event_type.contains(EventTypes::PSTATE_CHANGE) {
+            EventKind::PstateChange(device.performance_state().ok())
+        } else {
+            EventKind::ClockChange(device.clock_info(Clock::Graphics).ok())
+        };
+
+        Event {
+            uuid: device.uuid().unwrap_or_else(|_| "unknown".to_string()),
+            kind,
+        }
+    }
+}
+
+/// One `key=value` log line per event, e.g. `event=xid gpu=GPU-... code=79`
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            EventKind::Xid(code) => {
+                write!(f, "event=xid gpu={}", self.uuid)?;
+                match code {
+                    Some(code) => write!(f, " code={}", code),
+                    None => write!(f, " code=unknown"),
+                }
+            }
+            EventKind::ClockChange(clock) => {
+                write!(f, "event=clock_change gpu={}", self.uuid)?;
+                match clock {
+                    Some(clock) => write!(f, " graphics_mhz={}", clock),
+                    None => Ok(()),
+                }
+            }
+            EventKind::PstateChange(pstate) => {
+                write!(f, "event=pstate_change gpu={}", self.uuid)?;
+                match pstate {
+                    Some(pstate) => write!(f, " pstate={:?}", pstate),
+                    None => Ok(()),
+                }
+            }
+            EventKind::PowerSourceChange => {
+                write!(f, "event=power_source_change gpu={}", self.uuid)
+            }
+            EventKind::EccError { double_bit } => write!(
+                f,
+                "event=ecc_error gpu={} bits={}",
+                self.uuid,
+                if *double_bit { "double" } else { "single" }
+            ),
+        }
+    }
+}
+
+/// NVML event subscription for a set of GPUs, for use in place of sleeping
+/// between polls: [`EventMonitor::wait`] returns after the same timeout but
+/// hands over whatever happened in the meantime.
+pub struct EventMonitor<'nvml> {
+    set: EventSet<'nvml>,
+}
+
+impl<'nvml> EventMonitor<'nvml> {
+    /// Subscribes to every supported event of interest on each GPU. Fails if
+    /// any registration fails, NVML frees the whole set in that case.
+    pub fn new(nvml: &'nvml Nvml, devices: &[Device<'nvml>]) -> Result<Self, NvmlError> {
+        let mut set = nvml.create_event_set()?;
+        for device in devices {
+            let supported = device.supported_event_types()?;
+            let events = supported & wanted_events();
+            if events.is_empty() {
+                continue;
+            }
+            set = device.register_events(events, set).map_err(|e| e.error)?;
+        }
+        Ok(EventMonitor { set })
+    }
+
+    /// Subscribes to every GPU NVML can see
+    pub fn all(nvml: &'nvml Nvml) -> Result<Self, NvmlError> {
+        let devices = (0..nvml.device_count()?)
+            .map(|index| nvml.device_by_index(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(nvml, &devices)
+    }
+
+    /// Blocks for `timeout`, calling `on_event` for each event that arrives
+    pub fn wait(&self, timeout: Duration, mut on_event: impl FnMut(&Event)) {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return;
+            }
+            match self.set.wait(remaining.as_millis() as u32) {
+                Ok(data) => on_event(&Event::from_data(
+                    &data.device,
+                    data.event_type,
+                    data.event_data,
+                )),
+                // Also returned when a signal interrupted the wait
+                Err(NvmlError::Timeout) => continue,
+                Err(e) => {
+                    eprintln!("Failed to wait for GPU events: {:?}", e);
+                    thread::sleep(remaining);
+                    return;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::config::{Config, ConfigKey};
+use crate::fan::{FanPolicy, FanSpeeds};
+use crate::legacy::{GRAPHICS_CLOCK_OFFSET, MEMORY_TRANSFER_RATE_OFFSET};
+use crate::offset::Offset;
+use crate::power::PowerLimit;
+use crate::{ComputeModeArg, Sets};
+use clap::ValueEnum;
+use nvml_wrapper::Nvml;
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ExportFormat {
+    /// Shell commands for nvidia-smi and nvidia-settings
+    Commands,
+}
+
+/// nvidia-settings only knows GPUs by index, so a UUID key is looked up on
+/// this machine if NVML is available
+fn settings_index(key: &ConfigKey, nvml: Option<&Nvml>) -> Option<u32> {
+    match key {
+        ConfigKey::Index(index) => Some(*index),
+        ConfigKey::Uuid(uuid) => nvml?.device_by_uuid(uuid.as_str()).ok()?.index().ok(),
+        // Name globs and the defaults are expanded to indices before this
+        ConfigKey::Name(_) | ConfigKey::Defaults => None,
+    }
+}
+
+/// The nvidia-smi and nvidia-settings commands reproducing one GPU's
+/// settings, with comments for the ones that have no equivalent
+fn commands(key: &ConfigKey, sets: &Sets, nvml: Option<&Nvml>) -> Vec<String> {
+    let smi = |args: String| format!("nvidia-smi -i {} {}", key, args);
+    let mut lines = Vec::new();
+
+    match sets.power_limit {
+        Some(PowerLimit::Milliwatts(limit)) => lines.push(smi(format!("-pl {}", limit / 1000))),
+        Some(PowerLimit::Relative(_)) => {}
+        Some(limit @ PowerLimit::Percent(_)) => {
+            // nvidia-smi only takes watts, resolve against this GPU's default
+            let resolved = nvml
+                .and_then(|nvml| key.device(nvml).ok())
+                .and_then(|device| limit.resolve(&device).ok());
+            match resolved {
+                Some(watts) => lines.push(smi(format!("-pl {}", watts / 1000))),
+                None => lines.push(format!(
+                    "# GPU {}: power limit {} of the default, which couldn't be read",
+                    key, limit
+                )),
+            }
+        }
+        None => {}
+    }
+    if let (Some(min), Some(max)) = (sets.min_clock, sets.max_clock) {
+        lines.push(smi(format!("-lgc {},{}", min, max)));
+    }
+    if let (Some(min), Some(max)) = (sets.min_mem_clock, sets.max_mem_clock) {
+        lines.push(smi(format!("-lmc {},{}", min, max)));
+    }
+    if let Some(temp_limit) = sets.temp_limit {
+        lines.push(smi(format!("-gtt {}", temp_limit)));
+    }
+    if let Some(ecc) = sets.ecc {
+        lines.push(smi(format!("-e {}", ecc.enabled() as u8)));
+    }
+    if let Some(mig) = sets.mig {
+        lines.push(smi(format!("-mig {}", mig.enabled() as u8)));
+    }
+    if let Some(mode) = sets.compute_mode {
+        let mode = match mode {
+            ComputeModeArg::Default => "DEFAULT",
+            ComputeModeArg::ExclusiveProcess => "EXCLUSIVE_PROCESS",
+            ComputeModeArg::Prohibited => "PROHIBITED",
+        };
+        lines.push(smi(format!("-c {}", mode)));
+    }
+
+    let index = settings_index(key, nvml);
+    let gpu = index.map(|index| index.to_string()).unwrap_or_default();
+    let mut assignments = Vec::new();
+    if let Some(Offset::Absolute(offset)) = sets.freq_offset {
+        assignments.push(format!(
+            "[gpu:{}]/{}={}",
+            gpu, GRAPHICS_CLOCK_OFFSET, offset
+        ));
+    }
+    if let Some(Offset::Absolute(offset)) = sets.mem_offset {
+        // The X driver takes the offset as a transfer rate, twice the clock
+        assignments.push(format!(
+            "[gpu:{}]/{}={}",
+            gpu,
+            MEMORY_TRANSFER_RATE_OFFSET,
+            offset * 2
+        ));
+    }
+    if let Some(FanSpeeds(speeds)) = &sets.fan {
+        assignments.push(format!("[gpu:{}]/GPUFanControlState=1", gpu));
+        // nvidia-settings numbers fans across all GPUs, this assumes the
+        // GPU's fans come first
+        for (fan, speed) in speeds {
+            assignments.push(format!("[fan:{}]/GPUTargetFanSpeed={}", fan, speed));
+        }
+    }
+    if let Some(policy) = sets.fan_policy {
+        let state = match policy {
+            FanPolicy::Auto => 0,
+            FanPolicy::Manual => 1,
+        };
+        assignments.push(format!("[gpu:{}]/GPUFanControlState={}", gpu, state));
+    }
+    if !assignments.is_empty() {
+        match index {
+            Some(_) => lines.push(format!(
+                "nvidia-settings {}",
+                assignments
+                    .iter()
+                    .map(|a| format!("-a '{}'", a))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )),
+            None => lines.push(format!(
+                "# GPU {} is not present here, its offsets and fan speeds need nvidia-settings with the GPU index",
+                key
+            )),
+        }
+    }
+
+    let relative = |offset: Option<Offset>| matches!(offset, Some(Offset::Relative(_)));
+    let unsupported = [
+        ("A relative freqOffset", relative(sets.freq_offset)),
+        ("A relative memOffset", relative(sets.mem_offset)),
+        (
+            "A relative powerLimit",
+            matches!(sets.power_limit, Some(PowerLimit::Relative(_))),
+        ),
+        ("freqOffsetPstate", sets.freq_offset_pstate.is_some()),
+        ("fanCurve", sets.fan_curve.is_some()),
+        ("alerts", sets.alerts.is_some()),
+        ("powerGovernor", sets.power_governor.is_some()),
+        ("idleProfile", sets.idle_profile.is_some()),
+        ("battery", sets.battery.is_some()),
+    ];
+    for (name, _) in unsupported.iter().filter(|(_, set)| *set) {
+        lines.push(format!(
+            "# {} has no nvidia-smi or nvidia-settings equivalent",
+            name
+        ));
+    }
+    lines
+}
+
+/// Indices of the GPUs that get their settings from an entry, a more
+/// specific entry for the same GPU takes precedence
+fn entry_indices(config: &Config, key: &ConfigKey, nvml: &Nvml) -> Vec<u32> {
+    let count = nvml.device_count().unwrap_or(0);
+    (0..count)
+        .filter(|index| {
+            let Ok(device) = nvml.device_by_index(*index) else {
+                return false;
+            };
+            let uuid = device.uuid().unwrap_or_default();
+            let name = device.name().unwrap_or_default();
+            config
+                .entry(*index, &uuid, &name)
+                .is_some_and(|(entry, _)| entry == key)
+        })
+        .collect()
+}
+
+/// Prints the config as commands that reproduce it without this tool.
+/// nvidia-settings needs a running X server with Coolbits enabled.
+pub fn run(config: &Config, format: ExportFormat) {
+    let nvml = Nvml::init().ok();
+    match format {
+        ExportFormat::Commands => {
+            println!("#!/bin/sh");
+            let mut keys: Vec<&ConfigKey> = config.sets.keys().collect();
+            keys.sort_by_key(|key| key.to_string());
+            for key in keys {
+                let sets = &config.sets[key];
+                let (ConfigKey::Name(_) | ConfigKey::Defaults) = key else {
+                    println!();
+                    println!("# GPU {}", key);
+                    for line in commands(key, sets, nvml.as_ref()) {
+                        println!("{}", line);
+                    }
+                    continue;
+                };
+
+                // Neither tool selects GPUs by name, so this needs the GPUs
+                // the entry applies to on this machine
+                let indices = nvml.as_ref().map(|nvml| entry_indices(config, key, nvml));
+                match indices {
+                    Some(indices) if !indices.is_empty() => {
+                        for index in indices {
+                            println!();
+                            println!("# GPU {} ({})", index, key);
+                            for line in commands(&ConfigKey::Index(index), sets, nvml.as_ref()) {
+                                println!("{}", line);
+                            }
+                        }
+                    }
+                    _ => {
+                        println!();
+                        println!("# No GPU here uses {}, its settings are skipped", key);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use clap::ValueEnum;
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::enums::device::FanControlPolicy;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Device;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Who drives the fans: the driver's temperature curve or fixed speeds
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FanPolicy {
+    Auto,
+    Manual,
+}
+
+impl FanPolicy {
+    pub fn read(device: &Device, fan: u32) -> Result<FanPolicy, NvmlError> {
+        Ok(match device.fan_control_policy(fan)? {
+            FanControlPolicy::TemperatureContinousSw => FanPolicy::Auto,
+            FanControlPolicy::Manual => FanPolicy::Manual,
+        })
+    }
+
+    /// Switching to auto also drops any fixed speed, so the driver's curve
+    /// takes over right away
+    pub fn apply(self, device: &mut Device, fan: u32) -> Result<(), NvmlError> {
+        match self {
+            FanPolicy::Auto => {
+                device.set_default_fan_speed(fan)?;
+                device.set_fan_control_policy(fan, FanControlPolicy::TemperatureContinousSw)
+            }
+            FanPolicy::Manual => device.set_fan_control_policy(fan, FanControlPolicy::Manual),
+        }
+    }
+}
+
+/// Switches the fan to manual control before setting a fixed speed, some
+/// drivers ignore the speed while the fan is still on the auto policy
+pub fn set_manual_speed(device: &mut Device, fan: u32, speed: u32) -> Result<(), NvmlError> {
+    FanPolicy::Manual.apply(device, fan)?;
+    device.set_fan_speed(fan, speed)
+}
+
+/// Fixed fan speeds keyed by fan index.
+///
+/// Parsed from `0:40,1:60` on the command line and from a `{"0": 40, "1": 60}`
+/// object in the config file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(from = "BTreeMap<u32, u32>", into = "BTreeMap<u32, u32>")]
+pub struct FanSpeeds(pub Vec<(u32, u32)>);
+
+impl FromStr for FanSpeeds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|entry| {
+                let (fan, speed) = entry
+                    .split_once(':')
+                    .ok_or_else(|| format!("invalid entry `{}`, expected <fan>:<speed>", entry))?;
+                let fan = fan
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|e| format!("invalid fan index `{}`: {}", fan, e))?;
+                let speed = speed
+                    .trim()
+                    .trim_end_matches('%')
+                    .parse::<u32>()
+                    .map_err(|e| format!("invalid fan speed `{}`: {}", speed, e))?;
+                Ok((fan, speed))
+            })
+            .collect::<Result<_, _>>()
+            .map(FanSpeeds)
+    }
+}
+
+impl From<FanSpeeds> for BTreeMap<u32, u32> {
+    fn from(speeds: FanSpeeds) -> Self {
+        speeds.0.into_iter().collect()
+    }
+}
+
+impl From<BTreeMap<u32, u32>> for FanSpeeds {
+    fn from(map: BTreeMap<u32, u32>) -> Self {
+        FanSpeeds(map.into_iter().collect())
+    }
+}
+
+/// The `fanCurve` config entry: either one curve shared by every fan of the
+/// GPU, or separate curves keyed by fan index
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum FanCurves {
+    All(FanCurve),
+    PerFan(PerFanCurves),
+}
+
+impl FanCurves {
+    pub fn into_controllers(self) -> Vec<FanController> {
+        match self {
+            FanCurves::All(curve) => vec![FanController::new(curve, None)],
+            FanCurves::PerFan(PerFanCurves(curves)) => curves
+                .into_iter()
+                .map(|(fan, curve)| FanController::new(curve, Some(fan)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(
+    try_from = "BTreeMap<String, FanCurve>",
+    into = "BTreeMap<String, FanCurve>"
+)]
+pub struct PerFanCurves(pub Vec<(u32, FanCurve)>);
+
+impl From<PerFanCurves> for BTreeMap<String, FanCurve> {
+    fn from(curves: PerFanCurves) -> Self {
+        curves
+            .0
+            .into_iter()
+            .map(|(fan, curve)| (fan.to_string(), curve))
+            .collect()
+    }
+}
+
+impl TryFrom<BTreeMap<String, FanCurve>> for PerFanCurves {
+    type Error = String;
+
+    fn try_from(map: BTreeMap<String, FanCurve>) -> Result<Self, Self::Error> {
+        map.into_iter()
+            .map(|(fan, curve)| {
+                let fan = fan
+                    .parse::<u32>()
+                    .map_err(|e| format!("invalid fan index `{}`: {}", fan, e))?;
+                Ok((fan, curve))
+            })
+            .collect::<Result<_, _>>()
+            .map(PerFanCurves)
+    }
+}
+
+/// Temperature to fan speed mapping, configured per GPU under `fanCurve`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FanCurve {
+    /// `[temperature °C, fan speed %]` pairs, interpolated linearly
+    pub points: Vec<(u32, u32)>,
+    /// Degrees the temperature has to drop before the fans slow down again
+    #[serde(default)]
+    pub hysteresis: u32,
+    /// Stop the fans entirely below this temperature
+    pub zero_rpm_below: Option<u32>,
+}
+
+impl FanCurve {
+    /// Fan speed the curve asks for at `temp`, clamped to the end points
+    pub fn speed_at(&self, temp: u32) -> u32 {
+        let mut points = self.points.clone();
+        points.sort_by_key(|&(t, _)| t);
+
+        let (Some(&(first_temp, first_speed)), Some(&(last_temp, last_speed))) =
+            (points.first(), points.last())
+        else {
+            return 0;
+        };
+        if temp <= first_temp {
+            return first_speed;
+        }
+        if temp >= last_temp {
+            return last_speed;
+        }
+
+        for pair in points.windows(2) {
+            let ((t0, s0), (t1, s1)) = (pair[0], pair[1]);
+            if temp >= t0 && temp <= t1 && t1 > t0 {
+                let ratio = (temp - t0) as f32 / (t1 - t0) as f32;
+                return (s0 as f32 + (s1 as f32 - s0 as f32) * ratio).round() as u32;
+            }
+        }
+        last_speed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FanState {
+    Unset,
+    Stopped,
+    Running(u32),
+}
+
+/// Drives one fan (or all fans) of a GPU along a [`FanCurve`], remembering
+/// the last applied speed so hysteresis can be honoured and unchanged speeds
+/// aren't rewritten.
+pub struct FanController {
+    curve: FanCurve,
+    fan: Option<u32>,
+    state: FanState,
+}
+
+impl FanController {
+    pub fn new(curve: FanCurve, fan: Option<u32>) -> Self {
+        Self {
+            curve,
+            fan,
+            state: FanState::Unset,
+        }
+    }
+
+    fn next_state(&self, temp: u32) -> FanState {
+        let hysteresis = self.curve.hysteresis;
+
+        if let Some(zero_rpm_below) = self.curve.zero_rpm_below {
+            match self.state {
+                // Only spin up once we are clearly above the zero RPM band
+                FanState::Stopped if temp < zero_rpm_below + hysteresis => {
+                    return FanState::Stopped
+                }
+                FanState::Stopped => {}
+                _ if temp < zero_rpm_below => return FanState::Stopped,
+                _ => {}
+            }
+        }
+
+        let rising = self.curve.speed_at(temp);
+        match self.state {
+            FanState::Running(current) if rising <= current => {
+                // Slow down only once the temperature has dropped by the
+                // hysteresis, i.e. as if it were `hysteresis` degrees hotter
+                let falling = self.curve.speed_at(temp + hysteresis);
+                FanState::Running(falling.min(current))
+            }
+            _ => FanState::Running(rising),
+        }
+    }
+
+    /// Reads the GPU temperature and adjusts the fan(s) if the curve demands it
+    pub fn update(&mut self, device: &mut Device) -> Result<(), NvmlError> {
+        let temp = device.temperature(TemperatureSensor::Gpu)?;
+        let next = self.next_state(temp);
+        if next == self.state {
+            return Ok(());
+        }
+
+        let (min_speed, max_speed) = device.min_max_fan_speed()?;
+        let fans = match self.fan {
+            Some(fan) => fan..fan + 1,
+            None => 0..device.num_fans()?,
+        };
+        for fan in fans {
+            match next {
+                // Boards that can't be driven to 0% manually idle their fans
+                // themselves once control is handed back to the driver
+                FanState::Stopped if min_speed > 0 => FanPolicy::Auto.apply(device, fan)?,
+                FanState::Stopped => set_manual_speed(device, fan, 0)?,
+                FanState::Running(speed) => {
+                    set_manual_speed(device, fan, speed.clamp(min_speed, max_speed))?
+                }
+                FanState::Unset => {}
+            }
+        }
+        self.state = next;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn curve() -> FanCurve {
+        FanCurve {
+            points: vec![(80, 100), (40, 30)],
+            hysteresis: 4,
+            zero_rpm_below: Some(35),
+        }
+    }
+
+    #[test]
+    fn parses_a_curve() {
+        let curve: FanCurve =
+            serde_json::from_str(r#"{"points": [[40, 30], [80, 100]], "zeroRpmBelow": 35}"#)
+                .unwrap();
+        assert_eq!(curve.points, vec![(40, 30), (80, 100)]);
+        assert_eq!(curve.hysteresis, 0);
+        assert_eq!(curve.zero_rpm_below, Some(35));
+    }
+
+    #[test]
+    fn rejects_invalid_curves() {
+        for invalid in [
+            r#"{}"#,
+            r#"{"points": [[40, 30, 1]]}"#,
+            r#"{"points": [[-40, 30]]}"#,
+            r#"{"points": [[40, 30]], "hysteresis": -1}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<FanCurve>(invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn parses_fan_speeds() {
+        let speeds: FanSpeeds = "0:40,1:60%, 2 : 100".parse().unwrap();
+        assert_eq!(speeds.0, vec![(0, 40), (1, 60), (2, 100)]);
+    }
+
+    #[test]
+    fn rejects_invalid_fan_speeds() {
+        for invalid in [
+            "",
+            "40",
+            "0=40",
+            "0:",
+            "a:40",
+            "0:-10",
+            "-1:40",
+            "0:40,",
+            "0:4294967296",
+            "4294967296:40",
+        ] {
+            assert!(invalid.parse::<FanSpeeds>().is_err(), "{}", invalid);
+        }
+        assert!("4294967295:0".parse::<FanSpeeds>().is_ok());
+    }
+
+    #[test]
+    fn parses_fan_speeds_from_the_config_file() {
+        let speeds: FanSpeeds = serde_json::from_value(json!({ "1": 60, "0": 40 })).unwrap();
+        assert_eq!(speeds.0, vec![(0, 40), (1, 60)]);
+        assert!(serde_json::from_value::<FanSpeeds>(json!({ "a": 40 })).is_err());
+        assert!(serde_json::from_value::<FanSpeeds>(json!({ "0": -1 })).is_err());
+    }
+
+    #[test]
+    fn parses_one_curve_for_every_fan() {
+        let curves: FanCurves =
+            serde_json::from_value(json!({ "points": [[40, 30], [80, 100]], "hysteresis": 3 }))
+                .unwrap();
+        let FanCurves::All(curve) = curves else {
+            panic!("expected a curve for every fan, got {:?}", curves);
+        };
+        assert_eq!(curve.points, vec![(40, 30), (80, 100)]);
+        assert_eq!(curve.hysteresis, 3);
+        assert_eq!(curve.zero_rpm_below, None);
+    }
+
+    #[test]
+    fn parses_per_fan_curves() {
+        let curves: FanCurves = serde_json::from_value(json!({
+            "1": { "points": [[50, 40]] },
+            "0": { "points": [[40, 30]], "zeroRpmBelow": 35 },
+        }))
+        .unwrap();
+        let FanCurves::PerFan(PerFanCurves(curves)) = curves else {
+            panic!("expected per-fan curves, got {:?}", curves);
+        };
+        let fans: Vec<u32> = curves.iter().map(|(fan, _)| *fan).collect();
+        assert_eq!(fans, vec![0, 1]);
+        assert_eq!(curves[0].1.zero_rpm_below, Some(35));
+    }
+
+    #[test]
+    fn rejects_invalid_per_fan_curves() {
+        for invalid in [
+            json!({ "a": { "points": [[40, 30]] } }),
+            json!({ "-1": { "points": [[40, 30]] } }),
+            json!({ "0": { "points": [[40, 30, 1]] } }),
+            json!({ "0": { "points": [[-40, 30]] } }),
+            json!({ "0": 40 }),
+        ] {
+            assert!(
+                serde_json::from_value::<FanCurves>(invalid.clone()).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn interpolates_between_curve_points() {
+        let curve = curve();
+        assert_eq!(curve.speed_at(20), 30);
+        assert_eq!(curve.speed_at(40), 30);
+        assert_eq!(curve.speed_at(60), 65);
+        assert_eq!(curve.speed_at(90), 100);
+        let empty = FanCurve {
+            points: Vec::new(),
+            ..curve
+        };
+        assert_eq!(empty.speed_at(60), 0);
+    }
+
+    #[test]
+    fn honours_hysteresis_and_the_zero_rpm_band() {
+        let mut controller = FanController::new(curve(), None);
+        assert_eq!(controller.next_state(30), FanState::Stopped);
+
+        controller.state = FanState::Stopped;
+        assert_eq!(controller.next_state(37), FanState::Stopped);
+        assert_eq!(controller.next_state(40), FanState::Running(30));
+
+        controller.state = FanState::Running(65);
+        assert_eq!(controller.next_state(58), FanState::Running(65));
+        assert_eq!(controller.next_state(55), FanState::Running(63));
+        assert_eq!(controller.next_state(70), FanState::Running(83));
+        assert_eq!(controller.next_state(34), FanState::Stopped);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::power;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Device;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+fn default_raise_above() -> u32 {
+    80
+}
+
+fn default_lower_below() -> u32 {
+    20
+}
+
+fn default_sustained_for() -> u64 {
+    10
+}
+
+fn default_step() -> u32 {
+    25
+}
+
+/// Utilization driven power limit, configured per GPU under `powerGovernor`
+/// and run by `nvidia_oc daemon`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerGovernor {
+    /// Lowest power limit in W the governor will set
+    pub min_watts: u32,
+    /// Highest power limit in W the governor will set
+    pub max_watts: u32,
+    /// GPU utilization in percent above which the limit is raised
+    #[serde(default = "default_raise_above")]
+    pub raise_above: u32,
+    /// GPU utilization in percent below which the limit is lowered
+    #[serde(default = "default_lower_below")]
+    pub lower_below: u32,
+    /// Seconds utilization has to stay above/below the threshold per step
+    #[serde(default = "default_sustained_for")]
+    pub sustained_for: u64,
+    /// Watts to raise or lower the limit by per step
+    #[serde(default = "default_step")]
+    pub step_watts: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Trend {
+    Busy,
+    Idle,
+    Steady,
+}
+
+/// Steps the power limit of one GPU between the bounds of a [`PowerGovernor`]
+/// whenever utilization stays high or low for long enough.
+pub struct GovernorState {
+    governor: PowerGovernor,
+    trend: Trend,
+    since: Instant,
+}
+
+impl GovernorState {
+    pub fn new(governor: PowerGovernor) -> Self {
+        Self {
+            governor,
+            trend: Trend::Steady,
+            since: Instant::now(),
+        }
+    }
+
+    pub fn update(&mut self, gpu: &str, device: &mut Device) -> Result<(), NvmlError> {
+        let utilization = device.utilization_rates()?.gpu;
+        let trend = if utilization > self.governor.raise_above {
+            Trend::Busy
+        } else if utilization < self.governor.lower_below {
+            Trend::Idle
+        } else {
+            Trend::Steady
+        };
+
+        if trend != self.trend {
+            self.trend = trend;
+            self.since = Instant::now();
+            return Ok(());
+        }
+        if trend == Trend::Steady
+            || self.since.elapsed() < Duration::from_secs(self.governor.sustained_for)
+        {
+            return Ok(());
+        }
+        // Every further sustained period is worth another step
+        self.since = Instant::now();
+
+        let constraints = device.power_management_limit_constraints()?;
+        let min_mw = (self.governor.min_watts * 1000).max(constraints.min_limit);
+        let max_mw = (self.governor.max_watts * 1000).min(constraints.max_limit);
+        let current = device.power_management_limit()?;
+        let step = self.governor.step_watts * 1000;
+        let target = match trend {
+            Trend::Busy => current.saturating_add(step),
+            _ => current.saturating_sub(step),
+        }
+        .clamp(min_mw.min(max_mw), max_mw);
+
+        if target != current {
+            device.set_power_management_limit(target)?;
+            println!(
+                "GPU {} at {}% utilization, power limit {} -> {}",
+                gpu,
+                utilization,
+                power::format_watts(current),
+                power::format_watts(target)
+            );
+        }
+        Ok(())
+    }
+}
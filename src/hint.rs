@@ -0,0 +1,67 @@
+use nvml_wrapper::error::NvmlError;
+
+/// What to do about an NVML error, for the errors users can fix themselves
+pub fn hint(e: &NvmlError) -> Option<&'static str> {
+    Some(match e {
+        NvmlError::NotSupported => {
+            "This GPU or driver doesn't allow the setting, `nvidia_oc caps` shows \
+             what it supports. Clock offsets need a Turing or newer GPU and driver \
+             555 or newer, older setups can try --legacy-fallback under X."
+        }
+        NvmlError::NoPermission => {
+            "Changing GPU settings needs root. Run it through the installed \
+             service, with sudo or pkexec, or without --no-escalate."
+        }
+        NvmlError::InvalidArg => {
+            "The value is out of range for this GPU, `nvidia_oc get` shows the \
+             allowed ranges."
+        }
+        NvmlError::InsufficientPower => {
+            "The GPU doesn't get enough power, check that all its power cables \
+             are plugged in."
+        }
+        NvmlError::DriverNotLoaded => {
+            "The NVIDIA kernel module isn't loaded, check `lsmod | grep nvidia` \
+             and that the driver is built for the running kernel."
+        }
+        NvmlError::LibloadingError(_) | NvmlError::LibraryNotFound => {
+            "libnvidia-ml.so wasn't found, install the NVIDIA driver's utilities \
+             package, e.g. nvidia-utils."
+        }
+        NvmlError::FailedToLoadSymbol(_) | NvmlError::FunctionNotFound => {
+            "The installed driver is too old for this call, update the NVIDIA \
+             driver."
+        }
+        NvmlError::LibRmVersionMismatch => {
+            "The NVML library and the kernel module are from different driver \
+             versions, reboot after a driver update."
+        }
+        NvmlError::GpuLost => {
+            "The GPU fell off the bus, usually from an unstable overclock or a \
+             power problem. Reboot and use lower offsets."
+        }
+        NvmlError::ResetRequired => "The GPU needs a reset, run `nvidia_oc gpu-reset` or reboot.",
+        NvmlError::InUse => "Processes are using the GPU, `nvidia_oc processes` lists them.",
+        NvmlError::CorruptedInfoROM => "The GPU's InfoROM is corrupted, contact the board vendor.",
+        _ => return None,
+    })
+}
+
+/// An NVML error followed by its hint, if there is one
+pub fn describe(e: &NvmlError) -> String {
+    match hint(e) {
+        Some(hint) => format!("{:?}\nHint: {}", e, hint),
+        None => format!("{:?}", e),
+    }
+}
+
+/// Like `expect`, with a hint on how to fix the error in the panic message
+pub trait ExpectHint<T> {
+    fn expect_hint(self, msg: &str) -> T;
+}
+
+impl<T> ExpectHint<T> for Result<T, NvmlError> {
+    fn expect_hint(self, msg: &str) -> T {
+        self.unwrap_or_else(|e| panic!("{}: {}", msg, describe(&e)))
+    }
+}
@@ -0,0 +1,195 @@
+use crate::power::format_watts;
+use nvml_wrapper::Device;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Journal of every setting nvidia_oc changed, one JSON object per line
+pub const HISTORY_PATH: &str = "/var/lib/nvidia_oc/history.jsonl";
+
+/// What triggered an apply
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Cli,
+    Config,
+    Daemon,
+    Undo,
+    /// Reverted because `--confirm-required` settings weren't confirmed
+    Watchdog,
+    /// Reset to stock by `nvidia_oc uninstall`
+    Uninstall,
+    /// Reset to stock because nvidia_oc crashed, see [`crate::watchdog::reset_on_panic`]
+    Crash,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Milliseconds since the Unix epoch, shared by all changes of one apply
+    pub timestamp_ms: u64,
+    pub index: u32,
+    pub uuid: String,
+    /// Config key of the parameter, e.g. `powerLimit`
+    pub parameter: String,
+    /// Value before the change, null if it couldn't be read back
+    pub old: Value,
+    pub new: Value,
+    pub source: Source,
+}
+
+/// Records the changes made by a single apply on a single GPU
+pub struct Journal {
+    index: u32,
+    uuid: String,
+    source: Source,
+    timestamp_ms: u64,
+}
+
+impl Journal {
+    pub fn new(device: &Device, source: Source) -> Journal {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        Journal {
+            index: device.index().unwrap_or_default(),
+            uuid: device.uuid().unwrap_or_default(),
+            source,
+            timestamp_ms,
+        }
+    }
+
+    /// Appends a change to the history. Failing to write the journal never
+    /// fails the apply itself.
+    pub fn record<O: Serialize, N: Serialize>(&self, parameter: &str, old: Option<O>, new: N) {
+        let entry = Entry {
+            timestamp_ms: self.timestamp_ms,
+            index: self.index,
+            uuid: self.uuid.clone(),
+            parameter: parameter.to_string(),
+            old: old
+                .and_then(|old| serde_json::to_value(old).ok())
+                .unwrap_or(Value::Null),
+            new: serde_json::to_value(new).unwrap_or(Value::Null),
+            source: self.source,
+        };
+
+        if let Err(e) = append(&entry) {
+            eprintln!("Failed to record change in {}: {}", HISTORY_PATH, e);
+        }
+    }
+}
+
+fn append(entry: &Entry) -> io::Result<()> {
+    if let Some(dir) = Path::new(HISTORY_PATH).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(HISTORY_PATH)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+}
+
+/// Reads the whole history, oldest first, skipping lines that don't parse
+pub fn read() -> io::Result<Vec<Entry>> {
+    let contents = match fs::read_to_string(HISTORY_PATH) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Returns the changes made by the most recent apply to the GPU with the given
+/// UUID, ignoring applies that were themselves undos
+pub fn last_apply(uuid: &str) -> io::Result<Vec<Entry>> {
+    let entries: Vec<Entry> = read()?
+        .into_iter()
+        .filter(|entry| entry.uuid == uuid && entry.source != Source::Undo)
+        .collect();
+
+    let Some(last) = entries.last().map(|entry| entry.timestamp_ms) else {
+        return Ok(Vec::new());
+    };
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.timestamp_ms == last)
+        .collect())
+}
+
+/// Formats a Unix timestamp in milliseconds as `YYYY-MM-DD HH:MM:SS` UTC
+pub fn format_timestamp(timestamp_ms: u64) -> String {
+    let secs = timestamp_ms / 1000;
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+fn show(parameter: &str, value: &Value) -> String {
+    match value {
+        Value::Null => "?".to_string(),
+        // Power limits are journaled in mW
+        Value::Number(n) if parameter == "powerLimit" => match n.as_u64() {
+            Some(mw) => format_watts(mw as u32),
+            None => n.to_string(),
+        },
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Prints the newest `limit` entries, optionally only for one GPU
+pub fn print(index: Option<u32>, limit: usize) {
+    let entries = read().unwrap_or_else(|e| panic!("Failed to read {}: {}", HISTORY_PATH, e));
+    let entries: Vec<&Entry> = entries
+        .iter()
+        .filter(|entry| index.is_none_or(|index| entry.index == index))
+        .collect();
+
+    if entries.is_empty() {
+        println!("No changes recorded yet.");
+        return;
+    }
+
+    for entry in &entries[entries.len().saturating_sub(limit)..] {
+        println!(
+            "{} UTC  GPU {}  {:<7}  {}: {} -> {}",
+            format_timestamp(entry.timestamp_ms),
+            entry.index,
+            format!("{:?}", entry.source).to_lowercase(),
+            entry.parameter,
+            show(&entry.parameter, &entry.old),
+            show(&entry.parameter, &entry.new)
+        );
+    }
+}
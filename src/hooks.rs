@@ -0,0 +1,134 @@
+use crate::history::Source;
+use nvml_wrapper::Device;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::process::Command;
+
+/// Shell commands run around an apply, one command or a list of them
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(from = "OneOrMany", into = "Vec<String>")]
+pub struct Hook(pub Vec<String>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<OneOrMany> for Hook {
+    fn from(commands: OneOrMany) -> Self {
+        match commands {
+            OneOrMany::One(command) => Hook(vec![command]),
+            OneOrMany::Many(commands) => Hook(commands),
+        }
+    }
+}
+
+impl From<Hook> for Vec<String> {
+    fn from(hook: Hook) -> Self {
+        hook.0
+    }
+}
+
+/// `global` hooks around the per-GPU ones, so a global `preApply` runs
+/// first and a global `postApply` last
+pub fn wrap(global: Option<&Hook>, gpu: Option<Hook>, global_first: bool) -> Option<Hook> {
+    let Some(global) = global.cloned() else {
+        return gpu;
+    };
+    let gpu = gpu.unwrap_or_default();
+    let (first, second) = if global_first {
+        (global, gpu)
+    } else {
+        (gpu, global)
+    };
+    Some(Hook(first.0.into_iter().chain(second.0).collect()))
+}
+
+/// Prefix of the variables hooks get. It keeps them apart from the
+/// `NVIDIA_OC_<SETTING>` overrides, which an `nvidia_oc` run from a hook
+/// would otherwise pick up, see [`crate::config::env_overrides`].
+pub const ENV_PREFIX: &str = "NVIDIA_OC_HOOK_";
+
+/// `freqOffset` becomes `NVIDIA_OC_HOOK_FREQ_OFFSET`
+fn variable(setting: &str) -> String {
+    let mut name = ENV_PREFIX.to_string();
+    for c in setting.chars() {
+        if c.is_ascii_uppercase() {
+            name.push('_');
+        }
+        name.push(c.to_ascii_uppercase());
+    }
+    name
+}
+
+/// The GPU, what triggered the apply and every configured setting, scalars
+/// as they're written in the config and anything else as JSON
+fn environment(
+    device: &Device,
+    source: Source,
+    stage: &str,
+    settings: &serde_json::Map<String, Value>,
+) -> Vec<(String, String)> {
+    let mut env = vec![
+        (format!("{}STAGE", ENV_PREFIX), stage.to_string()),
+        (
+            format!("{}SOURCE", ENV_PREFIX),
+            format!("{:?}", source).to_lowercase(),
+        ),
+        (
+            format!("{}INDEX", ENV_PREFIX),
+            device
+                .index()
+                .map_or_else(|_| String::new(), |i| i.to_string()),
+        ),
+        (
+            format!("{}UUID", ENV_PREFIX),
+            device.uuid().unwrap_or_default(),
+        ),
+        (
+            format!("{}NAME", ENV_PREFIX),
+            device.name().unwrap_or_default(),
+        ),
+        (
+            format!("{}SETTINGS", ENV_PREFIX),
+            Value::Object(settings.clone()).to_string(),
+        ),
+    ];
+    for (setting, value) in settings {
+        let value = match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        env.push((variable(setting), value));
+    }
+    env
+}
+
+/// Runs the commands of a hook through `sh -c` in order, stopping at the
+/// first one that fails
+pub fn run(
+    hook: Option<&Hook>,
+    stage: &str,
+    device: &Device,
+    source: Source,
+    settings: &serde_json::Map<String, Value>,
+) -> Result<(), String> {
+    let Some(hook) = hook else {
+        return Ok(());
+    };
+    let env = environment(device, source, stage, settings);
+    for command in &hook.0 {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .status()
+            .map_err(|e| format!("failed to run `{}`: {}", command, e))?;
+        if !status.success() {
+            return Err(format!("`{}` exited with {}", command, status));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::{Device, Nvml};
+use std::{fs, io, path::Path};
+
+/// Where the readings are published, one `hwmon<index>` directory per GPU
+pub const HWMON_DIR: &str = "/run/nvidia_oc/hwmon";
+
+/// Writes a file so readers never see it half written
+fn write_atomic(dir: &Path, name: &str, contents: &str) -> io::Result<()> {
+    let tmp = dir.join(format!(".{}", name));
+    fs::write(&tmp, format!("{}\n", contents))?;
+    fs::rename(tmp, dir.join(name))
+}
+
+/// The hwmon attributes for a GPU, in hwmon units: millidegrees, microwatts,
+/// RPM and 0-255 PWM
+fn attributes(index: u32, device: &Device) -> Vec<(String, String)> {
+    let mut attributes = vec![("name".to_string(), format!("nvidia_oc_gpu{}", index))];
+    let mut add = |name: &str, value: String| attributes.push((name.to_string(), value));
+    if let Ok(temp) = device.temperature(TemperatureSensor::Gpu) {
+        add("temp1_input", (temp * 1000).to_string());
+        add("temp1_label", "GPU".to_string());
+    }
+    if let Ok(power) = device.power_usage() {
+        add("power1_input", (power as u64 * 1000).to_string());
+        add("power1_label", "GPU".to_string());
+    }
+    if let Ok(limit) = device.enforced_power_limit() {
+        add("power1_cap", (limit as u64 * 1000).to_string());
+    }
+    // hwmon numbers channels from 1
+    for fan in 0..device.num_fans().unwrap_or(0) {
+        if let Ok(rpm) = device.fan_speed_rpm(fan) {
+            add(&format!("fan{}_input", fan + 1), rpm.to_string());
+        }
+        if let Ok(speed) = device.fan_speed(fan) {
+            add(
+                &format!("pwm{}", fan + 1),
+                (speed.min(100) * 255 / 100).to_string(),
+            );
+        }
+    }
+    attributes
+}
+
+/// Publishes every GPU's temperature, power and fan readings as files laid
+/// out like a kernel hwmon device, e.g.
+/// `/run/nvidia_oc/hwmon/hwmon0/temp1_input`.
+///
+/// `sensors` itself only lists devices registered by kernel drivers and
+/// there is no interface for userspace to register one, so this is for fan
+/// control tools that take sensor file paths, like fancontrol or
+/// CoolerControl, which can then drive case fans from the GPU.
+pub fn publish(nvml: &Nvml) -> io::Result<()> {
+    let count = nvml.device_count().map_err(io::Error::other)?;
+    for index in 0..count {
+        let Ok(device) = nvml.device_by_index(index) else {
+            continue;
+        };
+        let dir = Path::new(HWMON_DIR).join(format!("hwmon{}", index));
+        fs::create_dir_all(&dir)?;
+        for (name, value) in attributes(index, &device) {
+            write_atomic(&dir, &name, &value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the readings an earlier run published, its GPUs may be gone
+pub fn clear() {
+    match fs::remove_dir_all(HWMON_DIR) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("Failed to remove {}: {}", HWMON_DIR, e),
+    }
+}
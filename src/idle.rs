@@ -0,0 +1,143 @@
+use crate::power::{self, format_watts, PowerLimit};
+use nvml_wrapper::enums::device::GpuLockedClocksSetting;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Device;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+fn default_below() -> u32 {
+    5
+}
+
+fn default_after() -> u64 {
+    300
+}
+
+/// Low power settings for an idle GPU, configured per GPU under
+/// `idleProfile` and run by `nvidia_oc daemon`. Doesn't combine with a
+/// `powerGovernor`, both would fight over the power limit.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdleProfile {
+    /// GPU utilization in percent below which the GPU counts as idle
+    #[serde(default = "default_below")]
+    pub below: u32,
+    /// Seconds the GPU has to stay idle before the profile applies
+    #[serde(default = "default_after")]
+    pub after: u64,
+    /// Power limit while idle
+    pub power_limit: Option<PowerLimit>,
+    /// Locked clock range in MHz while idle
+    pub min_clock: Option<u32>,
+    pub max_clock: Option<u32>,
+}
+
+/// What the idle profile changed, restored when load returns
+struct Active {
+    /// Power limit before the profile applied
+    power_limit: Option<u32>,
+    clocks_locked: bool,
+}
+
+/// Switches one GPU to its [`IdleProfile`] once utilization stays low for
+/// long enough, and back as soon as utilization rises again.
+pub struct IdleState {
+    profile: IdleProfile,
+    /// Locked clocks of the config entry, NVML can't read them back
+    locked_clocks: Option<(u32, u32)>,
+    idle_since: Option<Instant>,
+    /// Set while the idle profile is applied
+    active: Option<Active>,
+}
+
+impl IdleState {
+    pub fn new(profile: IdleProfile, locked_clocks: Option<(u32, u32)>) -> Self {
+        Self {
+            profile,
+            locked_clocks,
+            idle_since: None,
+            active: None,
+        }
+    }
+
+    /// Whether the idle profile is applied right now
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    pub fn update(&mut self, gpu: &str, device: &mut Device) -> Result<(), NvmlError> {
+        let utilization = device.utilization_rates()?.gpu;
+        if utilization >= self.profile.below {
+            self.idle_since = None;
+            if let Some(active) = self.active.take() {
+                self.restore(device, &active)?;
+                println!(
+                    "GPU {} at {}% utilization, leaving idle profile",
+                    gpu, utilization
+                );
+            }
+            return Ok(());
+        }
+
+        let idle_since = *self.idle_since.get_or_insert_with(Instant::now);
+        if self.active.is_some() || idle_since.elapsed() < Duration::from_secs(self.profile.after) {
+            return Ok(());
+        }
+
+        let mut active = Active {
+            power_limit: None,
+            clocks_locked: false,
+        };
+        if let Some(limit) = self.profile.power_limit {
+            match limit.resolve(device) {
+                Ok(limit) => {
+                    active.power_limit = Some(device.power_management_limit()?);
+                    let limit = power::clamp_power_limit(device, limit);
+                    device.set_power_management_limit(limit)?;
+                    println!("GPU {} idle, power limit {}", gpu, format_watts(limit));
+                }
+                Err(e) => eprintln!("Invalid idle power limit for GPU {}: {}", gpu, e),
+            }
+        }
+        if let (Some(min_clock), Some(max_clock)) = (self.profile.min_clock, self.profile.max_clock)
+        {
+            // The power limit is already lowered, so this only logs and
+            // still records what has to be restored
+            match device.set_gpu_locked_clocks(GpuLockedClocksSetting::Numeric {
+                min_clock_mhz: min_clock,
+                max_clock_mhz: max_clock,
+            }) {
+                Ok(()) => {
+                    println!(
+                        "GPU {} idle, clocks locked to {}-{} MHz",
+                        gpu, min_clock, max_clock
+                    );
+                    active.clocks_locked = true;
+                }
+                Err(e) => eprintln!("Failed to lock idle clocks of GPU {}: {:?}", gpu, e),
+            }
+        }
+        self.active = Some(active);
+        Ok(())
+    }
+
+    /// Puts back the power limit from before and the config entry's locked
+    /// clocks, or unlocks them when the entry has none
+    fn restore(&self, device: &mut Device, active: &Active) -> Result<(), NvmlError> {
+        if let Some(limit) = active.power_limit {
+            device.set_power_management_limit(limit)?;
+        }
+        if !active.clocks_locked {
+            return Ok(());
+        }
+        match self.locked_clocks {
+            Some((min_clock, max_clock)) => {
+                device.set_gpu_locked_clocks(GpuLockedClocksSetting::Numeric {
+                    min_clock_mhz: min_clock,
+                    max_clock_mhz: max_clock,
+                })
+            }
+            None => device.reset_gpu_locked_clocks(),
+        }
+    }
+}
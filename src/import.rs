@@ -0,0 +1,190 @@
+use clap::ValueEnum;
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ImportFormat {
+    /// GreenWithEnvy's database, the active overclock and fan profiles
+    Gwe,
+    /// `nvidia-settings -a` assignments, e.g. from a startup script
+    NvidiaSettings,
+}
+
+/// Settings per GPU index, in config file form
+type Imported = BTreeMap<u32, Map<String, Value>>;
+
+/// Where a native GWE install keeps its database, the Flatpak one lives under
+/// `~/.var/app/com.leinardi.gwe/data` and has to be passed explicitly
+fn default_gwe_database() -> PathBuf {
+    let data_dir = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_default();
+    data_dir.join("gwe").join("gwe.db")
+}
+
+/// Runs a query through the sqlite3 CLI and splits the rows into columns
+fn sqlite(database: &Path, query: &str) -> Result<Vec<Vec<String>>, String> {
+    let output = Command::new("sqlite3")
+        .args(["-readonly", "-separator", "|"])
+        .arg(database)
+        .arg(query)
+        .output()
+        .map_err(|e| format!("Failed to run sqlite3: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "sqlite3 failed on {}: {}",
+            database.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|row| row.split('|').map(str::to_string).collect())
+        .collect())
+}
+
+fn parse_column<T: std::str::FromStr>(row: &[String], column: usize) -> Result<T, String> {
+    row.get(column)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("Unexpected row in GWE database: {}", row.join("|")))
+}
+
+/// Maps GWE's active overclock and fan profiles onto one GPU. GWE stores the
+/// memory offset as nvidia-settings does, as twice the memory clock. A fan
+/// profile with a single speed step is a fixed speed, more steps a curve.
+fn import_gwe(database: &Path, index: u32) -> Result<Imported, String> {
+    let mut settings = Map::new();
+
+    let overclock = sqlite(
+        database,
+        "SELECT p.gpu, p.memory FROM currentoverclockprofile c \
+         JOIN overclockprofile p ON p.id = c.profile_id",
+    )?;
+    if let Some(row) = overclock.first() {
+        settings.insert(
+            "freqOffset".to_string(),
+            json!(parse_column::<i32>(row, 0)?),
+        );
+        settings.insert(
+            "memOffset".to_string(),
+            json!(parse_column::<i32>(row, 1)? / 2),
+        );
+    }
+
+    let steps = sqlite(
+        database,
+        "SELECT s.temperature, s.duty FROM currentfanprofile c \
+         JOIN speedstep s ON s.profile_id = c.profile_id ORDER BY s.temperature",
+    )?
+    .iter()
+    .map(|row| Ok((parse_column::<u32>(row, 0)?, parse_column::<u32>(row, 1)?)))
+    .collect::<Result<Vec<_>, String>>()?;
+    match steps.as_slice() {
+        // No fan profile means GWE left the fans to the driver
+        [] => {}
+        [(_, duty)] => {
+            settings.insert("fan".to_string(), json!({ "0": duty }));
+        }
+        points => {
+            settings.insert("fanCurve".to_string(), json!({ "points": points }));
+        }
+    }
+
+    Ok(BTreeMap::from([(index, settings)]))
+}
+
+/// Maps `[gpu:N]/Attribute[level]=value` assignments onto the GPUs they
+/// target. Assignments without a target and `[fan:N]` ones go to `index`,
+/// nvidia-settings numbers fans across all GPUs. Per level offsets are
+/// treated as all-level offsets, the highest level is the one that matters.
+fn import_nvidia_settings(text: &str, index: u32) -> Result<Imported, String> {
+    let assignment =
+        Regex::new(r"(?:\[(gpu|fan):(\d+)\]/)?(\w+)(?:\[\d+\])?\s*=\s*(-?\d+)").unwrap();
+
+    let mut imported = Imported::new();
+    for captures in assignment.captures_iter(text) {
+        let target = captures.get(1).map(|m| m.as_str());
+        let target_index = captures.get(2).and_then(|m| m.as_str().parse::<u32>().ok());
+        let attribute = &captures[3];
+        let value: i32 = captures[4]
+            .parse()
+            .map_err(|e| format!("Invalid value in {}: {}", &captures[0], e))?;
+
+        let gpu = match target {
+            Some("gpu") => target_index.unwrap_or(index),
+            _ => index,
+        };
+        let settings = imported.entry(gpu).or_default();
+        match attribute {
+            "GPUGraphicsClockOffset" | "GPUGraphicsClockOffsetAllPerformanceLevels" => {
+                settings.insert("freqOffset".to_string(), json!(value));
+            }
+            "GPUMemoryTransferRateOffset" | "GPUMemoryTransferRateOffsetAllPerformanceLevels" => {
+                settings.insert("memOffset".to_string(), json!(value / 2));
+            }
+            "GPUTargetFanSpeed" => {
+                let fan = target_index.filter(|_| target == Some("fan")).unwrap_or(0);
+                let speeds = settings
+                    .entry("fan".to_string())
+                    .or_insert_with(|| json!({}));
+                speeds[fan.to_string()] = json!(value);
+            }
+            "GPUFanControlState" => {
+                let policy = if value == 0 { "auto" } else { "manual" };
+                settings.insert("fanPolicy".to_string(), json!(policy));
+            }
+            _ => eprintln!("Skipping unsupported assignment {}", &captures[0]),
+        }
+    }
+    imported.retain(|_, settings| !settings.is_empty());
+
+    if imported.is_empty() {
+        return Err("No supported nvidia-settings assignments found".to_string());
+    }
+    Ok(imported)
+}
+
+/// Converts a GWE database or nvidia-settings assignments into a config and
+/// prints it, ready to review and save as the config file. nvidia-settings
+/// assignments are read from stdin when no path is given.
+pub fn run(format: ImportFormat, path: Option<&Path>, index: u32) {
+    let imported = match format {
+        ImportFormat::Gwe => {
+            let database = path.map_or_else(default_gwe_database, Path::to_path_buf);
+            import_gwe(&database, index)
+        }
+        ImportFormat::NvidiaSettings => {
+            let text = match path {
+                Some(path) => fs::read_to_string(path)
+                    .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e)),
+                None => {
+                    let mut text = String::new();
+                    io::stdin()
+                        .read_to_string(&mut text)
+                        .expect("Failed to read assignments from stdin");
+                    text
+                }
+            };
+            import_nvidia_settings(&text, index)
+        }
+    }
+    .unwrap_or_else(|e| panic!("{}", e));
+
+    let sets: Map<String, Value> = imported
+        .into_iter()
+        .map(|(index, settings)| (index.to_string(), Value::Object(settings)))
+        .collect();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&json!({ "sets": sets })).expect("Failed to serialize config")
+    );
+}
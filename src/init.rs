@@ -0,0 +1,152 @@
+use crate::config::SYSTEM_CONFIG_PATH;
+use nvml_wrapper::{Device, Nvml};
+use serde_json::{json, Map, Value};
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+    str::FromStr,
+};
+
+/// Asks until the answer parses and passes `validate`, an empty answer picks
+/// `default`
+fn prompt<T>(question: &str, default: T, validate: impl Fn(&T) -> Result<(), String>) -> T
+where
+    T: FromStr + Display,
+    T::Err: Display,
+{
+    let stdin = io::stdin();
+    loop {
+        print!("{} [{}]: ", question, default);
+        io::stdout().flush().expect("Failed to write prompt");
+
+        let mut answer = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut answer)
+            .expect("Failed to read answer")
+            == 0
+        {
+            panic!("Input closed before the config was complete");
+        }
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return default;
+        }
+
+        match answer.parse::<T>() {
+            Ok(value) => match validate(&value) {
+                Ok(()) => return value,
+                Err(e) => println!("  {}", e),
+            },
+            Err(e) => println!("  Invalid value: {}", e),
+        }
+    }
+}
+
+fn confirm(question: &str) -> bool {
+    let answer: String =
+        prompt(
+            &format!("{} (y/n)", question),
+            "n".to_string(),
+            |answer| match answer.to_lowercase().as_str() {
+                "y" | "yes" | "n" | "no" => Ok(()),
+                _ => Err("Please answer y or n".to_string()),
+            },
+        );
+    answer.to_lowercase().starts_with('y')
+}
+
+/// Asks for the settings of one GPU, returns only the ones that differ from
+/// the driver defaults
+fn ask_settings(device: &Device) -> Map<String, Value> {
+    let mut settings = Map::new();
+
+    if let (Ok(default), Ok(constraints)) = (
+        device.power_management_limit_default(),
+        device.power_management_limit_constraints(),
+    ) {
+        let (min, max) = (constraints.min_limit / 1000, constraints.max_limit / 1000);
+        let current = device
+            .power_management_limit()
+            .unwrap_or(default)
+            .clamp(constraints.min_limit, constraints.max_limit);
+        let watts: u32 = prompt(
+            &format!("  Power limit in W ({}-{})", min, max),
+            current / 1000,
+            |watts| {
+                if (min..=max).contains(watts) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "The power limit must be between {} and {} W",
+                        min, max
+                    ))
+                }
+            },
+        );
+        if watts * 1000 != default {
+            settings.insert("powerLimit".to_string(), json!(format!("{}W", watts)));
+        }
+    }
+
+    if let Ok(current) = device.gpc_clock_vf_offset() {
+        let offset: i32 = prompt("  Core clock offset in MHz", current, |_| Ok(()));
+        if offset != 0 {
+            settings.insert("freqOffset".to_string(), json!(offset));
+        }
+    }
+
+    if let Ok(current) = device.mem_clock_vf_offset() {
+        let offset: i32 = prompt("  Memory clock offset in MHz", current, |_| Ok(()));
+        if offset != 0 {
+            settings.insert("memOffset".to_string(), json!(offset));
+        }
+    }
+
+    settings
+}
+
+/// Walks through every detected GPU asking for its settings and writes the
+/// result to `path`, /etc/nvidia_oc.json unless `--file` says otherwise.
+pub fn run(nvml: &Nvml, path: &Path) {
+    if path.exists() && !confirm(&format!("{} already exists, overwrite it?", path.display())) {
+        return;
+    }
+
+    let count = nvml.device_count().expect("Failed to get GPU count");
+    let mut sets = Map::new();
+    for index in 0..count {
+        let device = nvml.device_by_index(index).expect("Failed to get GPU");
+        let name = device.name().unwrap_or_else(|_| "unknown GPU".to_string());
+        println!("GPU {}: {}", index, name);
+        if !confirm("  Configure this GPU?") {
+            continue;
+        }
+
+        let settings = ask_settings(&device);
+        if settings.is_empty() {
+            println!("  Nothing differs from the defaults, skipping.");
+            continue;
+        }
+        // Keyed by UUID so the entry follows the card if the order changes
+        let key = device.uuid().unwrap_or_else(|_| index.to_string());
+        sets.insert(key, Value::Object(settings));
+    }
+
+    if sets.is_empty() {
+        println!("No GPU configured, not writing {}.", path.display());
+        return;
+    }
+
+    let config =
+        serde_json::to_string_pretty(&json!({ "sets": sets })).expect("Failed to serialize config");
+    fs::write(path, config + "\n").expect("Failed to write config file");
+    println!("Wrote {}.", path.display());
+    if path == Path::new(SYSTEM_CONFIG_PATH) {
+        println!(
+            "Run `nvidia_oc` to apply it, or `nvidia_oc udev-rule --install` to apply it at boot."
+        );
+    }
+}
@@ -0,0 +1,33 @@
+use nvml_wrapper::Device;
+use std::process::Command;
+
+/// nvidia-settings (Coolbits) attribute for the core clock offset
+pub const GRAPHICS_CLOCK_OFFSET: &str = "GPUGraphicsClockOffsetAllPerformanceLevels";
+/// nvidia-settings (Coolbits) attribute for the memory transfer rate offset
+pub const MEMORY_TRANSFER_RATE_OFFSET: &str = "GPUMemoryTransferRateOffsetAllPerformanceLevels";
+
+/// Sets an X driver attribute through nvidia-settings for GPUs where NVML
+/// refuses VF offsets (pre-Turing). Needs a running X server with Coolbits
+/// enabled. nvidia-settings numbers GPUs in the same PCI order as NVML.
+pub fn set_attribute(device: &Device, attribute: &str, value: i32) -> Result<(), String> {
+    let index = device
+        .index()
+        .map_err(|e| format!("Failed to get GPU index: {:?}", e))?;
+    let assignment = format!("[gpu:{}]/{}={}", index, attribute, value);
+
+    let output = Command::new("nvidia-settings")
+        .args(["-a", &assignment])
+        .output()
+        .map_err(|e| format!("Failed to run nvidia-settings: {}", e))?;
+    // nvidia-settings reports some failures on stdout with a zero exit code
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() || stdout.contains("ERROR") {
+        return Err(format!(
+            "nvidia-settings failed to assign {}: {}{}",
+            assignment,
+            stdout.trim(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+
+/// Lock file serializing NVML writes between concurrent nvidia_oc processes,
+/// e.g. the boot service and a user running `set` at login
+pub const LOCK_PATH: &str = "/run/nvidia_oc.lock";
+
+/// Held while settings are being applied, released on drop.
+pub struct ApplyLock {
+    _file: File,
+}
+
+impl ApplyLock {
+    /// Blocks until no other instance is applying settings
+    pub fn acquire() -> io::Result<ApplyLock> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(LOCK_PATH)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                println!("Waiting for another nvidia_oc instance to finish applying settings...");
+                file.lock()?;
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        Ok(ApplyLock { _file: file })
+    }
+}
@@ -1,36 +1,419 @@
-use clap::{arg, Args, CommandFactory, Parser, Subcommand};
+mod alert;
+mod analyze;
+mod battery;
+mod caps;
+mod color;
+mod compare;
+mod config;
+mod coolbits;
+mod daemon;
+mod doctor;
+mod drift;
+mod dump;
+mod energy;
+mod events;
+mod export;
+mod fan;
+mod governor;
+mod hint;
+mod history;
+mod hooks;
+mod hwmon;
+mod idle;
+mod import;
+mod init;
+mod legacy;
+mod lock;
+mod mig;
+#[cfg(feature = "windows")]
+mod nvapi;
+mod nvlink;
+mod offset;
+mod output;
+mod pcie;
+mod polkit;
+mod power;
+mod pstate;
+mod results;
+mod retry;
+mod self_update;
+mod signal;
+mod socket;
+mod status;
+mod store;
+mod telemetry;
+mod thermal;
+mod throttle;
+mod topology;
+mod uninstall;
+mod watch;
+mod watchdog;
+
+use alert::Alert;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use clap_complete::{generate, Generator, Shell};
+use color::{paint, ColorChoice, Severity};
+use config::Config;
+use fan::{FanCurves, FanPolicy, FanSpeeds};
+use governor::PowerGovernor;
+use hint::ExpectHint;
+use history::{Journal, Source};
+use hooks::Hook;
+use idle::IdleProfile;
+use lock::ApplyLock;
+use nvml_wrapper::enum_wrappers::device::{Clock, ComputeMode, PerformanceState};
+use nvml_wrapper::enums::device::UsedGpuMemory;
+use nvml_wrapper::error::NvmlError;
 use nvml_wrapper::{Device, Nvml};
-use serde::Deserialize;
-use std::{collections::HashMap, io};
+use offset::Offset;
+use output::OutputFormat;
+use power::PowerLimit;
+use pstate::{pstate_name, PstateOffsets};
+use retry::retry;
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant},
+};
+use throttle::{throttle_reason_names, throttle_severity, violation_times};
 
 #[derive(Parser, Debug)]
 #[command(version, about)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
-    /// Path to the config file
-    #[arg(short, long, default_value = "/etc/nvidia_oc.json")]
-    file: String,
+    /// Path to the config file [default: $NVIDIA_OC_CONFIG, then
+    /// ~/.config/nvidia_oc/config.json, then /etc/nvidia_oc.json]
+    #[arg(short, long)]
+    file: Option<String>,
+    /// Never re-run through sudo/doas/pkexec, fail instead if not root. For
+    /// service managers that already run the tool with enough privileges.
+    #[arg(long, global = true, env = "NVIDIA_OC_NO_ESCALATE")]
+    no_escalate: bool,
+    /// Revert the applied settings to stock at the next boot unless
+    /// `nvidia_oc confirm` is run first
+    #[arg(long, global = true)]
+    confirm_required: bool,
+    /// When to color output
+    #[arg(long, global = true, value_enum, default_value = "auto")]
+    color: ColorChoice,
+    /// Output format of get, status, diff, caps, analyze, topology, runs,
+    /// trials and compare
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    format: OutputFormat,
+    /// Attempts for NVML calls that fail with a transient error when
+    /// applying settings, e.g. while the driver is still loading at boot
+    #[arg(long, global = true, default_value_t = 3)]
+    retries: u32,
+    /// Milliseconds to wait before the first retry, doubled after each one
+    #[arg(long, global = true, default_value_t = 500)]
+    retry_backoff: u64,
 }
 
+// Parsed once per run, boxing `Sets` wouldn't buy anything
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Sets GPU parameters like frequency offset and power limit
     Set {
-        /// GPU index
-        #[arg(short, long)]
-        index: u32,
+        /// GPU index, several as 0,2,3 or a repeated --index
+        #[arg(
+            short,
+            long,
+            value_delimiter = ',',
+            required_unless_present = "matching"
+        )]
+        index: Vec<u32>,
+        /// Also apply to every GPU whose name matches this glob, e.g.
+        /// "RTX 3080*"
+        #[arg(long = "match", value_name = "GLOB")]
+        matching: Option<String>,
+        /// Read a JSON object of settings, as in the config file, from stdin.
+        /// Flags given alongside override its values.
+        #[arg(long, group = "Sets")]
+        stdin: bool,
+        /// Restore the previous values after this many seconds, or on Ctrl-C
+        #[arg(long, value_name = "SECONDS")]
+        test: Option<u64>,
+        /// Also write the settings into each GPU's entry in the config file,
+        /// see --file. Takes absolute values only.
+        #[arg(long, conflicts_with = "test")]
+        save: bool,
 
         #[command(flatten)]
         sets: Sets,
     },
     /// Gets GPU parameters
     Get {
+        /// GPU index
+        #[arg(short, long, required_unless_present = "all")]
+        index: Option<u32>,
+        /// Report every GPU
+        #[arg(long, conflicts_with = "index")]
+        all: bool,
+        /// Print only this reading's raw value, e.g. power_limit or
+        /// core_clock_offset, the names are those of --format json
+        #[arg(long, conflicts_with = "all")]
+        field: Option<String>,
+    },
+    /// Prints a table of every GPU's settings and whether they match the config
+    Status,
+    /// Interactively creates a config file for the detected GPUs
+    Init,
+    /// Shows where the live GPU settings deviate from the config file
+    Diff,
+    /// Prints a JSON report of whether every GPU still matches the config and
+    /// exits with 1 if one doesn't, for monitoring
+    Verify,
+    /// Prints the config as equivalent nvidia-smi and nvidia-settings commands
+    Export {
+        /// Output format
+        #[arg(long = "as", value_enum, default_value = "commands")]
+        format: export::ExportFormat,
+    },
+    /// Converts GreenWithEnvy profiles or nvidia-settings assignments into a
+    /// config and prints it
+    Import {
+        /// What to import from
+        #[arg(value_enum)]
+        from: import::ImportFormat,
+        /// GWE database or file with assignments, defaults to GWE's database
+        /// or stdin
+        path: Option<PathBuf>,
+        /// GPU index the settings apply to when the source doesn't say
+        #[arg(short, long, default_value_t = 0)]
+        index: u32,
+    },
+    /// Lists processes with graphics or compute contexts on the GPU
+    Processes {
+        /// GPU index
+        #[arg(short, long)]
+        index: u32,
+    },
+    /// Continuously prints live readings and time spent capped per interval
+    Watch {
+        /// GPU index
+        #[arg(short, long)]
+        index: u32,
+        /// Seconds between readings
+        #[arg(long, default_value_t = 1.0)]
+        interval: f64,
+        /// Also show sparklines of the last minute of temperature, power and
+        /// core clock
+        #[arg(long)]
+        trend: bool,
+        /// Also show 10 and 60 second averages and session peaks of power
+        /// and temperature
+        #[arg(long)]
+        averages: bool,
+    },
+    /// Samples throttle reasons while a workload runs and summarizes which
+    /// limits held the GPU back, with a tuning recommendation
+    Analyze {
+        /// GPU index
+        #[arg(short, long)]
+        index: u32,
+        /// Seconds to sample for
+        #[arg(long, default_value_t = 60)]
+        duration: u64,
+        /// Seconds between samples
+        #[arg(long, default_value_t = 0.5)]
+        interval: f64,
+    },
+    /// Records clocks, temperature, power, utilization and throttle reasons
+    /// until interrupted
+    Log {
+        /// GPU index
+        #[arg(short, long)]
+        index: u32,
+        /// Seconds between samples
+        #[arg(long, default_value_t = 1.0)]
+        interval: f64,
+        /// Output file, JSON lines for .json/.jsonl and CSV otherwise [default: stdout]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Measures energy consumption and projects kWh per day and week
+    Energy {
+        /// GPU index, all GPUs when omitted
+        #[arg(short, long)]
+        index: Option<u32>,
+        /// Seconds between reports
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+        /// Stop after this many seconds instead of running until interrupted
+        #[arg(long)]
+        duration: Option<u64>,
+    },
+    /// Applies the config file and keeps running to drive fan curves, alerts and
+    /// power governors
+    Daemon {
+        /// Seconds between fan curve and alert checks
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+        /// Minutes between checks for settings that drifted from the config,
+        /// e.g. offsets reset by a driver event, which are then reapplied
+        /// without running the hooks
+        #[arg(long)]
+        reapply_interval: Option<u64>,
+        /// Publish temperature, power and fan readings as hwmon style files
+        /// under /run/nvidia_oc/hwmon for fan control tools
+        #[arg(long)]
+        hwmon: bool,
+        /// Answer queries for live readings as JSON on /run/nvidia_oc.sock,
+        /// for status bars and overlays
+        #[arg(long)]
+        socket: bool,
+    },
+    /// Shows the journal of applied changes
+    History {
+        /// Only show changes to this GPU
+        #[arg(short, long)]
+        index: Option<u32>,
+        /// Number of most recent changes to show
+        #[arg(short = 'n', long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// Lists the tuning runs recorded by the GUI tuner
+    Runs {
+        /// Only runs on GPUs whose name contains this, or with this UUID
+        #[arg(long)]
+        gpu: Option<String>,
+        /// Only runs on driver versions starting with this, e.g. `560.`
+        #[arg(long)]
+        driver: Option<String>,
+        /// Number of most recent runs to show
+        #[arg(short = 'n', long, default_value_t = 50)]
+        limit: usize,
+        /// Results database [default: ~/.local/share/nvidia_oc/results.db]
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Lists the best trials of the recorded tuning runs
+    Trials {
+        /// Only trials of this run
+        #[arg(long)]
+        run: Option<i64>,
+        /// Only runs on GPUs whose name contains this, or with this UUID
+        #[arg(long)]
+        gpu: Option<String>,
+        /// Only runs on driver versions starting with this, e.g. `560.`
+        #[arg(long)]
+        driver: Option<String>,
+        /// What to rank the trials by
+        #[arg(long, value_enum, default_value = "score")]
+        sort: store::TrialOrder,
+        /// Number of trials to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Results database [default: ~/.local/share/nvidia_oc/results.db]
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Compares the scores and power of two tuning runs at the settings both
+    /// tried, flagging which differences are outside the trial to trial noise
+    Compare {
+        /// Run id from `nvidia_oc runs`, the baseline
+        run_a: i64,
+        /// Run id to compare against the baseline
+        run_b: i64,
+        /// Results database [default: ~/.local/share/nvidia_oc/results.db]
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Keeps settings applied with --confirm-required across reboots
+    Confirm,
+    /// Restores the values recorded before the most recent apply
+    Undo {
+        /// GPU index
+        #[arg(short, long)]
+        index: u32,
+    },
+    /// Shows state, peer and error counters of each NVLink
+    Nvlink {
+        /// GPU index, all GPUs when omitted
+        #[arg(short, long)]
+        index: Option<u32>,
+    },
+    /// Shows how the GPUs are connected to each other and which CPUs and
+    /// NUMA node each is closest to
+    Topology,
+    /// Prints every reading NVML offers as JSON, for bug reports
+    Dump {
+        /// GPU index, all GPUs when omitted
+        #[arg(short, long)]
+        index: Option<u32>,
+    },
+    /// Shows the MIG mode and MIG devices, set the mode with `set --mig`
+    Mig {
+        /// GPU index, all GPUs when omitted
+        #[arg(short, long)]
+        index: Option<u32>,
+    },
+    /// Resets a wedged GPU via nvidia-smi, refusing while processes use it
+    GpuReset {
         /// GPU index
         #[arg(short, long)]
         index: u32,
     },
+    /// Generates a udev rule reapplying the config whenever the driver binds a GPU
+    UdevRule {
+        /// Write the rule to /etc/udev/rules.d instead of printing it
+        #[arg(long)]
+        install: bool,
+    },
+    /// Generates a systemd-sleep hook reapplying the config after suspend and
+    /// hibernation, for systems not running the daemon
+    SleepHook {
+        /// Write the hook to /usr/lib/systemd/system-sleep instead of printing it
+        #[arg(long)]
+        install: bool,
+    },
+    /// Shows the Coolbits value of the Xorg config, which nvidia-settings
+    /// needs for offsets and fan control, and generates a config setting it
+    Coolbits {
+        /// Coolbits value, the sum of 4 for fan control, 8 for clock offsets
+        /// and 16 for overvoltage
+        #[arg(long, default_value_t = coolbits::DEFAULT_VALUE)]
+        value: u32,
+        /// Write the value to the Xorg config, backing up the changed file,
+        /// instead of printing a snippet
+        #[arg(long)]
+        install: bool,
+    },
+    /// Reports which operations each GPU and the driver support
+    Caps,
+    /// Checks the driver, its module options, NVML, privileges, persistence,
+    /// Coolbits and conflicting tools, exiting with 1 if a check fails
+    Doctor,
+    /// Generates a polkit policy so `pkexec nvidia_oc set` works for a group
+    /// without a password, used by the GUI when it isn't running as root
+    Polkit {
+        /// Group allowed to change GPU settings without a password
+        #[arg(long, default_value = "wheel")]
+        group: String,
+        /// Write the policy and rule to the polkit directories instead of
+        /// printing them
+        #[arg(long)]
+        install: bool,
+    },
+    /// Resets every GPU to stock and removes the systemd unit, udev rule,
+    /// sleep hook and polkit policy
+    Uninstall {
+        /// Also delete the system config and the change history
+        #[arg(long)]
+        purge: bool,
+    },
+    /// Replaces this binary with the latest GitHub release, verified against
+    /// its published checksum
+    SelfUpdate {
+        /// Install the latest release even when it is older than this binary
+        #[arg(long)]
+        force: bool,
+    },
     /// Generate shell completion script
     Completion {
         /// The shell to generate the script for
@@ -39,19 +422,72 @@ enum Commands {
     },
 }
 
-#[derive(Args, Debug, Deserialize)]
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Toggle {
+    On,
+    Off,
+}
+
+impl Toggle {
+    fn enabled(self) -> bool {
+        self == Toggle::On
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum ComputeModeArg {
+    /// Multiple contexts per device
+    Default,
+    /// Only one context per device, usable from multiple threads
+    ExclusiveProcess,
+    /// No contexts per device
+    Prohibited,
+}
+
+impl ComputeModeArg {
+    fn from_mode(mode: ComputeMode) -> Option<ComputeModeArg> {
+        match mode {
+            ComputeMode::Default => Some(ComputeModeArg::Default),
+            ComputeMode::ExclusiveProcess => Some(ComputeModeArg::ExclusiveProcess),
+            ComputeMode::Prohibited => Some(ComputeModeArg::Prohibited),
+            _ => None,
+        }
+    }
+}
+
+impl From<ComputeModeArg> for ComputeMode {
+    fn from(mode: ComputeModeArg) -> Self {
+        match mode {
+            ComputeModeArg::Default => ComputeMode::Default,
+            ComputeModeArg::ExclusiveProcess => ComputeMode::ExclusiveProcess,
+            ComputeModeArg::Prohibited => ComputeMode::Prohibited,
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 #[group(required = true, multiple = true)]
 struct Sets {
-    /// GPU frequency offset
+    /// GPU frequency offset, +15 or -=15 adjust the current offset
     #[arg(short, long, allow_hyphen_values = true)]
-    freq_offset: Option<i32>,
-    /// GPU memory frequency offset
+    freq_offset: Option<Offset>,
+    /// GPU memory frequency offset, +15 or -=15 adjust the current offset
     #[arg(long, allow_hyphen_values = true)]
-    mem_offset: Option<i32>,
-    /// GPU power limit in milliwatts
-    #[arg(short, long)]
-    power_limit: Option<u32>,
+    mem_offset: Option<Offset>,
+    /// GPU frequency offset per performance state, e.g. P0:200,P2:100
+    #[arg(long, allow_hyphen_values = true)]
+    freq_offset_pstate: Option<PstateOffsets>,
+    /// GPU power limit like 250W or 250000mW, plain numbers are watts. Also
+    /// takes a percentage of the default limit like 80%, or a change to the
+    /// current limit like -20W
+    #[arg(short, long, allow_hyphen_values = true)]
+    power_limit: Option<PowerLimit>,
+    /// GPU target temperature in °C, the driver throttles to stay below it
+    #[arg(long)]
+    temp_limit: Option<u32>,
     /// GPU min clock
     #[arg(long, requires = "max_clock")]
     min_clock: Option<u32>,
@@ -64,111 +500,1029 @@ struct Sets {
     /// GPU max memory clock
     #[arg(long, requires = "min_mem_clock")]
     max_mem_clock: Option<u32>,
+    /// ECC memory mode, takes effect after the next reboot
+    #[arg(long)]
+    ecc: Option<Toggle>,
+    /// MIG mode, takes effect after the next GPU reset or reboot
+    #[arg(long)]
+    mig: Option<Toggle>,
+    /// Fixed fan speed in percent per fan index, e.g. 0:40,1:60, switches
+    /// those fans to the manual policy
+    #[arg(long)]
+    fan: Option<FanSpeeds>,
+    /// Fan control policy for every fan, applied after `fan`; auto hands the
+    /// fans back to the driver
+    #[arg(long)]
+    fan_policy: Option<FanPolicy>,
+    /// CUDA compute mode
+    #[arg(long)]
+    compute_mode: Option<ComputeModeArg>,
+    /// Fall back to nvidia-settings (X with Coolbits) for offsets NVML doesn't
+    /// support, e.g. on pre-Turing GPUs
+    #[arg(long)]
+    #[serde(default)]
+    legacy_fallback: bool,
+    /// Fan curve driven by `nvidia_oc daemon`, config file only
+    #[arg(skip)]
+    fan_curve: Option<FanCurves>,
+    /// Temperature/power thresholds checked by `nvidia_oc daemon`, config file only
+    #[arg(skip)]
+    alerts: Option<Vec<Alert>>,
+    /// Utilization based power limit bounds used by `nvidia_oc daemon`, config file only
+    #[arg(skip)]
+    power_governor: Option<PowerGovernor>,
+    /// Power saving settings applied by `nvidia_oc daemon` while the GPU is
+    /// idle, config file only
+    #[arg(skip)]
+    idle_profile: Option<IdleProfile>,
+    /// Settings `nvidia_oc daemon` applies on top of these while the system
+    /// runs on battery, config file only
+    #[arg(skip)]
+    battery: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Shell commands run before the settings are applied, with them in
+    /// `NVIDIA_OC_*` environment variables. If one fails nothing is applied.
+    /// Config file only.
+    #[arg(skip)]
+    pre_apply: Option<Hook>,
+    /// Shell commands run after the settings were applied, config file only
+    #[arg(skip)]
+    post_apply: Option<Hook>,
 }
 
 impl Sets {
-    fn apply(&self, device: &mut Device) {
+    /// Runs the `preApply` hook, applies every configured parameter,
+    /// recording each change in the history journal as it succeeds, then
+    /// runs the `postApply` hook. Nothing is applied when the `preApply`
+    /// hook fails.
+    fn apply(&self, device: &mut Device, source: Source) -> Result<(), String> {
+        let settings = self.settings();
+        hooks::run(
+            self.pre_apply.as_ref(),
+            "preApply",
+            device,
+            source,
+            &settings,
+        )
+        .map_err(|e| format!("preApply hook failed, not applying: {}", e))?;
+        watchdog::track(device);
+        self.apply_settings(device, source);
+        if let Err(e) = hooks::run(
+            self.post_apply.as_ref(),
+            "postApply",
+            device,
+            source,
+            &settings,
+        ) {
+            eprintln!("postApply hook failed: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Parses one setting from a string the way its flag does, e.g.
+    /// `powerLimit` from `250` as 250 W, as the JSON the config file would
+    /// hold. `None` for settings without a flag taking a value.
+    fn parse_flag(setting: &str, value: &str) -> Option<Result<serde_json::Value, String>> {
+        let id: String = setting
+            .chars()
+            .flat_map(|c| {
+                if c.is_ascii_uppercase() {
+                    vec!['_', c.to_ascii_lowercase()]
+                } else {
+                    vec![c]
+                }
+            })
+            .collect();
+        // Just this one flag is given, so drop what it requires of the others
+        let command = Sets::augment_args(clap::Command::new("nvidia_oc"))
+            .mut_args(|arg| arg.requires(clap::builder::Resettable::Reset));
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str())?;
+        if !arg.get_action().takes_values() {
+            return None;
+        }
+        let flag = format!("--{}={}", arg.get_long()?, value);
+        let parsed = command
+            .try_get_matches_from(["nvidia_oc", flag.as_str()])
+            .and_then(|matches| Sets::from_arg_matches(&matches))
+            .map_err(|e| {
+                let message = e.render().to_string();
+                let first = message.lines().next().unwrap_or_default();
+                first.trim_start_matches("error: ").to_string()
+            })
+            .and_then(|sets| serde_json::to_value(sets).map_err(|e| e.to_string()));
+        Some(parsed.map(|mut sets| sets[setting].take()))
+    }
+
+    /// Whether any value is a change to the current one rather than a value
+    /// to set
+    fn is_relative(&self) -> bool {
+        matches!(self.freq_offset, Some(Offset::Relative(_)))
+            || matches!(self.mem_offset, Some(Offset::Relative(_)))
+            || matches!(self.power_limit, Some(PowerLimit::Relative(_)))
+    }
+
+    /// Whether these settings run shell commands as root, from hooks or
+    /// alerts, including the ones applied on battery
+    fn runs_commands(&self) -> bool {
+        let alert_commands = self
+            .alerts
+            .iter()
+            .flatten()
+            .any(|alert| alert.command.is_some());
+        let battery = self.battery.as_ref().is_some_and(|battery| {
+            ["preApply", "postApply", "alerts"]
+                .iter()
+                .any(|key| battery.contains_key(*key))
+        });
+        self.pre_apply.is_some() || self.post_apply.is_some() || alert_commands || battery
+    }
+
+    /// The configured settings as the config file holds them, without the
+    /// hooks. Hooks get these, and `set --save` writes them.
+    fn settings(&self) -> serde_json::Map<String, serde_json::Value> {
+        let Ok(serde_json::Value::Object(mut settings)) = serde_json::to_value(self) else {
+            panic!("Failed to serialize settings");
+        };
+        settings.retain(|key, value| {
+            !value.is_null()
+                && value != &serde_json::Value::Bool(false)
+                && key != "preApply"
+                && key != "postApply"
+        });
+        settings
+    }
+
+    fn apply_settings(&self, device: &mut Device, source: Source) {
+        let journal = Journal::new(device, source);
+
         if let Some(freq_offset) = self.freq_offset {
-            device
-                .set_gpc_clock_vf_offset(freq_offset)
-                .expect("Failed to set GPU frequency offset");
+            let old = device.gpc_clock_vf_offset().ok();
+            let freq_offset = freq_offset
+                .resolve(old)
+                .unwrap_or_else(|e| panic!("Failed to resolve GPU frequency offset: {}", e));
+            let freq_offset =
+                offset::clamp_offset(device, Clock::Graphics, PerformanceState::Zero, freq_offset);
+            match retry(|| device.set_gpc_clock_vf_offset(freq_offset)) {
+                Err(NvmlError::NotSupported) if self.legacy_fallback => {
+                    legacy::set_attribute(device, legacy::GRAPHICS_CLOCK_OFFSET, freq_offset)
+                        .expect("Failed to set GPU frequency offset through nvidia-settings")
+                }
+                #[cfg(feature = "windows")]
+                Err(NvmlError::NotSupported) => {
+                    nvapi::set_offset(device, nvapi::Domain::Graphics, freq_offset)
+                        .expect("Failed to set GPU frequency offset through NVAPI")
+                }
+                result => result.expect_hint("Failed to set GPU frequency offset"),
+            }
+            journal.record("freqOffset", old, freq_offset);
         }
 
         if let Some(mem_offset) = self.mem_offset {
-            device
-                .set_mem_clock_vf_offset(mem_offset)
-                .expect("Failed to set GPU memory frequency offset");
+            let old = device.mem_clock_vf_offset().ok();
+            let mem_offset = mem_offset
+                .resolve(old)
+                .unwrap_or_else(|e| panic!("Failed to resolve GPU memory frequency offset: {}", e));
+            let mem_offset =
+                offset::clamp_offset(device, Clock::Memory, PerformanceState::Zero, mem_offset);
+            match retry(|| device.set_mem_clock_vf_offset(mem_offset)) {
+                // The X driver takes the offset as a transfer rate, which is
+                // twice the memory clock
+                Err(NvmlError::NotSupported) if self.legacy_fallback => legacy::set_attribute(
+                    device,
+                    legacy::MEMORY_TRANSFER_RATE_OFFSET,
+                    mem_offset * 2,
+                )
+                .expect("Failed to set GPU memory frequency offset through nvidia-settings"),
+                #[cfg(feature = "windows")]
+                Err(NvmlError::NotSupported) => {
+                    nvapi::set_offset(device, nvapi::Domain::Memory, mem_offset)
+                        .expect("Failed to set GPU memory frequency offset through NVAPI")
+                }
+                result => result.expect_hint("Failed to set GPU memory frequency offset"),
+            }
+            journal.record("memOffset", old, mem_offset);
+        }
+
+        if let Some(PstateOffsets(offsets)) = &self.freq_offset_pstate {
+            let old: Option<Vec<_>> = offsets
+                .iter()
+                .map(|(pstate, _)| {
+                    device
+                        .clock_offset(Clock::Graphics, *pstate)
+                        .ok()
+                        .map(|offset| (*pstate, offset.clock_offset_mhz))
+                })
+                .collect();
+            let offsets: Vec<_> = offsets
+                .iter()
+                .map(|(pstate, offset)| {
+                    let clamped = offset::clamp_offset(device, Clock::Graphics, *pstate, *offset);
+                    (*pstate, clamped)
+                })
+                .collect();
+            for (pstate, offset) in &offsets {
+                retry(|| device.set_clock_offset(Clock::Graphics, *pstate, *offset))
+                    .unwrap_or_else(|e| {
+                        panic!(
+                            "Failed to set GPU frequency offset for {}: {}",
+                            pstate_name(*pstate),
+                            hint::describe(&e)
+                        )
+                    });
+            }
+            journal.record(
+                "freqOffsetPstate",
+                old.map(PstateOffsets),
+                PstateOffsets(offsets),
+            );
         }
 
         if let Some(limit) = self.power_limit {
-            device
-                .set_power_management_limit(limit)
-                .expect("Failed to set GPU power limit");
+            let limit = limit
+                .resolve(device)
+                .unwrap_or_else(|e| panic!("Failed to resolve GPU power limit: {}", e));
+            let limit = power::clamp_power_limit(device, limit);
+            let old = device.power_management_limit().ok();
+            retry(|| device.set_power_management_limit(limit))
+                .expect_hint("Failed to set GPU power limit");
+            journal.record("powerLimit", old, limit);
+        }
+
+        if let Some(temp_limit) = self.temp_limit {
+            let old = thermal::temp_limit(device).ok().flatten();
+            thermal::set_temp_limit(device, temp_limit)
+                .unwrap_or_else(|e| panic!("Failed to set GPU target temperature: {}", e));
+            journal.record("tempLimit", old, temp_limit);
         }
 
         if let (Some(min_clock), Some(max_clock)) = (self.min_clock, self.max_clock) {
-            device
-                .set_gpu_locked_clocks(
+            retry(|| {
+                device.set_gpu_locked_clocks(
                     nvml_wrapper::enums::device::GpuLockedClocksSetting::Numeric {
                         min_clock_mhz: min_clock,
                         max_clock_mhz: max_clock,
                     },
                 )
-                .expect("Failed to set GPU min and max clocks");
+            })
+            .expect_hint("Failed to set GPU min and max clocks");
+            // NVML can't read locked clocks back, so the old value is unknown
+            journal.record("minClock", None::<u32>, min_clock);
+            journal.record("maxClock", None::<u32>, max_clock);
         }
 
         if let (Some(min_mem_clock), Some(max_mem_clock)) = (self.min_mem_clock, self.max_mem_clock)
         {
-            device
-                .set_mem_locked_clocks(min_mem_clock, max_mem_clock)
-                .expect("Failed to set GPU min and max memory clocks");
+            retry(|| device.set_mem_locked_clocks(min_mem_clock, max_mem_clock))
+                .expect_hint("Failed to set GPU min and max memory clocks");
+            journal.record("minMemClock", None::<u32>, min_mem_clock);
+            journal.record("maxMemClock", None::<u32>, max_mem_clock);
         }
-    }
-}
 
-#[derive(Deserialize)]
-struct Config {
-    sets: HashMap<u32, Sets>,
+        if let Some(ecc) = self.ecc {
+            let old = device.is_ecc_enabled().ok().map(|ecc| {
+                if ecc.pending_enabled {
+                    Toggle::On
+                } else {
+                    Toggle::Off
+                }
+            });
+            retry(|| device.set_ecc(ecc.enabled())).expect_hint("Failed to set GPU ECC mode");
+            journal.record("ecc", old, ecc);
+        }
+
+        if let Some(mig) = self.mig {
+            let old = device.mig_mode().ok().map(|mode| {
+                if mode.pending != 0 {
+                    Toggle::On
+                } else {
+                    Toggle::Off
+                }
+            });
+            mig::set_mig_mode(device, mig.enabled())
+                .unwrap_or_else(|e| panic!("Failed to set GPU MIG mode: {}", e));
+            journal.record("mig", old, mig);
+        }
+
+        if let Some(FanSpeeds(speeds)) = &self.fan {
+            let old: Option<Vec<_>> = speeds
+                .iter()
+                .map(|(fan, _)| device.fan_speed(*fan).ok().map(|speed| (*fan, speed)))
+                .collect();
+            // Recorded so undo hands the fans back to the driver if they
+            // were on auto before, instead of pinning the old speed
+            let old_policy = speeds
+                .first()
+                .and_then(|(fan, _)| FanPolicy::read(device, *fan).ok());
+            for (fan, speed) in speeds {
+                retry(|| fan::set_manual_speed(device, *fan, *speed)).unwrap_or_else(|e| {
+                    panic!(
+                        "Failed to set GPU fan {} speed: {}",
+                        fan,
+                        hint::describe(&e)
+                    )
+                });
+            }
+            journal.record("fan", old.map(FanSpeeds), FanSpeeds(speeds.clone()));
+            if self.fan_policy.is_none() {
+                journal.record("fanPolicy", old_policy, FanPolicy::Manual);
+            }
+        }
+
+        if let Some(policy) = self.fan_policy {
+            let num_fans = device.num_fans().expect("Failed to get GPU fan count");
+            let old = FanPolicy::read(device, 0).ok();
+            for fan in 0..num_fans {
+                retry(|| policy.apply(device, fan)).unwrap_or_else(|e| {
+                    panic!(
+                        "Failed to set GPU fan {} control policy: {}",
+                        fan,
+                        hint::describe(&e)
+                    )
+                });
+            }
+            journal.record("fanPolicy", old, policy);
+        }
+
+        if let Some(mode) = self.compute_mode {
+            let old = device
+                .compute_mode()
+                .ok()
+                .and_then(ComputeModeArg::from_mode);
+            retry(|| device.set_compute_mode(mode.into()))
+                .expect_hint("Failed to set GPU compute mode");
+            journal.record("computeMode", old, mode);
+        }
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
+    color::init(cli.color);
+    retry::init(cli.retries, Duration::from_millis(cli.retry_backoff));
+    // A pkexec'd run never looks for a config through the caller's
+    // environment, without --file it's the system config
+    let config_path = match &cli.file {
+        None if polkit::under_pkexec() => PathBuf::from(config::SYSTEM_CONFIG_PATH),
+        file => config::config_path(file.as_deref()),
+    };
+    if polkit::under_pkexec() {
+        if let Err(e) = check_pkexec(&cli, &config_path) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 
     match &cli.command {
-        Some(Commands::Set { index, sets }) => {
-            escalate_permissions().expect("Failed to escalate permissions");
+        Some(Commands::Set {
+            index,
+            matching,
+            stdin,
+            test,
+            save,
+            sets,
+        }) => {
+            escalate_permissions(cli.no_escalate, true).expect("Failed to escalate permissions");
+
+            let sets = if *stdin {
+                read_stdin_sets(sets)
+            } else {
+                sets.clone()
+            };
+            if *save && sets.is_relative() {
+                panic!("--save needs absolute values, not changes like +15");
+            }
+            if polkit::under_pkexec() && sets.runs_commands() {
+                eprintln!("Hooks and alert commands can't be set under pkexec, use sudo or doas");
+                std::process::exit(1);
+            }
 
-            sudo2::escalate_if_needed()
-                .or_else(|_| sudo2::doas())
-                .or_else(|_| sudo2::pkexec())
-                .expect("Failed to escalate privileges");
+            let _lock = ApplyLock::acquire().expect("Failed to acquire apply lock");
+            let nvml = retry(Nvml::init).expect_hint("Failed to initialize NVML");
+            // Root after the escalation above
+            doctor::warn_blockers(true);
 
-            let nvml = Nvml::init().expect("Failed to initialize NVML");
+            let mut indices = index.clone();
+            if let Some(pattern) = matching {
+                let count = nvml.device_count().expect("Failed to get GPU count");
+                let matched: Vec<u32> = (0..count)
+                    .filter(|index| {
+                        nvml.device_by_index(*index)
+                            .and_then(|device| device.name())
+                            .is_ok_and(|name| config::name_matches(pattern, &name))
+                    })
+                    .collect();
+                if matched.is_empty() {
+                    panic!("No GPU name matches {}", pattern);
+                }
+                indices.extend(matched);
+            }
+            indices.sort_unstable();
+            indices.dedup();
 
-            let mut device = nvml.device_by_index(*index).expect("Failed to get GPU");
+            let mut devices: Vec<_> = indices
+                .iter()
+                .map(|index| {
+                    retry(|| nvml.device_by_index(*index))
+                        .unwrap_or_else(|e| panic!("Failed to get GPU {}: {:?}", index, e))
+                })
+                .collect();
 
-            sets.apply(&mut device);
+            for device in &mut devices {
+                if cli.confirm_required {
+                    watchdog::arm(device).expect("Failed to arm the confirmation watchdog");
+                }
+                if let Err(e) = sets.apply(device, Source::Cli) {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
             println!("Successfully set GPU parameters.");
+
+            if *save {
+                for (device, index) in devices.iter().zip(&indices) {
+                    let uuid = device.uuid().expect("Failed to get GPU UUID");
+                    config::save_entry(&config_path, *index, &uuid, sets.settings())
+                        .unwrap_or_else(|e| panic!("{}", e));
+                }
+                println!("Saved to {}.", config_path.display());
+            }
+
+            if let Some(seconds) = test {
+                signal::catch_interrupt();
+                // Let the daemon and other invocations in while the test runs
+                drop(_lock);
+                println!(
+                    "Testing for {} seconds, press Ctrl-C to revert early.",
+                    seconds
+                );
+                let deadline = Instant::now() + Duration::from_secs(*seconds);
+                while Instant::now() < deadline && !signal::interrupted() {
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+
+                let _lock = ApplyLock::acquire().expect("Failed to acquire apply lock");
+                for device in &mut devices {
+                    let uuid = device.uuid().expect("Failed to get GPU UUID");
+                    let changes =
+                        history::last_apply(&uuid).expect("Failed to read change history");
+                    restore(device, &changes);
+                }
+                println!("Test over, restored the previous GPU parameters.");
+            }
         }
-        Some(Commands::Get { index }) => {
-            let nvml = Nvml::init().expect("Failed to initialize NVML");
-            let device = nvml.device_by_index(*index).expect("Failed to get GPU");
+        Some(Commands::Get { index, all, field }) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
 
-            let freq_offset = device.gpc_clock_vf_offset();
-            match freq_offset {
-                Ok(freq_offset) => println!("GPU core clock offset: {} MHz", freq_offset),
-                Err(e) => eprintln!("Failed to get GPU core clock offset: {:?}", e),
+            if let (Some(field), Some(index)) = (field, index) {
+                let device = nvml.device_by_index(*index).expect("Failed to get GPU");
+                let report = device_report(*index, &device);
+                match report_field(&report, field) {
+                    Ok(value) => println!("{}", value),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                }
+                return;
             }
 
-            let mem_offset = device.mem_clock_vf_offset();
-            match mem_offset {
-                Ok(mem_offset) => println!("GPU memory clock offset: {} MHz", mem_offset),
-                Err(e) => eprintln!("Failed to get GPU memory clock offset: {:?}", e),
+            let indices = match index {
+                Some(index) if !*all => vec![*index],
+                _ => (0..nvml.device_count().expect("Failed to get GPU count")).collect(),
+            };
+            if cli.format != OutputFormat::Text {
+                let reports: Vec<_> = indices
+                    .iter()
+                    .map(|index| {
+                        let device = nvml.device_by_index(*index).expect("Failed to get GPU");
+                        device_report(*index, &device)
+                    })
+                    .collect();
+                match (*all, reports.as_slice()) {
+                    (false, [report]) => output::print(cli.format, report),
+                    _ => output::print(cli.format, &reports),
+                }
+                return;
             }
 
-            let power_limit = device.enforced_power_limit();
-            match power_limit {
-                Ok(power_limit) => println!("GPU power limit: {} W", power_limit / 1000),
-                Err(e) => eprintln!("Failed to get GPU power limit: {:?}", e),
+            for index in indices {
+                let device = nvml.device_by_index(index).expect("Failed to get GPU");
+                if *all {
+                    let name = device.name().unwrap_or_else(|_| "unknown".to_string());
+                    if index > 0 {
+                        println!();
+                    }
+                    println!("GPU {}: {}", index, name);
+                }
+                print_device(&device);
             }
         }
+        Some(Commands::Status) => {
+            let config = match Config::load(&config_path) {
+                Ok(config) => Some(config),
+                Err(_) if !Config::exists(&config_path) => None,
+                Err(e) => {
+                    eprintln!("{}, not comparing against it", e);
+                    None
+                }
+            };
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            status::run(&nvml, config.as_ref(), cli.format);
+        }
         None => {
-            let Ok(config_file) = std::fs::read_to_string(cli.file) else {
+            if !Config::exists(&config_path) {
                 panic!("Configuration file not found and no valid arguments were provided. Run `nvidia_oc --help` for more information.");
+            }
+            let config = Config::load(&config_path).unwrap_or_else(|e| panic!("{}", e));
+
+            escalate_permissions(cli.no_escalate, false).expect("Failed to escalate permissions");
+
+            let _lock = ApplyLock::acquire().expect("Failed to acquire apply lock");
+            let nvml = retry(Nvml::init).expect_hint("Failed to initialize NVML");
+            // Root after the escalation above
+            doctor::warn_blockers(true);
+
+            let reverted = watchdog::revert_unconfirmed(&nvml);
+            let devices = config.devices(&nvml).expect("Failed to get GPUs");
+            let mut failed = false;
+            for (mut device, key, sets) in devices {
+                if device.uuid().is_ok_and(|uuid| reverted.contains(&uuid)) {
+                    continue;
+                }
+                if cli.confirm_required {
+                    watchdog::arm(&device).expect("Failed to arm the confirmation watchdog");
+                }
+                if let Err(e) = sets.apply(&mut device, Source::Config) {
+                    eprintln!("Config entry {}: {}", key, e);
+                    failed = true;
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
+            println!("Successfully set GPU parameters.");
+        }
+        Some(Commands::Daemon {
+            interval,
+            reapply_interval,
+            hwmon,
+            socket,
+        }) => {
+            let config = Config::load(&config_path).unwrap_or_else(|e| panic!("{}", e));
+
+            escalate_permissions(cli.no_escalate, false).expect("Failed to escalate permissions");
+            watchdog::reset_on_panic();
+
+            if *socket {
+                socket::spawn().expect("Failed to open the query socket");
+            }
+            daemon::run(
+                &config_path,
+                config,
+                Duration::from_secs(*interval),
+                reapply_interval.map(|minutes| Duration::from_secs(minutes * 60)),
+                *hwmon,
+            );
+        }
+        Some(Commands::Init) => {
+            let path = match &cli.file {
+                Some(file) => PathBuf::from(file),
+                None => PathBuf::from(config::SYSTEM_CONFIG_PATH),
             };
+            // Only the system config needs root, a --file elsewhere stays user owned
+            if path == Path::new(config::SYSTEM_CONFIG_PATH) {
+                escalate_permissions(cli.no_escalate, false)
+                    .expect("Failed to escalate permissions");
+            }
+
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            init::run(&nvml, &path);
+        }
+        Some(Commands::Export { format }) => {
+            let config = Config::load(&config_path).unwrap_or_else(|e| panic!("{}", e));
+            export::run(&config, *format);
+        }
+        Some(Commands::Import { from, path, index }) => {
+            import::run(*from, path.as_deref(), *index);
+        }
+        Some(Commands::Diff) => {
+            let config = Config::load(&config_path).unwrap_or_else(|e| panic!("{}", e));
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
 
-            escalate_permissions().expect("Failed to escalate permissions");
+            let devices = config.devices(&nvml).expect("Failed to get GPUs");
+            let mut report = Vec::new();
+            for (device, key, sets) in devices {
+                let gpu = gpu_label(&device, key);
+                let drifts = drift::drift(&sets, &device);
+                if cli.format != OutputFormat::Text {
+                    report.push(serde_json::json!({ "gpu": gpu, "drifts": drifts }));
+                    continue;
+                }
+                if drifts.is_empty() {
+                    println!(
+                        "GPU {} {}",
+                        gpu,
+                        paint("matches the config.", Severity::Good)
+                    );
+                }
+                for drift in drifts {
+                    let severity = if drift.actual.starts_with("unreadable") {
+                        Severity::Bad
+                    } else {
+                        Severity::Warning
+                    };
+                    println!(
+                        "GPU {} {}: configured {}, actual {}",
+                        gpu,
+                        drift.parameter,
+                        drift.configured,
+                        paint(&drift.actual, severity)
+                    );
+                }
+            }
+            output::print(cli.format, &report);
+        }
+        Some(Commands::Verify) => {
+            let config = Config::load(&config_path).unwrap_or_else(|e| panic!("{}", e));
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            // What the daemon applies right now
+            let on_battery = battery::on_battery() == Some(true);
 
-            let config: Config =
-                serde_json::from_str(&config_file).expect("Invalid configuration file");
+            let devices = config.devices(&nvml).expect("Failed to get GPUs");
+            let mut gpus = Vec::new();
+            for (device, key, sets) in devices {
+                let sets = if on_battery {
+                    battery::battery_sets(&sets).unwrap_or(sets)
+                } else {
+                    sets
+                };
+                gpus.push(serde_json::json!({
+                    "gpu": gpu_label(&device, key),
+                    "uuid": device.uuid().ok(),
+                    "drifts": drift::drift(&sets, &device),
+                }));
+            }
+            let tuned = gpus
+                .iter()
+                .all(|gpu| gpu["drifts"].as_array().is_some_and(Vec::is_empty));
+            let report = serde_json::json!({ "tuned": tuned, "gpus": gpus });
+            // Always machine readable, text means JSON here
+            let format = match cli.format {
+                OutputFormat::Yaml => OutputFormat::Yaml,
+                _ => OutputFormat::Json,
+            };
+            output::print(format, &report);
+            if !tuned {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Processes { index }) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            let device = nvml.device_by_index(*index).expect("Failed to get GPU");
 
-            let nvml = Nvml::init().expect("Failed to initialize NVML");
+            let compute = device
+                .running_compute_processes()
+                .expect("Failed to get GPU compute processes");
+            let graphics = device
+                .running_graphics_processes()
+                .expect("Failed to get GPU graphics processes");
 
-            for (index, sets) in config.sets {
-                let mut device = nvml.device_by_index(index).expect("Failed to get GPU");
-                sets.apply(&mut device);
+            if compute.is_empty() && graphics.is_empty() {
+                println!("No processes are using GPU {}.", index);
+                return;
             }
-            println!("Successfully set GPU parameters.");
+
+            println!("{:>8}  {:<8}  {:>10}  NAME", "PID", "TYPE", "MEMORY");
+            let processes = compute
+                .iter()
+                .map(|p| (p, "compute"))
+                .chain(graphics.iter().map(|p| (p, "graphics")));
+            for (process, kind) in processes {
+                let memory = match process.used_gpu_memory {
+                    UsedGpuMemory::Used(bytes) => format!("{} MiB", bytes / 1024 / 1024),
+                    UsedGpuMemory::Unavailable => "n/a".to_string(),
+                };
+                let name = nvml
+                    .sys_process_name(process.pid, 256)
+                    .unwrap_or_else(|_| "?".to_string());
+                println!("{:>8}  {:<8}  {:>10}  {}", process.pid, kind, memory, name);
+            }
+        }
+        Some(Commands::Watch {
+            index,
+            interval,
+            trend,
+            averages,
+        }) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            let device = nvml.device_by_index(*index).expect("Failed to get GPU");
+
+            watch::run(
+                &device,
+                Duration::from_secs_f64(*interval),
+                *trend,
+                *averages,
+            );
+        }
+        Some(Commands::Analyze {
+            index,
+            duration,
+            interval,
+        }) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            let device = nvml.device_by_index(*index).expect("Failed to get GPU");
+
+            analyze::run(
+                *index,
+                &device,
+                Duration::from_secs(*duration),
+                Duration::from_secs_f64(*interval),
+                cli.format,
+            );
+        }
+        Some(Commands::Log {
+            index,
+            interval,
+            output,
+        }) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            let device = nvml.device_by_index(*index).expect("Failed to get GPU");
+
+            telemetry::log(
+                &device,
+                Duration::from_secs_f64(*interval),
+                output.as_deref(),
+            )
+            .expect("Failed to write telemetry log");
+        }
+        Some(Commands::Energy {
+            index,
+            interval,
+            duration,
+        }) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            let indices = match index {
+                Some(index) => vec![*index],
+                None => (0..nvml.device_count().expect("Failed to get GPU count")).collect(),
+            };
+
+            energy::run(
+                &nvml,
+                &indices,
+                Duration::from_secs(*interval),
+                duration.map(Duration::from_secs),
+            );
+        }
+        Some(Commands::History { index, limit }) => {
+            history::print(*index, *limit);
+        }
+        Some(Commands::Runs {
+            gpu,
+            driver,
+            limit,
+            db,
+        }) => {
+            let filter = store::Filter {
+                run: None,
+                gpu: gpu.clone(),
+                driver: driver.clone(),
+            };
+            results::print_runs(db.as_deref(), &filter, *limit, cli.format);
+        }
+        Some(Commands::Trials {
+            run,
+            gpu,
+            driver,
+            sort,
+            limit,
+            db,
+        }) => {
+            let filter = store::Filter {
+                run: *run,
+                gpu: gpu.clone(),
+                driver: driver.clone(),
+            };
+            results::print_trials(db.as_deref(), &filter, *sort, *limit, cli.format);
+        }
+        Some(Commands::Compare { run_a, run_b, db }) => {
+            compare::run(db.as_deref(), *run_a, *run_b, cli.format);
+        }
+        Some(Commands::Confirm) => {
+            escalate_permissions(cli.no_escalate, false).expect("Failed to escalate permissions");
+
+            if watchdog::confirm().expect("Failed to confirm settings") {
+                println!("Settings confirmed, they will be kept after reboot.");
+            } else {
+                println!("No settings are waiting for confirmation.");
+            }
+        }
+        Some(Commands::Undo { index }) => {
+            escalate_permissions(cli.no_escalate, false).expect("Failed to escalate permissions");
+
+            let _lock = ApplyLock::acquire().expect("Failed to acquire apply lock");
+            let nvml = retry(Nvml::init).expect_hint("Failed to initialize NVML");
+            let mut device = nvml.device_by_index(*index).expect("Failed to get GPU");
+            let uuid = device.uuid().expect("Failed to get GPU UUID");
+
+            let changes = history::last_apply(&uuid).expect("Failed to read change history");
+            let Some(last) = changes.first() else {
+                panic!("No recorded changes to undo for GPU {}", index);
+            };
+            println!(
+                "Undoing {} change(s) from {} UTC",
+                changes.len(),
+                history::format_timestamp(last.timestamp_ms)
+            );
+
+            restore(&mut device, &changes);
+            println!("Successfully restored GPU parameters.");
+        }
+        Some(Commands::Nvlink { index }) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            let indices = match index {
+                Some(index) => vec![*index],
+                None => (0..nvml.device_count().expect("Failed to get GPU count")).collect(),
+            };
+            for index in indices {
+                let device = nvml.device_by_index(index).expect("Failed to get GPU");
+                nvlink::print(index, &device);
+            }
+        }
+        Some(Commands::Topology) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            topology::run(&nvml, cli.format);
+        }
+        Some(Commands::Dump { index }) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            dump::run(&nvml, *index, cli.format);
+        }
+        Some(Commands::Mig { index }) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            let indices = match index {
+                Some(index) => vec![*index],
+                None => (0..nvml.device_count().expect("Failed to get GPU count")).collect(),
+            };
+            for index in indices {
+                let device = nvml.device_by_index(index).expect("Failed to get GPU");
+                mig::print(index, &device);
+            }
+        }
+        Some(Commands::GpuReset { index }) => {
+            escalate_permissions(cli.no_escalate, false).expect("Failed to escalate permissions");
+
+            let bus_id = {
+                let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+                let device = nvml.device_by_index(*index).expect("Failed to get GPU");
+
+                let compute = device
+                    .running_compute_processes()
+                    .expect("Failed to get GPU compute processes");
+                let graphics = device
+                    .running_graphics_processes()
+                    .expect("Failed to get GPU graphics processes");
+                if !compute.is_empty() || !graphics.is_empty() {
+                    let pids: Vec<String> = compute
+                        .iter()
+                        .chain(&graphics)
+                        .map(|p| p.pid.to_string())
+                        .collect();
+                    panic!(
+                        "Refusing to reset GPU {}: it is in use by process(es) {}",
+                        index,
+                        pids.join(", ")
+                    );
+                }
+
+                device
+                    .pci_info()
+                    .expect("Failed to get GPU PCI info")
+                    .bus_id
+                // NVML is shut down here so our own handle doesn't block the reset
+            };
+
+            // NVML has no public reset entry point, nvidia-smi uses a private one
+            let status = Command::new("nvidia-smi")
+                .args(["--gpu-reset", "-i", &bus_id])
+                .status()
+                .expect("Failed to run nvidia-smi");
+            if !status.success() {
+                panic!("nvidia-smi failed to reset GPU {}", index);
+            }
+            println!("Successfully reset GPU {}.", index);
+        }
+        Some(Commands::UdevRule { install }) => {
+            let exe = std::env::current_exe().expect("Failed to locate the nvidia_oc binary");
+            let rule = udev_rule(&exe.to_string_lossy(), &config_path.to_string_lossy());
+
+            if *install {
+                escalate_permissions(cli.no_escalate, false)
+                    .expect("Failed to escalate permissions");
+
+                std::fs::write(UDEV_RULE_PATH, rule).expect("Failed to write udev rule");
+                Command::new("udevadm")
+                    .args(["control", "--reload-rules"])
+                    .status()
+                    .expect("Failed to reload udev rules");
+                println!("Installed udev rule to {}.", UDEV_RULE_PATH);
+            } else {
+                print!("{}", rule);
+            }
+        }
+        Some(Commands::SleepHook { install }) => {
+            let exe = std::env::current_exe().expect("Failed to locate the nvidia_oc binary");
+            let hook = sleep_hook(&exe.to_string_lossy(), &config_path.to_string_lossy());
+
+            if *install {
+                escalate_permissions(cli.no_escalate, false)
+                    .expect("Failed to escalate permissions");
+
+                std::fs::write(SLEEP_HOOK_PATH, hook).expect("Failed to write sleep hook");
+                std::fs::set_permissions(SLEEP_HOOK_PATH, std::fs::Permissions::from_mode(0o755))
+                    .expect("Failed to make sleep hook executable");
+                println!("Installed sleep hook to {}.", SLEEP_HOOK_PATH);
+            } else {
+                print!("{}", hook);
+            }
+        }
+        Some(Commands::Coolbits { value, install }) => {
+            match coolbits::current() {
+                Some((path, current)) => println!(
+                    "Coolbits {} set in {}, unlocking: {}",
+                    current,
+                    path.display(),
+                    coolbits::describe(current).join(", ")
+                ),
+                None => println!("Coolbits isn't set in the Xorg config."),
+            }
+
+            if *install {
+                escalate_permissions(cli.no_escalate, false)
+                    .expect("Failed to escalate permissions");
+
+                let (path, backup) =
+                    coolbits::install(*value).expect("Failed to write Xorg config");
+                if let Some(backup) = backup {
+                    println!("Backed up {} to {}.", path.display(), backup.display());
+                }
+                println!(
+                    "Set Coolbits to {} in {}, restart X for it to take effect.",
+                    value,
+                    path.display()
+                );
+            } else {
+                println!();
+                print!("{}", coolbits::snippet(*value));
+            }
+        }
+        Some(Commands::Caps) => {
+            let nvml = Nvml::init().expect_hint("Failed to initialize NVML");
+            caps::run(
+                &nvml,
+                sudo2::running_as_root() || has_admin_capability(),
+                cli.format,
+            );
+        }
+        Some(Commands::Doctor) => {
+            let root = sudo2::running_as_root() || has_admin_capability();
+            if !doctor::run(root, cli.format) {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Polkit { group, install }) => {
+            let exe = std::env::current_exe().expect("Failed to locate the nvidia_oc binary");
+            let exe = exe.to_string_lossy();
+
+            if *install {
+                escalate_permissions(cli.no_escalate, false)
+                    .expect("Failed to escalate permissions");
+
+                polkit::install(&exe, group).expect("Failed to install polkit policy");
+                println!(
+                    "Installed polkit policy {}, members of {} can now run `pkexec {} set`.",
+                    polkit::ACTION_ID,
+                    group,
+                    exe
+                );
+            } else {
+                print!("{}", polkit::policy(&exe));
+                println!();
+                print!("{}", polkit::rules(group));
+            }
+        }
+        Some(Commands::Uninstall { purge }) => {
+            escalate_permissions(cli.no_escalate, false).expect("Failed to escalate permissions");
+
+            let _lock = ApplyLock::acquire().expect("Failed to acquire apply lock");
+            uninstall::run(*purge);
+            println!("Uninstalled, the nvidia_oc binary itself is left in place.");
+        }
+        Some(Commands::SelfUpdate { force }) => {
+            if self_update::needs_root() {
+                escalate_permissions(cli.no_escalate, false)
+                    .expect("Failed to escalate permissions");
+            }
+            self_update::run(*force).unwrap_or_else(|e| panic!("{}", e));
         }
         Some(Commands::Completion { shell }) => {
             generate_completion_script(*shell);
@@ -176,16 +1530,363 @@ fn main() {
     }
 }
 
-fn escalate_permissions() -> Result<(), Box<dyn std::error::Error>> {
-    if sudo2::running_as_root() {
+/// How diff and verify name the GPU an entry applies to. A name glob or the
+/// defaults can match several GPUs, those get the index as well.
+fn gpu_label(device: &Device, key: &config::ConfigKey) -> String {
+    match key {
+        config::ConfigKey::Name(_) | config::ConfigKey::Defaults => {
+            format!("{} ({})", device.index().unwrap_or_default(), key)
+        }
+        _ => key.to_string(),
+    }
+}
+
+/// Reapplies the old values recorded for the given changes
+fn restore(device: &mut Device, changes: &[history::Entry]) {
+    let mut previous = serde_json::Map::new();
+    for change in changes {
+        match change.parameter.as_str() {
+            // Locked clocks can't be read back, so undo unlocks them
+            "minClock" | "maxClock" => device
+                .reset_gpu_locked_clocks()
+                .expect("Failed to reset GPU locked clocks"),
+            "minMemClock" | "maxMemClock" => device
+                .reset_mem_locked_clocks()
+                .expect("Failed to reset GPU locked memory clocks"),
+            _ if change.old.is_null() => eprintln!(
+                "Previous {} is unknown, leaving it unchanged",
+                change.parameter
+            ),
+            parameter => {
+                previous.insert(parameter.to_string(), change.old.clone());
+            }
+        }
+    }
+
+    let sets: Sets = serde_json::from_value(serde_json::Value::Object(previous))
+        .expect("Failed to parse recorded settings");
+    sets.apply(device, Source::Undo)
+        .unwrap_or_else(|e| panic!("{}", e));
+}
+
+/// Reads settings as JSON from stdin, with the flags in `overrides` taking
+/// precedence over the values read
+fn read_stdin_sets(overrides: &Sets) -> Sets {
+    let mut settings: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_reader(io::stdin().lock()).expect("Failed to parse settings from stdin");
+
+    let overrides = serde_json::to_value(overrides).expect("Failed to serialize settings");
+    if let serde_json::Value::Object(overrides) = overrides {
+        for (key, value) in overrides {
+            // Flags that weren't given serialize as null or false
+            if !value.is_null() && value != serde_json::Value::Bool(false) {
+                settings.insert(key, value);
+            }
+        }
+    }
+
+    serde_json::from_value(serde_json::Value::Object(settings))
+        .expect("Failed to parse settings from stdin")
+}
+
+/// The readings `get` prints, for JSON and YAML output. Values that can't
+/// be read are null.
+fn device_report(index: u32, device: &Device) -> serde_json::Value {
+    let watts = |mw: u32| mw / 1000;
+    let constraints = device.power_management_limit_constraints().ok();
+    let pstate_offsets: serde_json::Map<_, _> = device
+        .supported_performance_states()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|pstate| {
+            let offset = device.clock_offset(Clock::Graphics, pstate).ok()?;
+            Some((pstate_name(pstate), offset.clock_offset_mhz.into()))
+        })
+        .collect();
+    let fans: Vec<_> = (0..device.num_fans().unwrap_or(0))
+        .map(|fan| {
+            serde_json::json!({
+                "speedPercent": device.fan_speed(fan).ok(),
+                "rpm": device.fan_speed_rpm(fan).ok(),
+                "policy": FanPolicy::read(device, fan).ok(),
+            })
+        })
+        .collect();
+    let capped_seconds: serde_json::Map<_, _> = violation_times(device)
+        .into_iter()
+        .map(|(name, time)| {
+            let seconds = time.violation_time as f64 / 1e9;
+            (name.to_string(), seconds.into())
+        })
+        .collect();
+    let link = pcie::PcieLink::read(device).ok();
+
+    serde_json::json!({
+        "index": index,
+        "name": device.name().ok(),
+        "uuid": device.uuid().ok(),
+        "vbiosVersion": device.vbios_version().ok(),
+        "boardPartNumber": device.board_part_number().ok(),
+        "coreClockOffsetMhz": device.gpc_clock_vf_offset().ok(),
+        "memClockOffsetMhz": device.mem_clock_vf_offset().ok(),
+        "pstateCoreClockOffsetsMhz": pstate_offsets,
+        "powerLimitW": device.enforced_power_limit().ok().map(watts),
+        "defaultPowerLimitW": device.power_management_limit_default().ok().map(watts),
+        "minPowerLimitW": constraints.as_ref().map(|c| watts(c.min_limit)),
+        "maxPowerLimitW": constraints.as_ref().map(|c| watts(c.max_limit)),
+        "targetTemperatureC": thermal::temp_limit(device).ok().flatten(),
+        "pcieLink": link.map(|link| serde_json::json!({
+            "gen": link.gen,
+            "maxGen": link.max_gen,
+            "width": link.width,
+            "maxWidth": link.max_width,
+        })),
+        "throttleReasons": device.current_throttle_reasons().ok().map(throttle_reason_names),
+        "cappedSeconds": capped_seconds,
+        "eccEnabled": device.is_ecc_enabled().ok().map(|ecc| ecc.currently_enabled),
+        "computeMode": device.compute_mode().ok().map(|mode| format!("{:?}", mode)),
+        "fans": fans,
+    })
+}
+
+/// Looks up a reading of [`device_report`] by name for `get --field`. Case
+/// and underscores don't matter and the unit suffix is optional, so
+/// `power_limit` finds `powerLimitW`. Strings print without quotes.
+fn report_field(report: &serde_json::Value, field: &str) -> Result<String, String> {
+    let normalize = |name: &str| name.replace('_', "").to_lowercase();
+    let field = normalize(field);
+    let object = report.as_object().cloned().unwrap_or_default();
+    let value = object
+        .iter()
+        .find(|(key, _)| {
+            let key = normalize(key);
+            key == field
+                || ["w", "mhz", "c"]
+                    .iter()
+                    .any(|unit| key.strip_suffix(unit) == Some(&field))
+        })
+        .map(|(_, value)| value)
+        .ok_or_else(|| {
+            let fields: Vec<&str> = object.keys().map(String::as_str).collect();
+            format!("Unknown field, available: {}", fields.join(", "))
+        })?;
+    match value {
+        serde_json::Value::Null => Err("Not available on this GPU".to_string()),
+        serde_json::Value::String(value) => Ok(value.clone()),
+        value => Ok(value.to_string()),
+    }
+}
+
+fn print_device(device: &Device) {
+    print_identity(device);
+
+    let freq_offset = device.gpc_clock_vf_offset();
+    #[cfg(feature = "windows")]
+    let freq_offset = freq_offset.or_else(|e| {
+        nvapi::offset(device, nvapi::Domain::Graphics)
+            .map_err(|nvapi_e| format!("{:?}, {}", e, nvapi_e))
+    });
+    match freq_offset {
+        Ok(freq_offset) => println!("GPU core clock offset: {} MHz", freq_offset),
+        Err(e) => eprintln!("Failed to get GPU core clock offset: {:?}", e),
+    }
+
+    print_pstate_offsets(device);
+
+    let mem_offset = device.mem_clock_vf_offset();
+    #[cfg(feature = "windows")]
+    let mem_offset = mem_offset.or_else(|e| {
+        nvapi::offset(device, nvapi::Domain::Memory)
+            .map_err(|nvapi_e| format!("{:?}, {}", e, nvapi_e))
+    });
+    match mem_offset {
+        Ok(mem_offset) => println!("GPU memory clock offset: {} MHz", mem_offset),
+        Err(e) => eprintln!("Failed to get GPU memory clock offset: {:?}", e),
+    }
+
+    power::print_power_limit(device);
+
+    thermal::print(device);
+
+    pcie::print(device);
+
+    match device.current_throttle_reasons() {
+        Ok(reasons) => {
+            let names = throttle_reason_names(reasons);
+            if names.is_empty() {
+                println!("GPU throttle reasons: none");
+            } else {
+                let names = names.join(", ");
+                let names = match throttle_severity(reasons) {
+                    Some(severity) => paint(&names, severity),
+                    None => names,
+                };
+                println!("GPU throttle reasons: {}", names);
+            }
+        }
+        Err(e) => eprintln!("Failed to get GPU throttle reasons: {:?}", e),
+    }
+
+    // Cumulative since the driver loaded, `watch` shows them per interval
+    for (name, time) in violation_times(device) {
+        println!(
+            "GPU time capped by {} policy: {:.1} s",
+            name,
+            time.violation_time as f64 / 1e9
+        );
+    }
+
+    match device.is_ecc_enabled() {
+        Ok(ecc) => {
+            let state = |enabled| if enabled { "enabled" } else { "disabled" };
+            if ecc.currently_enabled == ecc.pending_enabled {
+                println!("GPU ECC mode: {}", state(ecc.currently_enabled));
+            } else {
+                println!(
+                    "GPU ECC mode: {} ({} after reboot)",
+                    state(ecc.currently_enabled),
+                    state(ecc.pending_enabled)
+                );
+            }
+        }
+        // Consumer cards have no ECC, which isn't worth an error
+        Err(NvmlError::NotSupported) => {}
+        Err(e) => eprintln!("Failed to get GPU ECC mode: {:?}", e),
+    }
+
+    match device.compute_mode() {
+        Ok(mode) => println!("GPU compute mode: {:?}", mode),
+        Err(e) => eprintln!("Failed to get GPU compute mode: {:?}", e),
+    }
+
+    print_fans(device);
+}
+
+/// VBIOS and board part number, which tell apart board revisions of the same
+/// GPU. NVML has no query for the memory vendor.
+fn print_identity(device: &Device) {
+    match device.vbios_version() {
+        Ok(version) => println!("GPU VBIOS version: {}", version),
+        Err(e) => eprintln!("Failed to get GPU VBIOS version: {:?}", e),
+    }
+    match device.board_part_number() {
+        Ok(part_number) => println!("GPU board part number: {}", part_number),
+        // Many consumer boards have no part number in the InfoROM
+        Err(NvmlError::NotSupported) => {}
+        Err(e) => eprintln!("Failed to get GPU board part number: {:?}", e),
+    }
+}
+
+fn print_pstate_offsets(device: &Device) {
+    // Per-pstate offsets are only exposed by newer drivers, stay quiet otherwise
+    let Ok(pstates) = device.supported_performance_states() else {
+        return;
+    };
+
+    for pstate in pstates {
+        if let Ok(offset) = device.clock_offset(Clock::Graphics, pstate) {
+            println!(
+                "GPU core clock offset {}: {} MHz (allowed {} to {} MHz)",
+                pstate_name(pstate),
+                offset.clock_offset_mhz,
+                offset.min_clock_offset_mhz,
+                offset.max_clock_offset_mhz
+            );
+        }
+    }
+}
+
+fn print_fans(device: &Device) {
+    let num_fans = match device.num_fans() {
+        Ok(num_fans) => num_fans,
+        Err(e) => {
+            eprintln!("Failed to get GPU fan count: {:?}", e);
+            return;
+        }
+    };
+
+    for fan in 0..num_fans {
+        let speed = match device.fan_speed(fan) {
+            Ok(speed) => format!("{}%", speed),
+            Err(e) => {
+                eprintln!("Failed to get GPU fan {} speed: {:?}", fan, e);
+                continue;
+            }
+        };
+        // Not every board has a tachometer, so RPM is optional
+        let rpm = device
+            .fan_speed_rpm(fan)
+            .map(|rpm| format!(" ({} RPM)", rpm))
+            .unwrap_or_default();
+        let policy = match FanPolicy::read(device, fan) {
+            Ok(FanPolicy::Auto) => "auto",
+            Ok(FanPolicy::Manual) => "manual",
+            Err(_) => "unknown",
+        };
+        println!("GPU fan {}: {}{}, policy: {}", fan, speed, rpm, policy);
+    }
+}
+
+const UDEV_RULE_PATH: &str = "/etc/udev/rules.d/70-nvidia_oc.rules";
+
+/// The rule fires when the nvidia driver binds a PCI device (driver reload,
+/// GPU reset, switching back from VFIO) and when a device node appears.
+fn udev_rule(exe: &str, config: &str) -> String {
+    let run = format!("RUN+=\"{} --file {}\"", exe, config);
+    format!(
+        "# Generated by nvidia_oc, reapplies GPU settings when the driver (re)binds\n\
+         ACTION==\"bind\", SUBSYSTEM==\"pci\", DRIVER==\"nvidia\", {run}\n\
+         ACTION==\"add\", SUBSYSTEM==\"nvidia\", KERNEL==\"nvidia[0-9]*\", {run}\n",
+        run = run
+    )
+}
+
+const SLEEP_HOOK_PATH: &str = "/usr/lib/systemd/system-sleep/nvidia_oc";
+
+/// systemd-sleep runs every executable in its directory with `pre` before
+/// and `post` after suspend or hibernation. The driver drops offsets and
+/// limits while the GPU is powered down, so they are reapplied on `post`.
+fn sleep_hook(exe: &str, config: &str) -> String {
+    format!(
+        "#!/bin/sh\n\
+         # Generated by nvidia_oc, reapplies GPU settings after suspend and hibernation\n\
+         case \"$1\" in\n    post) \"{}\" --file \"{}\" ;;\nesac\n",
+        exe, config
+    )
+}
+
+/// Only `set`, and only with a config file no one but root can write, runs
+/// under pkexec, see [`polkit::under_pkexec`]
+fn check_pkexec(cli: &Cli, config_path: &Path) -> Result<(), String> {
+    let Some(Commands::Set { save, .. }) = &cli.command else {
+        return Err("Only `nvidia_oc set` runs under pkexec, use sudo or doas".to_string());
+    };
+    if cli.file.is_some() || *save {
+        polkit::check_root_only(config_path)?;
+    }
+    Ok(())
+}
+
+/// Re-runs the tool as root if needed. `pkexec` is only used for
+/// subcommands that run under it, see [`check_pkexec`].
+fn escalate_permissions(no_escalate: bool, pkexec: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if sudo2::running_as_root() || has_admin_capability() {
         return Ok(());
     }
 
-    if which::which("sudo").is_ok() {
+    if no_escalate {
+        return Err("Insufficient privileges: not running as root and escalation is disabled by --no-escalate/NVIDIA_OC_NO_ESCALATE.".into());
+    }
+
+    let has_pkexec = pkexec && which::which("pkexec").is_ok();
+    // With the policy installed pkexec may not need a password at all
+    if has_pkexec && polkit::installed() {
+        sudo2::pkexec()?;
+    } else if which::which("sudo").is_ok() {
         sudo2::escalate_if_needed()?;
     } else if which::which("doas").is_ok() {
         sudo2::doas()?;
-    } else if which::which("pkexec").is_ok() {
+    } else if has_pkexec {
         sudo2::pkexec()?;
     } else {
         return Err("Please install sudo, doas or pkexec and try again. Alternatively, run the program as root.".into());
@@ -194,6 +1895,25 @@ fn escalate_permissions() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// CAP_SYS_ADMIN, the capability the NVIDIA driver checks for privileged NVML
+/// calls (see capabilities(7) for the numbering)
+const CAP_SYS_ADMIN: u32 = 21;
+
+/// Whether the process holds CAP_SYS_ADMIN without being root, e.g. after
+/// `setcap cap_sys_admin+ep /usr/bin/nvidia_oc`. Device node group membership
+/// alone only grants read access, writes still need the capability.
+fn has_admin_capability() -> bool {
+    let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
+        return false;
+    };
+
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << CAP_SYS_ADMIN) != 0)
+}
+
 fn generate_completion_script<G: Generator>(gen: G) {
     let mut cmd = Cli::command();
     let name = cmd.get_name().to_string();
@@ -0,0 +1,75 @@
+use nvml_wrapper::Device;
+use std::process::Command;
+
+fn bus_id(device: &Device) -> Result<String, String> {
+    device
+        .pci_info()
+        .map(|info| info.bus_id)
+        .map_err(|e| format!("failed to get PCI info: {:?}", e))
+}
+
+/// Enables or disables MIG mode via nvidia-smi, NVML exposes the call but
+/// nvml-wrapper doesn't. The change stays pending until the GPU is reset.
+pub fn set_mig_mode(device: &Device, enabled: bool) -> Result<(), String> {
+    let mode = if enabled { "1" } else { "0" };
+    let status = Command::new("nvidia-smi")
+        .args(["-i", &bus_id(device)?, "-mig", mode])
+        .status()
+        .map_err(|e| format!("failed to run nvidia-smi: {}", e))?;
+    if !status.success() {
+        return Err(format!("nvidia-smi exited with {}", status));
+    }
+    Ok(())
+}
+
+/// Lists the MIG devices of a GPU as reported by `nvidia-smi -L`
+pub fn mig_devices(device: &Device) -> Result<Vec<String>, String> {
+    let output = Command::new("nvidia-smi")
+        .args(["-L", "-i", &bus_id(device)?])
+        .output()
+        .map_err(|e| format!("failed to run nvidia-smi: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("nvidia-smi exited with {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("MIG"))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Prints the current and pending MIG mode and the MIG devices, if any
+pub fn print(index: u32, device: &Device) {
+    let mode = match device.mig_mode() {
+        Ok(mode) => mode,
+        Err(e) => {
+            eprintln!("Failed to get GPU {} MIG mode: {:?}", index, e);
+            return;
+        }
+    };
+    let state = |mode| if mode != 0 { "enabled" } else { "disabled" };
+    if mode.current == mode.pending {
+        println!("GPU {} MIG mode: {}", index, state(mode.current));
+    } else {
+        println!(
+            "GPU {} MIG mode: {} ({} after GPU reset)",
+            index,
+            state(mode.current),
+            state(mode.pending)
+        );
+    }
+    if mode.current == 0 {
+        return;
+    }
+
+    match mig_devices(device) {
+        Ok(devices) if devices.is_empty() => println!("No MIG devices created."),
+        Ok(devices) => {
+            for mig_device in devices {
+                println!("  {}", mig_device);
+            }
+        }
+        Err(e) => eprintln!("Failed to list MIG devices: {}", e),
+    }
+}
@@ -0,0 +1,208 @@
+//! Clock offsets through NVAPI for Windows, where NVML's VF offset calls
+//! aren't available the same way. Everything else still goes through NVML,
+//! which the Windows driver ships as well.
+
+use libloading::Library;
+use nvml_wrapper::Device;
+use std::{ffi::c_void, mem, sync::OnceLock};
+
+const NVAPI_LIBRARY: &str = "nvapi64.dll";
+
+// Function ids handed to nvapi_QueryInterface
+const INITIALIZE: u32 = 0x0150_E828;
+const ENUM_PHYSICAL_GPUS: u32 = 0xE5AC_921F;
+const GPU_GET_BUS_ID: u32 = 0x1BE0_B8E5;
+const GPU_GET_PSTATES20: u32 = 0x6FF8_1213;
+const GPU_SET_PSTATES20: u32 = 0x0F4D_AE6B;
+
+const MAX_PHYSICAL_GPUS: usize = 64;
+const MAX_PSTATES: usize = 16;
+const MAX_CLOCKS: usize = 8;
+const MAX_BASE_VOLTAGES: usize = 4;
+
+type GpuHandle = *mut c_void;
+type QueryInterface = unsafe extern "C" fn(u32) -> *mut c_void;
+
+/// Clock domains of NV_GPU_PUBLIC_CLOCK_ID
+#[derive(Debug, Clone, Copy)]
+pub enum Domain {
+    Graphics = 0,
+    Memory = 4,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ParamDelta {
+    value: i32,
+    min: i32,
+    max: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ClockEntry {
+    domain_id: u32,
+    type_id: u32,
+    editable: u32,
+    freq_delta_khz: ParamDelta,
+    /// Single frequency or min/max frequency, voltage domain and voltages
+    data: [u32; 5],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct BaseVoltageEntry {
+    domain_id: u32,
+    editable: u32,
+    volt_uv: u32,
+    volt_delta_uv: ParamDelta,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Pstate {
+    pstate_id: u32,
+    editable: u32,
+    clocks: [ClockEntry; MAX_CLOCKS],
+    base_voltages: [BaseVoltageEntry; MAX_BASE_VOLTAGES],
+}
+
+/// NV_GPU_PERF_PSTATES20_INFO_V2
+#[repr(C)]
+struct Pstates20 {
+    version: u32,
+    editable: u32,
+    num_pstates: u32,
+    num_clocks: u32,
+    num_base_voltages: u32,
+    pstates: [Pstate; MAX_PSTATES],
+    num_over_voltages: u32,
+    over_voltages: [BaseVoltageEntry; MAX_BASE_VOLTAGES],
+}
+
+impl Pstates20 {
+    fn new() -> Box<Pstates20> {
+        // Plain integers all the way down, so all zeroes is a valid value
+        let mut info: Box<Pstates20> = Box::new(unsafe { mem::zeroed() });
+        info.version = mem::size_of::<Pstates20>() as u32 | (2 << 16);
+        info
+    }
+}
+
+struct Nvapi {
+    // Keeps the functions below loaded
+    _library: Library,
+    query: QueryInterface,
+}
+
+impl Nvapi {
+    fn load() -> Result<Nvapi, String> {
+        unsafe {
+            let library = Library::new(NVAPI_LIBRARY)
+                .map_err(|e| format!("Failed to load {}: {}", NVAPI_LIBRARY, e))?;
+            let query = *library
+                .get::<QueryInterface>(b"nvapi_QueryInterface\0")
+                .map_err(|e| format!("Failed to find nvapi_QueryInterface: {}", e))?;
+            let nvapi = Nvapi {
+                _library: library,
+                query,
+            };
+            let initialize: unsafe extern "C" fn() -> i32 = nvapi.function(INITIALIZE)?;
+            check("NvAPI_Initialize", initialize())?;
+            Ok(nvapi)
+        }
+    }
+
+    /// Looks up an NVAPI function, `F` has to match its signature
+    unsafe fn function<F: Copy>(&self, id: u32) -> Result<F, String> {
+        let pointer = (self.query)(id);
+        if pointer.is_null() {
+            return Err(format!("NVAPI function {:#010x} is not available", id));
+        }
+        Ok(mem::transmute_copy(&pointer))
+    }
+
+    /// Finds the NVAPI handle of an NVML device by its PCI bus
+    fn gpu(&self, device: &Device) -> Result<GpuHandle, String> {
+        let bus = device
+            .pci_info()
+            .map_err(|e| format!("Failed to get PCI info: {:?}", e))?
+            .bus;
+        unsafe {
+            let enumerate: unsafe extern "C" fn(*mut GpuHandle, *mut u32) -> i32 =
+                self.function(ENUM_PHYSICAL_GPUS)?;
+            let get_bus_id: unsafe extern "C" fn(GpuHandle, *mut u32) -> i32 =
+                self.function(GPU_GET_BUS_ID)?;
+
+            let mut handles = [std::ptr::null_mut(); MAX_PHYSICAL_GPUS];
+            let mut count = 0;
+            check(
+                "NvAPI_EnumPhysicalGPUs",
+                enumerate(handles.as_mut_ptr(), &mut count),
+            )?;
+            for handle in handles.into_iter().take(count as usize) {
+                let mut handle_bus = 0;
+                if get_bus_id(handle, &mut handle_bus) == 0 && handle_bus == bus {
+                    return Ok(handle);
+                }
+            }
+        }
+        Err(format!("No NVAPI GPU on PCI bus {}", bus))
+    }
+}
+
+fn check(function: &str, status: i32) -> Result<(), String> {
+    match status {
+        0 => Ok(()),
+        status => Err(format!("{} failed with status {}", function, status)),
+    }
+}
+
+fn nvapi() -> Result<&'static Nvapi, String> {
+    static NVAPI: OnceLock<Result<Nvapi, String>> = OnceLock::new();
+    NVAPI
+        .get_or_init(Nvapi::load)
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// Reads the P0 clock offset of a domain in MHz
+pub fn offset(device: &Device, domain: Domain) -> Result<i32, String> {
+    let nvapi = nvapi()?;
+    let gpu = nvapi.gpu(device)?;
+    let mut info = Pstates20::new();
+    unsafe {
+        let get: unsafe extern "C" fn(GpuHandle, *mut Pstates20) -> i32 =
+            nvapi.function(GPU_GET_PSTATES20)?;
+        check("NvAPI_GPU_GetPstates20", get(gpu, &mut *info))?;
+    }
+
+    info.pstates[..info.num_pstates as usize]
+        .iter()
+        .find(|pstate| pstate.pstate_id == 0)
+        .and_then(|pstate| {
+            pstate.clocks[..info.num_clocks as usize]
+                .iter()
+                .find(|clock| clock.domain_id == domain as u32)
+        })
+        .map(|clock| clock.freq_delta_khz.value / 1000)
+        .ok_or_else(|| format!("GPU has no editable {:?} clock in P0", domain))
+}
+
+/// Sets the P0 clock offset of a domain in MHz, the driver applies P0's
+/// offset to the whole VF curve like NVML does
+pub fn set_offset(device: &Device, domain: Domain, offset_mhz: i32) -> Result<(), String> {
+    let nvapi = nvapi()?;
+    let gpu = nvapi.gpu(device)?;
+    let mut info = Pstates20::new();
+    info.num_pstates = 1;
+    info.num_clocks = 1;
+    info.pstates[0].pstate_id = 0;
+    info.pstates[0].clocks[0].domain_id = domain as u32;
+    info.pstates[0].clocks[0].freq_delta_khz.value = offset_mhz * 1000;
+    unsafe {
+        let set: unsafe extern "C" fn(GpuHandle, *const Pstates20) -> i32 =
+            nvapi.function(GPU_SET_PSTATES20)?;
+        check("NvAPI_GPU_SetPstates20", set(gpu, &*info))
+    }
+}
@@ -0,0 +1,106 @@
+use nvml_wrapper::enum_wrappers::nv_link::ErrorCounter;
+use nvml_wrapper::enums::device::SampleValue;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::struct_wrappers::device::FieldValueSample;
+use nvml_wrapper::structs::device::FieldId;
+use nvml_wrapper::sys_exports::field_id::NVML_FI_DEV_NVLINK_SPEED_MBPS_COMMON;
+use nvml_wrapper::Device;
+
+/// NVML_NVLINK_MAX_LINKS, the most links any GPU has
+const MAX_LINKS: u32 = 18;
+
+const ERROR_COUNTERS: [(ErrorCounter, &str); 4] = [
+    (ErrorCounter::DlReplay, "replay"),
+    (ErrorCounter::DlRecovery, "recovery"),
+    (ErrorCounter::DlCrcFlit, "CRC flit"),
+    (ErrorCounter::DlCrcData, "CRC data"),
+];
+
+/// Speed shared by all active links in MB/s
+fn link_speed(device: &Device) -> Option<u64> {
+    let samples = device
+        .field_values_for(&[FieldId(NVML_FI_DEV_NVLINK_SPEED_MBPS_COMMON)])
+        .ok()?;
+    match samples.into_iter().next()? {
+        Ok(FieldValueSample {
+            value: Ok(value), ..
+        }) => match value {
+            SampleValue::U32(speed) => Some(speed as u64),
+            SampleValue::U64(speed) => Some(speed),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// PCI bus IDs at the far end of every active NVLink, once per link
+pub fn peer_bus_ids(device: &Device) -> Vec<String> {
+    (0..MAX_LINKS)
+        .filter_map(|link| {
+            let nvlink = device.link_wrapper_for(link);
+            if !nvlink.is_active().ok()? {
+                return None;
+            }
+            nvlink.remote_pci_info().ok().map(|pci| pci.bus_id)
+        })
+        .collect()
+}
+
+/// Prints state, version, peer and error counters of every NVLink of a GPU
+pub fn print(index: u32, device: &Device) {
+    let mut found = false;
+
+    for link in 0..MAX_LINKS {
+        let nvlink = device.link_wrapper_for(link);
+        let active = match nvlink.is_active() {
+            Ok(active) => active,
+            // Links past the last one the GPU has report these
+            Err(NvmlError::NotSupported | NvmlError::InvalidArg) => continue,
+            Err(e) => {
+                eprintln!("Failed to get GPU {} NVLink {} state: {:?}", index, link, e);
+                continue;
+            }
+        };
+        found = true;
+
+        if !active {
+            println!("GPU {} NVLink {}: inactive", index, link);
+            continue;
+        }
+
+        let version = nvlink
+            .version()
+            .map(|version| format!("v{}", version))
+            .unwrap_or_else(|_| "unknown version".to_string());
+        let peer = nvlink
+            .remote_pci_info()
+            .map(|info| info.bus_id)
+            .unwrap_or_else(|_| "unknown peer".to_string());
+        let errors: Vec<String> = ERROR_COUNTERS
+            .iter()
+            .filter_map(|(counter, name)| {
+                let count = nvlink.error_counter(*counter).ok()?;
+                Some(format!("{} {}", name, count))
+            })
+            .collect();
+
+        println!(
+            "GPU {} NVLink {}: active, {}, to {}, errors: {}",
+            index,
+            link,
+            version,
+            peer,
+            if errors.is_empty() {
+                "n/a".to_string()
+            } else {
+                errors.join(", ")
+            }
+        );
+    }
+
+    if !found {
+        println!("GPU {} has no NVLinks.", index);
+    } else if let Some(speed) = link_speed(device) {
+        println!("GPU {} NVLink speed: {} MB/s per link", index, speed);
+    }
+}
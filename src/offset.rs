@@ -0,0 +1,202 @@
+use crate::pstate::pstate_name;
+use nvml_wrapper::enum_wrappers::device::{Clock, PerformanceState};
+use nvml_wrapper::Device;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// A clock offset in MHz, either absolute or relative to the current offset.
+///
+/// `-100` sets the offset to -100 MHz as before, `+15` raises the current
+/// offset by 15 MHz and `-=15` lowers it, `+=15` works too. The config file
+/// takes a number or the same strings.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "RawOffset", into = "RawOffset")]
+pub enum Offset {
+    Absolute(i32),
+    Relative(i32),
+}
+
+impl Offset {
+    /// The offset to set given the current one, which relative offsets need
+    pub fn resolve(self, current: Option<i32>) -> Result<i32, String> {
+        match self {
+            Offset::Absolute(offset) => Ok(offset),
+            Offset::Relative(delta) => current
+                .map(|current| current.saturating_add(delta))
+                .ok_or_else(|| "the current offset couldn't be read".to_string()),
+        }
+    }
+}
+
+impl FromStr for Offset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let parse = |number: &str| {
+            number
+                .trim()
+                .parse::<i32>()
+                .map_err(|e| format!("invalid offset `{}`: {}", s, e))
+        };
+        if let Some(delta) = s.strip_prefix("+=").or_else(|| s.strip_prefix('+')) {
+            parse(delta).map(Offset::Relative)
+        } else if let Some(delta) = s.strip_prefix("-=") {
+            parse(delta).map(|delta| Offset::Relative(-delta))
+        } else {
+            parse(s).map(Offset::Absolute)
+        }
+    }
+}
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Offset::Absolute(offset) => write!(f, "{}", offset),
+            Offset::Relative(delta) if *delta < 0 => write!(f, "-={}", -delta),
+            Offset::Relative(delta) => write!(f, "+={}", delta),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum RawOffset {
+    Number(i32),
+    String(String),
+}
+
+impl TryFrom<RawOffset> for Offset {
+    type Error = String;
+
+    fn try_from(raw: RawOffset) -> Result<Self, Self::Error> {
+        match raw {
+            RawOffset::Number(offset) => Ok(Offset::Absolute(offset)),
+            RawOffset::String(offset) => offset.parse(),
+        }
+    }
+}
+
+impl From<Offset> for RawOffset {
+    fn from(offset: Offset) -> Self {
+        match offset {
+            Offset::Absolute(offset) => RawOffset::Number(offset),
+            Offset::Relative(_) => RawOffset::String(offset.to_string()),
+        }
+    }
+}
+
+fn clock_name(clock: Clock) -> &'static str {
+    match clock {
+        Clock::Memory => "Memory clock",
+        _ => "Core clock",
+    }
+}
+
+/// The offsets in MHz the driver allows for `clock` in `pstate`. `None`
+/// without the range query, or with an empty range as some drivers report
+/// for clocks they don't let you offset.
+fn range(device: &Device, clock: Clock, pstate: PerformanceState) -> Option<(i32, i32)> {
+    let range = device.clock_offset(clock, pstate).ok()?;
+    let (min, max) = (range.min_clock_offset_mhz, range.max_clock_offset_mhz);
+    (min <= max).then_some((min, max))
+}
+
+/// The clock offset in MHz closest to `offset` the driver allows, what
+/// [`clamp_offset`] applies. [`crate::drift`] compares against it, an out of
+/// range offset would show as drifted forever otherwise.
+pub fn in_range(device: &Device, clock: Clock, pstate: PerformanceState, offset: i32) -> i32 {
+    range(device, clock, pstate).map_or(offset, |(min, max)| offset.clamp(min, max))
+}
+
+/// Clamps a requested clock offset in MHz to the range the driver allows in
+/// `pstate`, warning when it had to. Out of range offsets otherwise fail with
+/// a bare "invalid argument" from NVML. The whole-curve VF offsets are
+/// checked against P0. Drivers without the range query pass offsets through.
+pub fn clamp_offset(device: &Device, clock: Clock, pstate: PerformanceState, offset: i32) -> i32 {
+    let Some((min, max)) = range(device, clock, pstate) else {
+        return offset;
+    };
+    let clamped = offset.clamp(min, max);
+    if clamped != offset {
+        eprintln!(
+            "{} offset {} MHz is outside the supported {} to {} MHz in {}, using {} MHz",
+            clock_name(clock),
+            offset,
+            min,
+            max,
+            pstate_name(pstate),
+            clamped
+        );
+    }
+    clamped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Offset, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_absolute_offsets() {
+        assert_eq!(parse("-100"), Ok(Offset::Absolute(-100)));
+        assert_eq!(parse("0"), Ok(Offset::Absolute(0)));
+        assert_eq!(parse(" 150 "), Ok(Offset::Absolute(150)));
+        assert_eq!(parse("-2147483648"), Ok(Offset::Absolute(i32::MIN)));
+    }
+
+    #[test]
+    fn parses_relative_offsets() {
+        assert_eq!(parse("+15"), Ok(Offset::Relative(15)));
+        assert_eq!(parse("+=15"), Ok(Offset::Relative(15)));
+        assert_eq!(parse("-=15"), Ok(Offset::Relative(-15)));
+        assert_eq!(parse("+= 15"), Ok(Offset::Relative(15)));
+        assert_eq!(parse("+2147483647"), Ok(Offset::Relative(i32::MAX)));
+    }
+
+    #[test]
+    fn rejects_invalid_offsets() {
+        for invalid in [
+            "",
+            "+",
+            "-=",
+            "abc",
+            "+abc",
+            "15MHz",
+            "1.5",
+            "2147483648",
+            "+2147483648",
+            "=15",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn resolves_against_the_current_offset() {
+        assert_eq!(Offset::Absolute(-100).resolve(None), Ok(-100));
+        assert_eq!(Offset::Relative(15).resolve(Some(100)), Ok(115));
+        assert_eq!(Offset::Relative(-15).resolve(Some(0)), Ok(-15));
+        assert_eq!(Offset::Relative(1).resolve(Some(i32::MAX)), Ok(i32::MAX));
+        assert!(Offset::Relative(15).resolve(None).is_err());
+    }
+
+    #[test]
+    fn config_takes_numbers_and_strings() {
+        let offset: Offset = serde_json::from_str("-100").unwrap();
+        assert_eq!(offset, Offset::Absolute(-100));
+        let offset: Offset = serde_json::from_str(r#""-=15""#).unwrap();
+        assert_eq!(offset, Offset::Relative(-15));
+        for offset in [
+            Offset::Absolute(-100),
+            Offset::Relative(15),
+            Offset::Relative(-15),
+        ] {
+            let json = serde_json::to_string(&offset).unwrap();
+            assert_eq!(serde_json::from_str::<Offset>(&json).unwrap(), offset);
+        }
+    }
+}
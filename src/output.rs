@@ -0,0 +1,76 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Human readable text
+    Text,
+    Json,
+    Yaml,
+}
+
+/// Prints a command's report as JSON or YAML. Text output is printed by each
+/// command itself, for it this prints nothing.
+pub fn print(format: OutputFormat, report: &impl Serialize) {
+    let value = serde_json::to_value(report).expect("Failed to serialize output");
+    match format {
+        OutputFormat::Text => {}
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&value).expect("Failed to serialize output")
+        ),
+        OutputFormat::Yaml => {
+            let mut yaml = String::new();
+            write_yaml(&mut yaml, &value, 0);
+            print!("{}", yaml);
+        }
+    }
+}
+
+/// Strings are written JSON quoted, which YAML reads as double quoted
+/// scalars, so no value can be mistaken for a number, bool or null
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Array(array) if array.is_empty() => "[]".to_string(),
+        Value::Object(object) if object.is_empty() => "{}".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn is_collection(value: &Value) -> bool {
+    match value {
+        Value::Array(array) => !array.is_empty(),
+        Value::Object(object) => !object.is_empty(),
+        _ => false,
+    }
+}
+
+/// Block style YAML, which is all the reports need and avoids a dependency
+fn write_yaml(out: &mut String, value: &Value, indent: usize) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (key, value) in object {
+                let key = Value::String(key.clone()).to_string();
+                if is_collection(value) {
+                    out.push_str(&format!("{}{}:\n", pad, key));
+                    write_yaml(out, value, indent + 2);
+                } else {
+                    out.push_str(&format!("{}{}: {}\n", pad, key, scalar(value)));
+                }
+            }
+        }
+        Value::Array(array) if !array.is_empty() => {
+            for value in array {
+                if is_collection(value) {
+                    out.push_str(&format!("{}-\n", pad));
+                    write_yaml(out, value, indent + 2);
+                } else {
+                    out.push_str(&format!("{}- {}\n", pad, scalar(value)));
+                }
+            }
+        }
+        other => out.push_str(&format!("{}{}\n", pad, scalar(other))),
+    }
+}
@@ -0,0 +1,67 @@
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Device;
+use std::fmt;
+
+/// Utilization in percent above which a lowered link generation counts as
+/// downtrained rather than idle power saving
+const BUSY_UTILIZATION: u32 = 50;
+
+/// Current and maximum PCIe link generation and width of a GPU
+pub struct PcieLink {
+    pub gen: u32,
+    pub max_gen: u32,
+    pub width: u32,
+    pub max_width: u32,
+}
+
+impl PcieLink {
+    pub fn read(device: &Device) -> Result<Self, NvmlError> {
+        Ok(Self {
+            gen: device.current_pcie_link_gen()?,
+            max_gen: device.max_pcie_link_gen()?,
+            width: device.current_pcie_link_width()?,
+            max_width: device.max_pcie_link_width()?,
+        })
+    }
+
+    /// Whether the link runs below what it's capable of. GPUs drop the link
+    /// generation when idle to save power, so a lower generation only counts
+    /// while the GPU is busy; a narrower link always does.
+    pub fn downtrained(&self, utilization: Option<u32>) -> bool {
+        let busy = utilization.is_some_and(|utilization| utilization >= BUSY_UTILIZATION);
+        self.width < self.max_width || (busy && self.gen < self.max_gen)
+    }
+}
+
+impl fmt::Display for PcieLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Gen{} x{}", self.gen, self.width)
+    }
+}
+
+/// Prints the link of a GPU for `get`, flagging it when downtrained
+pub fn print(device: &Device) {
+    let link = match PcieLink::read(device) {
+        Ok(link) => link,
+        Err(e) => {
+            eprintln!("Failed to get GPU PCIe link: {:?}", e);
+            return;
+        }
+    };
+    let utilization = device.utilization_rates().ok().map(|rates| rates.gpu);
+    println!(
+        "GPU PCIe link: {} (max Gen{} x{}){}",
+        link,
+        link.max_gen,
+        link.max_width,
+        if link.downtrained(utilization) {
+            ", downtrained, check the slot and riser"
+        } else {
+            ""
+        }
+    );
+
+    if let Ok(replays) = device.pcie_replay_counter() {
+        println!("GPU PCIe replays: {}", replays);
+    }
+}
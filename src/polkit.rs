@@ -0,0 +1,126 @@
+use std::{env, fs, io, os::unix::fs::MetadataExt, path::Path};
+
+pub const ACTION_ID: &str = "io.github.dreaming_codes.nvidia_oc";
+pub const POLICY_PATH: &str =
+    "/usr/share/polkit-1/actions/io.github.dreaming_codes.nvidia_oc.policy";
+pub const RULES_PATH: &str = "/etc/polkit-1/rules.d/50-nvidia_oc.rules";
+
+/// Lets `pkexec <exe>` run as root after admin authentication, which polkit
+/// remembers for a few minutes
+pub fn policy(exe: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE policyconfig PUBLIC "-//freedesktop//DTD PolicyKit Policy Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/PolicyKit/1/policyconfig.dtd">
+<!-- Generated by nvidia_oc -->
+<policyconfig>
+  <action id="{id}">
+    <description>Change NVIDIA GPU clocks, power limits and fans</description>
+    <message>Authentication is required to change GPU settings</message>
+    <defaults>
+      <allow_any>auth_admin</allow_any>
+      <allow_inactive>auth_admin</allow_inactive>
+      <allow_active>auth_admin_keep</allow_active>
+    </defaults>
+    <annotate key="org.freedesktop.policykit.exec.path">{exe}</annotate>
+  </action>
+</policyconfig>
+"#,
+        id = ACTION_ID,
+        exe = exe
+    )
+}
+
+/// Grants `pkexec nvidia_oc set` to members of `group` without asking for a
+/// password, anything else still needs admin authentication. The binary
+/// itself refuses everything but `set` under pkexec, see [`under_pkexec`].
+pub fn rules(group: &str) -> String {
+    format!(
+        "// Generated by nvidia_oc, lets {group} change GPU settings without a password\n\
+         polkit.addRule(function(action, subject) {{\n    \
+             if (action.id == \"{id}\" && subject.isInGroup(\"{group}\")) {{\n        \
+                 var args = (action.lookup(\"command_line\") || \"\").split(\" \");\n        \
+                 return args[1] == \"set\" ? polkit.Result.YES : polkit.Result.AUTH_ADMIN_KEEP;\n    \
+             }}\n\
+         }});\n",
+        id = ACTION_ID,
+        group = group
+    )
+}
+
+pub fn install(exe: &str, group: &str) -> io::Result<()> {
+    fs::write(POLICY_PATH, policy(exe))?;
+    fs::write(RULES_PATH, rules(group))?;
+    Ok(())
+}
+
+/// Whether `pkexec nvidia_oc` is set up, so escalation can prefer it
+pub fn installed() -> bool {
+    Path::new(POLICY_PATH).exists()
+}
+
+/// Whether pkexec started this process. With the rule from [`rules`] that
+/// can be any member of the group without a password, so such a run only
+/// sets GPU parameters: no other subcommand, no hooks or alert commands and
+/// no config file the user could write.
+pub fn under_pkexec() -> bool {
+    env::var_os("PKEXEC_UID").is_some()
+}
+
+/// Refuses a config file a pkexec'd run mustn't read or write: it and every
+/// directory above it, before and after resolving symlinks, has to be owned
+/// by root and not writable by group or others. The file itself may not
+/// exist yet.
+pub fn check_root_only(path: &Path) -> Result<(), String> {
+    if !path.is_absolute() {
+        return Err(format!("{} is not an absolute path", path.display()));
+    }
+    let resolved = match fs::canonicalize(path) {
+        Ok(resolved) => resolved,
+        Err(_) => {
+            let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                return Err(format!("{} is not a file", path.display()));
+            };
+            fs::canonicalize(parent)
+                .map_err(|e| format!("Failed to resolve {}: {}", parent.display(), e))?
+                .join(name)
+        }
+    };
+
+    for ancestor in path.ancestors().chain(resolved.ancestors()) {
+        let metadata = match fs::symlink_metadata(ancestor) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Failed to check {}: {}", ancestor.display(), e)),
+        };
+        // A symlink's own mode is always 777, its directory is what matters
+        let writable = !metadata.file_type().is_symlink() && metadata.mode() & 0o022 != 0;
+        if metadata.uid() != 0 || writable {
+            return Err(format!(
+                "{} must be owned by root and not writable by group or others under pkexec",
+                ancestor.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_set_skips_the_password() {
+        let rules = rules("wheel");
+        assert!(rules.contains(r#"subject.isInGroup("wheel")"#));
+        assert!(rules
+            .contains(r#"args[1] == "set" ? polkit.Result.YES : polkit.Result.AUTH_ADMIN_KEEP"#));
+    }
+
+    #[test]
+    fn refuses_config_files_others_can_write() {
+        assert!(check_root_only(Path::new("config.json")).is_err());
+        // World-writable, even with the sticky bit
+        assert!(check_root_only(&env::temp_dir().join("nvidia_oc.json")).is_err());
+    }
+}
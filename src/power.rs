@@ -0,0 +1,339 @@
+use nvml_wrapper::Device;
+use serde::{Deserialize, Serialize};
+use std::{fmt, process::Command, str::FromStr};
+
+/// A power limit, either in mW or as a percentage of the GPU's default limit.
+///
+/// Parsed from `250W`, `250000mW`, plain watts like `250`, or `80%`. The
+/// config file takes the same strings, a bare number there stays milliwatts
+/// as it always was. Plain numbers above 10000 are refused, they are almost
+/// certainly milliwatts. Percentages, at most 1000%, are resolved per GPU
+/// when applied, so one value suits different card models. A leading sign,
+/// as in `-20W`, adjusts the current limit instead.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "RawPowerLimit", into = "RawPowerLimit")]
+pub enum PowerLimit {
+    Milliwatts(u32),
+    Percent(u32),
+    /// Change to the current limit in mW
+    Relative(i64),
+}
+
+impl PowerLimit {
+    /// The limit in mW for this device
+    pub fn resolve(self, device: &Device) -> Result<u32, String> {
+        match self {
+            PowerLimit::Milliwatts(limit) => Ok(limit),
+            PowerLimit::Percent(percent) => {
+                let default = device
+                    .power_management_limit_default()
+                    .map_err(|e| format!("Failed to get default power limit: {:?}", e))?;
+                u32::try_from(default as u64 * percent as u64 / 100)
+                    .map_err(|_| format!("{}% of the default power limit is out of range", percent))
+            }
+            PowerLimit::Relative(delta) => {
+                let current = device
+                    .power_management_limit()
+                    .map_err(|e| format!("Failed to get current power limit: {:?}", e))?;
+                Ok((current as i64 + delta).clamp(0, u32::MAX as i64) as u32)
+            }
+        }
+    }
+}
+
+impl FromStr for PowerLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let sign = match s.chars().next() {
+            Some('+') => Some(1),
+            Some('-') => Some(-1),
+            _ => None,
+        };
+        if let Some(sign) = sign {
+            if s.ends_with('%') {
+                return Err(format!(
+                    "invalid power limit `{}`: relative limits take W or mW",
+                    s
+                ));
+            }
+            let delta = parse_milliwatts(s[1..].trim_start_matches('='))?;
+            return Ok(PowerLimit::Relative(sign * delta as i64));
+        }
+        match s.strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<u32>() {
+                Ok(percent) if percent <= MAX_PERCENT => Ok(PowerLimit::Percent(percent)),
+                Ok(_) => Err(format!(
+                    "invalid percentage `{}`: at most {}% of the default limit",
+                    s, MAX_PERCENT
+                )),
+                Err(e) => Err(format!("invalid percentage `{}`: {}", s, e)),
+            },
+            None => parse_milliwatts(s).map(PowerLimit::Milliwatts),
+        }
+    }
+}
+
+/// No GPU allows anywhere near this much of its default limit
+const MAX_PERCENT: u32 = 1000;
+
+/// Plain numbers above this can't be watts, they are mW written the way the
+/// flag took them before it read plain numbers as watts
+const MAX_PLAIN_WATTS: f64 = 10_000.0;
+
+/// Parses `250W`, `250000mW` or plain watts into mW
+fn parse_milliwatts(s: &str) -> Result<u32, String> {
+    let lower = s.to_lowercase();
+    let (number, scale) = match lower.strip_suffix("mw") {
+        Some(number) => (number, 1.0),
+        None => (lower.strip_suffix('w').unwrap_or(&lower), 1000.0),
+    };
+    let value = number
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| format!("invalid power limit `{}`: {}", s, e))?;
+    let plain = !lower.ends_with('w');
+    if plain && value > MAX_PLAIN_WATTS {
+        return Err(format!(
+            "power limit `{}` is too high for watts, plain numbers are W. Did you mean {}mW / {}?",
+            s,
+            number.trim(),
+            format_watts(value.min(u32::MAX as f64) as u32)
+        ));
+    }
+    if !(0.0..=u32::MAX as f64).contains(&(value * scale)) {
+        return Err(format!("power limit `{}` is out of range", s));
+    }
+    Ok((value * scale).round() as u32)
+}
+
+/// Formats mW as watts, with decimals only when needed
+pub fn format_watts(milliwatts: u32) -> String {
+    if milliwatts.is_multiple_of(1000) {
+        format!("{}W", milliwatts / 1000)
+    } else {
+        format!("{}W", milliwatts as f64 / 1000.0)
+    }
+}
+
+impl fmt::Display for PowerLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerLimit::Milliwatts(limit) => write!(f, "{}", format_watts(*limit)),
+            PowerLimit::Percent(percent) => write!(f, "{}%", percent),
+            PowerLimit::Relative(delta) => {
+                let sign = if *delta < 0 { '-' } else { '+' };
+                write!(f, "{}{}", sign, format_watts(delta.unsigned_abs() as u32))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum RawPowerLimit {
+    Number(u32),
+    String(String),
+}
+
+impl TryFrom<RawPowerLimit> for PowerLimit {
+    type Error = String;
+
+    fn try_from(raw: RawPowerLimit) -> Result<Self, Self::Error> {
+        match raw {
+            RawPowerLimit::Number(limit) => Ok(PowerLimit::Milliwatts(limit)),
+            RawPowerLimit::String(limit) => limit.parse(),
+        }
+    }
+}
+
+impl From<PowerLimit> for RawPowerLimit {
+    fn from(limit: PowerLimit) -> Self {
+        match limit {
+            PowerLimit::Milliwatts(limit) => RawPowerLimit::Number(limit),
+            PowerLimit::Percent(_) | PowerLimit::Relative(_) => {
+                RawPowerLimit::String(limit.to_string())
+            }
+        }
+    }
+}
+
+/// Whether the GPU is a mobile SKU, NVML has no form factor query so this goes
+/// by the marketing name, e.g. "NVIDIA GeForce RTX 4070 Laptop GPU"
+pub fn is_laptop(device: &Device) -> bool {
+    device.name().is_ok_and(|name| {
+        ["Laptop", "Mobile", "Max-Q"]
+            .iter()
+            .any(|marker| name.contains(marker))
+    })
+}
+
+/// Whether nvidia-powerd, which implements Dynamic Boost on laptops, is running
+pub fn dynamic_boost_active() -> bool {
+    Command::new("systemctl")
+        .args(["is-active", "--quiet", "nvidia-powerd"])
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// The power limit in mW closest to `limit` the device accepts, what
+/// [`clamp_power_limit`] applies. [`crate::drift`] compares against it, an
+/// out of range limit would show as drifted forever otherwise.
+pub fn in_range(device: &Device, limit: u32) -> u32 {
+    match device.power_management_limit_constraints() {
+        Ok(constraints) => limit.clamp(constraints.min_limit, constraints.max_limit),
+        Err(_) => limit,
+    }
+}
+
+/// Clamps a requested power limit in mW to what the device accepts, warning
+/// when it had to. Laptops report much narrower constraints than desktop
+/// cards, so this is where their "power limit rejected" errors came from.
+pub fn clamp_power_limit(device: &Device, limit: u32) -> u32 {
+    let clamped = in_range(device, limit);
+    if clamped != limit {
+        let bound = if clamped > limit {
+            "below the minimum"
+        } else {
+            "above the maximum"
+        };
+        eprintln!(
+            "Requested power limit {} is {} {} for this device, using {}",
+            format_watts(limit),
+            bound,
+            format_watts(clamped),
+            format_watts(clamped)
+        );
+        if is_laptop(device) {
+            eprintln!(
+                "Laptop GPUs only allow small power limit changes, the total \
+                 graphics power is managed by the platform and Dynamic Boost"
+            );
+        }
+    }
+    clamped
+}
+
+/// Prints the enforced power limit along with its default and allowed range
+pub fn print_power_limit(device: &Device) {
+    match device.enforced_power_limit() {
+        Ok(power_limit) => println!("GPU power limit: {}", format_watts(power_limit)),
+        Err(e) => eprintln!("Failed to get GPU power limit: {:?}", e),
+    }
+
+    let default = device.power_management_limit_default();
+    let constraints = device.power_management_limit_constraints();
+    if let (Ok(default), Ok(constraints)) = (default, constraints) {
+        println!(
+            "GPU power limit range: {}-{} (default {})",
+            format_watts(constraints.min_limit),
+            format_watts(constraints.max_limit),
+            format_watts(default)
+        );
+    }
+
+    if is_laptop(device) {
+        if dynamic_boost_active() {
+            println!(
+                "GPU is a laptop GPU, Dynamic Boost (nvidia-powerd) may shift extra power to it beyond this limit"
+            );
+        } else {
+            println!("GPU is a laptop GPU, Dynamic Boost (nvidia-powerd) is not running");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<PowerLimit, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_watts_and_milliwatts() {
+        assert_eq!(parse("250W"), Ok(PowerLimit::Milliwatts(250_000)));
+        assert_eq!(parse("250w"), Ok(PowerLimit::Milliwatts(250_000)));
+        assert_eq!(parse("250.5W"), Ok(PowerLimit::Milliwatts(250_500)));
+        assert_eq!(parse("250000mW"), Ok(PowerLimit::Milliwatts(250_000)));
+        assert_eq!(parse(" 250000 MW "), Ok(PowerLimit::Milliwatts(250_000)));
+        assert_eq!(parse("0W"), Ok(PowerLimit::Milliwatts(0)));
+    }
+
+    #[test]
+    fn plain_numbers_are_watts() {
+        assert_eq!(parse("250"), Ok(PowerLimit::Milliwatts(250_000)));
+        assert_eq!(parse("0.5"), Ok(PowerLimit::Milliwatts(500)));
+        assert_eq!(parse("10000"), Ok(PowerLimit::Milliwatts(10_000_000)));
+    }
+
+    #[test]
+    fn plain_numbers_too_high_for_watts_suggest_units() {
+        let error = parse("250000").unwrap_err();
+        assert!(error.contains("Did you mean 250000mW / 250W?"), "{}", error);
+        let error = parse("10001").unwrap_err();
+        assert!(
+            error.contains("Did you mean 10001mW / 10.001W?"),
+            "{}",
+            error
+        );
+        // With a unit the value is what was asked for
+        assert_eq!(parse("10001W"), Ok(PowerLimit::Milliwatts(10_001_000)));
+    }
+
+    #[test]
+    fn rejects_values_outside_u32_milliwatts() {
+        assert_eq!(parse("4294967295mW"), Ok(PowerLimit::Milliwatts(u32::MAX)));
+        assert!(parse("4294967296mW").is_err());
+        assert_eq!(parse("4294967W"), Ok(PowerLimit::Milliwatts(4_294_967_000)));
+        assert!(parse("4294968W").is_err());
+        assert!(parse_milliwatts("NaN").is_err());
+    }
+
+    #[test]
+    fn parses_percentages() {
+        assert_eq!(parse("80%"), Ok(PowerLimit::Percent(80)));
+        assert_eq!(parse(" 100 %"), Ok(PowerLimit::Percent(100)));
+        assert_eq!(parse("0%"), Ok(PowerLimit::Percent(0)));
+        assert_eq!(parse("1000%"), Ok(PowerLimit::Percent(1000)));
+        for invalid in ["%", "abc%", "80.5%", "1001%", "4294967295%", "+10%", "-10%"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_relative_limits() {
+        assert_eq!(parse("+20W"), Ok(PowerLimit::Relative(20_000)));
+        assert_eq!(parse("-20W"), Ok(PowerLimit::Relative(-20_000)));
+        assert_eq!(parse("+=500mW"), Ok(PowerLimit::Relative(500)));
+        assert_eq!(parse("-=15"), Ok(PowerLimit::Relative(-15_000)));
+        assert!(parse("+20000").is_err());
+        assert!(parse("--5W").is_err());
+        assert!(parse("+").is_err());
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for invalid in ["", "W", "mW", "abc", "250kW", "250 W W"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn config_numbers_stay_milliwatts() {
+        let limit: PowerLimit = serde_json::from_str("250000").unwrap();
+        assert_eq!(limit, PowerLimit::Milliwatts(250_000));
+        let limit: PowerLimit = serde_json::from_str(r#""250""#).unwrap();
+        assert_eq!(limit, PowerLimit::Milliwatts(250_000));
+        assert!(serde_json::from_str::<PowerLimit>("-1").is_err());
+    }
+
+    #[test]
+    fn displays_as_parsed() {
+        for limit in ["250W", "250.5W", "80%", "+20W", "-0.5W"] {
+            assert_eq!(parse(limit).unwrap().to_string(), limit);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use nvml_wrapper::enum_wrappers::device::PerformanceState;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, str::FromStr};
+
+const PSTATES: [PerformanceState; 16] = [
+    PerformanceState::Zero,
+    PerformanceState::One,
+    PerformanceState::Two,
+    PerformanceState::Three,
+    PerformanceState::Four,
+    PerformanceState::Five,
+    PerformanceState::Six,
+    PerformanceState::Seven,
+    PerformanceState::Eight,
+    PerformanceState::Nine,
+    PerformanceState::Ten,
+    PerformanceState::Eleven,
+    PerformanceState::Twelve,
+    PerformanceState::Thirteen,
+    PerformanceState::Fourteen,
+    PerformanceState::Fifteen,
+];
+
+/// Parses a performance state name such as `P0` or `p2`
+pub fn parse_pstate(s: &str) -> Result<PerformanceState, String> {
+    let number = s
+        .trim()
+        .strip_prefix(['P', 'p'])
+        .and_then(|n| n.parse::<usize>().ok())
+        .ok_or_else(|| format!("invalid performance state `{}`, expected P0 to P15", s))?;
+
+    PSTATES
+        .get(number)
+        .copied()
+        .ok_or_else(|| format!("invalid performance state `{}`, expected P0 to P15", s))
+}
+
+/// Formats a performance state the way `nvidia-smi` does, e.g. `P0`
+pub fn pstate_name(pstate: PerformanceState) -> String {
+    match PSTATES.iter().position(|p| *p == pstate) {
+        Some(number) => format!("P{}", number),
+        None => "P?".to_string(),
+    }
+}
+
+/// Clock offsets per performance state.
+///
+/// Parsed from `P0:200,P2:100` on the command line and from a
+/// `{"P0": 200, "P2": 100}` object in the config file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "BTreeMap<String, i32>", into = "BTreeMap<String, i32>")]
+pub struct PstateOffsets(pub Vec<(PerformanceState, i32)>);
+
+impl FromStr for PstateOffsets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|entry| {
+                let (pstate, offset) = entry.split_once(':').ok_or_else(|| {
+                    format!("invalid entry `{}`, expected <pstate>:<offset>", entry)
+                })?;
+                let offset = offset
+                    .trim()
+                    .parse::<i32>()
+                    .map_err(|e| format!("invalid offset `{}`: {}", offset, e))?;
+                Ok((parse_pstate(pstate)?, offset))
+            })
+            .collect::<Result<_, _>>()
+            .map(PstateOffsets)
+    }
+}
+
+impl From<PstateOffsets> for BTreeMap<String, i32> {
+    fn from(offsets: PstateOffsets) -> Self {
+        offsets
+            .0
+            .into_iter()
+            .map(|(pstate, offset)| (pstate_name(pstate), offset))
+            .collect()
+    }
+}
+
+impl TryFrom<BTreeMap<String, i32>> for PstateOffsets {
+    type Error = String;
+
+    fn try_from(map: BTreeMap<String, i32>) -> Result<Self, Self::Error> {
+        map.into_iter()
+            .map(|(pstate, offset)| Ok((parse_pstate(&pstate)?, offset)))
+            .collect::<Result<_, _>>()
+            .map(PstateOffsets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pstate_names() {
+        assert_eq!(parse_pstate("P0"), Ok(PerformanceState::Zero));
+        assert_eq!(parse_pstate("p2"), Ok(PerformanceState::Two));
+        assert_eq!(parse_pstate(" P15 "), Ok(PerformanceState::Fifteen));
+        for invalid in ["P16", "P-1", "P", "2", "X0", ""] {
+            assert!(parse_pstate(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for pstate in PSTATES {
+            assert_eq!(parse_pstate(&pstate_name(pstate)), Ok(pstate));
+        }
+    }
+
+    #[test]
+    fn parses_offsets_from_the_command_line() {
+        let offsets: PstateOffsets = "P0:200,p2:-100, P8: 0".parse().unwrap();
+        assert_eq!(
+            offsets.0,
+            vec![
+                (PerformanceState::Zero, 200),
+                (PerformanceState::Two, -100),
+                (PerformanceState::Eight, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_offsets() {
+        for invalid in [
+            "P0=200",
+            "P0:",
+            "P0:abc",
+            "P16:100",
+            "P0:200,",
+            "P0:2147483648",
+        ] {
+            assert!(invalid.parse::<PstateOffsets>().is_err(), "{}", invalid);
+        }
+        assert!("P0:-2147483648".parse::<PstateOffsets>().is_ok());
+    }
+
+    #[test]
+    fn parses_offsets_from_the_config_file() {
+        let offsets: PstateOffsets = serde_json::from_str(r#"{"P2": 100, "P0": 200}"#).unwrap();
+        assert_eq!(
+            offsets.0,
+            vec![(PerformanceState::Zero, 200), (PerformanceState::Two, 100)]
+        );
+        assert!(serde_json::from_str::<PstateOffsets>(r#"{"P99": 100}"#).is_err());
+        assert!(serde_json::from_str::<PstateOffsets>(r#"{"P0": "high"}"#).is_err());
+    }
+}
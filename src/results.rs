@@ -0,0 +1,102 @@
+use crate::history::format_timestamp;
+use crate::output::{self, OutputFormat};
+use crate::power::format_watts;
+use crate::store::{Filter, Store, TrialOrder};
+use std::path::Path;
+
+/// The database at `db`, or the default one
+pub fn open(db: Option<&Path>) -> Store {
+    let path = db.map_or_else(crate::store::default_path, Path::to_path_buf);
+    Store::open_existing(&path).unwrap_or_else(|e| panic!("Failed to open results: {}", e))
+}
+
+/// Prints the most recent tuning runs matching the filter
+pub fn print_runs(db: Option<&Path>, filter: &Filter, limit: usize, format: OutputFormat) {
+    let runs = open(db).runs(filter, limit).expect("Failed to query runs");
+    if format != OutputFormat::Text {
+        output::print(format, &runs);
+        return;
+    }
+    if runs.is_empty() {
+        println!("No runs recorded yet.");
+        return;
+    }
+
+    println!(
+        "{:>4}  {:<19}  {:<28}  {:<10}  {:<11}  {:<20}  {:>6}  BENCHMARK",
+        "RUN", "STARTED (UTC)", "GPU", "DRIVER", "STRATEGY", "OBJECTIVE", "TRIALS"
+    );
+    for run in &runs {
+        println!(
+            "{:>4}  {:<19}  {:<28}  {:<10}  {:<11}  {:<20}  {:>6}  {}",
+            run.id,
+            format_timestamp(run.started_ms),
+            run.gpu_name,
+            run.driver_version,
+            run.strategy,
+            run.objective,
+            run.trials,
+            run.benchmark
+        );
+        if let Some(result) = &run.result {
+            println!("      {}", result);
+        }
+    }
+}
+
+/// Prints the best trials of the runs matching the filter
+pub fn print_trials(
+    db: Option<&Path>,
+    filter: &Filter,
+    order: TrialOrder,
+    limit: usize,
+    format: OutputFormat,
+) {
+    let trials = open(db)
+        .trials(filter, order, limit)
+        .expect("Failed to query trials");
+    if format != OutputFormat::Text {
+        output::print(format, &trials);
+        return;
+    }
+    if trials.is_empty() {
+        println!("No trials match.");
+        return;
+    }
+
+    println!(
+        "{:>4}  {:>9}  {:>6}  {:>6}  {:>9}  {:>8}  {:>8}  {:>8}  {:>9}  {:>5}  {:>9}",
+        "RUN",
+        "POWER",
+        "CORE",
+        "MEM",
+        "SCORE",
+        "AVG W",
+        "SCORE/W",
+        "ENERGY J",
+        "CLOCK MHz",
+        "TEMP",
+        "THROTTLED"
+    );
+    for trial in &trials {
+        let per_watt = if trial.avg_power_w > 0.0 {
+            trial.score / trial.avg_power_w
+        } else {
+            0.0
+        };
+        println!(
+            "{:>4}  {:>9}  {:>+6}  {:>+6}  {:>9.0}  {:>8.2}  {:>8.2}  {:>8.0}  {:>9.0}  {:>4}C  {:>8.0}%",
+            trial.run_id,
+            format_watts(trial.power_limit_mw),
+            trial.freq_offset,
+            trial.mem_offset,
+            trial.score,
+            trial.avg_power_w,
+            per_watt,
+            trial.energy_j,
+            trial.avg_clock_mhz,
+            trial.max_temp_c,
+            trial.throttled * 100.0
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use nvml_wrapper::error::NvmlError;
+use std::{
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    thread,
+    time::Duration,
+};
+
+static ATTEMPTS: AtomicU32 = AtomicU32::new(3);
+static BACKOFF_MS: AtomicU64 = AtomicU64::new(500);
+
+/// Sets how often [`retry`] tries a call and how long it waits after the
+/// first failure
+pub fn init(attempts: u32, backoff: Duration) {
+    ATTEMPTS.store(attempts.max(1), Ordering::Relaxed);
+    BACKOFF_MS.store(backoff.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Errors of a busy driver or one still initializing during boot, which
+/// often succeed when tried again
+fn is_transient(e: &NvmlError) -> bool {
+    matches!(
+        e,
+        NvmlError::Unknown | NvmlError::Timeout | NvmlError::InUse | NvmlError::DriverNotLoaded
+    )
+}
+
+/// Runs an NVML call again while it fails with a transient error, doubling
+/// the wait after every failure, until the attempts set by [`init`] run out
+pub fn retry<T>(mut call: impl FnMut() -> Result<T, NvmlError>) -> Result<T, NvmlError> {
+    let attempts = ATTEMPTS.load(Ordering::Relaxed);
+    let mut backoff = Duration::from_millis(BACKOFF_MS.load(Ordering::Relaxed));
+    let mut attempt = 1;
+    loop {
+        match call() {
+            Err(e) if is_transient(&e) && attempt < attempts => {
+                eprintln!(
+                    "NVML call failed with {:?}, retrying in {} ms (attempt {} of {})",
+                    e,
+                    backoff.as_millis(),
+                    attempt + 1,
+                    attempts
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
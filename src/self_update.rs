@@ -0,0 +1,250 @@
+use serde::Deserialize;
+use std::{
+    cmp::Ordering,
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/Dreaming-Codes/nvidia_oc/releases/latest";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Fetches a URL with curl, which every distro ships, rather than pulling an
+/// HTTP and TLS stack into the binary
+fn download(url: &str, output: &Path) -> Result<(), String> {
+    let status = Command::new("curl")
+        .args([
+            "--fail",
+            "--location",
+            "--silent",
+            "--show-error",
+            "--output",
+        ])
+        .arg(output)
+        .arg(url)
+        .status()
+        .map_err(|e| format!("Failed to run curl: {}", e))?;
+    if !status.success() {
+        return Err(format!("curl failed to download {}", url));
+    }
+    Ok(())
+}
+
+fn fetch(url: &str) -> Result<String, String> {
+    let output = Command::new("curl")
+        .args(["--fail", "--location", "--silent", "--show-error"])
+        .args(["--header", "Accept: application/vnd.github+json"])
+        .arg(url)
+        .output()
+        .map_err(|e| format!("Failed to run curl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "curl failed to fetch {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout).map_err(|e| format!("Invalid response from {}: {}", url, e))
+}
+
+fn sha256(path: &Path) -> Result<String, String> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .map_err(|e| format!("Failed to run sha256sum: {}", e))?;
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(str::to_lowercase)
+        .ok_or_else(|| format!("sha256sum failed on {}", path.display()))
+}
+
+/// The binary for this architecture, or a plain `nvidia_oc` for releases that
+/// only ship one
+fn binary_asset(release: &Release) -> Option<&Asset> {
+    let arch = env::consts::ARCH;
+    release
+        .assets
+        .iter()
+        .filter(|asset| asset.name.starts_with("nvidia_oc") && !asset.name.contains('.'))
+        .find(|asset| asset.name.contains(arch))
+        .or_else(|| {
+            release
+                .assets
+                .iter()
+                .find(|asset| asset.name == "nvidia_oc")
+        })
+}
+
+/// The expected hash from `<binary>.sha256` or a `SHA256SUMS` listing
+fn expected_checksum(release: &Release, binary: &Asset) -> Result<String, String> {
+    let sums = release
+        .assets
+        .iter()
+        .find(|asset| asset.name == format!("{}.sha256", binary.name))
+        .or_else(|| {
+            release
+                .assets
+                .iter()
+                .find(|asset| asset.name == "SHA256SUMS")
+        })
+        .ok_or("The release has no checksum, refusing to install it")?;
+    let sums = fetch(&sums.browser_download_url)?;
+    sums.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((fields.next()?, fields.next()))
+        })
+        // A `.sha256` file may hold just the hash
+        .find(|(_, name)| name.is_none_or(|name| name.trim_start_matches('*') == binary.name))
+        .map(|(hash, _)| hash.to_lowercase())
+        .ok_or_else(|| format!("No checksum for {} in the release", binary.name))
+}
+
+/// A `major.minor.patch` version. A pre-release like `1.2.0-rc1` sorts
+/// before `1.2.0`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Version {
+    numbers: Vec<u64>,
+    release: bool,
+    pre: String,
+}
+
+fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim().trim_start_matches('v');
+    // Build metadata doesn't take part in the order
+    let version = version.split('+').next()?;
+    let (core, pre) = version.split_once('-').unwrap_or((version, ""));
+    let mut numbers = core
+        .split('.')
+        .map(|number| number.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    while numbers.len() > 1 && numbers.last() == Some(&0) {
+        numbers.pop();
+    }
+    Some(Version {
+        numbers,
+        release: pre.is_empty(),
+        pre: pre.to_string(),
+    })
+}
+
+/// Replaces the running binary with the latest GitHub release if it is newer,
+/// or with `force` whenever it differs. The download is verified against the
+/// release's SHA-256 checksum and renamed over the old binary, so an
+/// interrupted update leaves it intact.
+pub fn run(force: bool) -> Result<(), String> {
+    let release: Release = serde_json::from_str(&fetch(LATEST_RELEASE_URL)?)
+        .map_err(|e| format!("Unexpected response from GitHub: {}", e))?;
+    let latest = release.tag_name.trim_start_matches('v');
+    let current = env!("CARGO_PKG_VERSION");
+    let order = parse_version(latest)
+        .zip(parse_version(current))
+        .map(|(latest, current)| latest.cmp(&current));
+    match order {
+        Some(Ordering::Equal) => {
+            println!("nvidia_oc {} is up to date.", current);
+            return Ok(());
+        }
+        Some(Ordering::Greater) => {}
+        _ if force => {}
+        Some(Ordering::Less) => {
+            println!(
+                "nvidia_oc {} is newer than the latest release {}, pass --force to downgrade.",
+                current, latest
+            );
+            return Ok(());
+        }
+        None => {
+            return Err(format!(
+                "Can't compare release {} to nvidia_oc {}, pass --force to install it anyway",
+                latest, current
+            ))
+        }
+    }
+
+    let binary = binary_asset(&release)
+        .ok_or_else(|| format!("Release {} has no binary for {}", latest, env::consts::ARCH))?;
+    let expected = expected_checksum(&release, binary)?;
+
+    let exe = env::current_exe().map_err(|e| format!("Failed to locate nvidia_oc: {}", e))?;
+    // Same directory as the binary, so the rename below stays atomic
+    let download_path: PathBuf = exe.with_file_name(".nvidia_oc.update");
+    download(&binary.browser_download_url, &download_path)?;
+
+    let actual = sha256(&download_path)?;
+    if actual != expected {
+        let _ = fs::remove_file(&download_path);
+        return Err(format!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            binary.name, expected, actual
+        ));
+    }
+
+    fs::set_permissions(&download_path, fs::Permissions::from_mode(0o755))
+        .and_then(|()| fs::rename(&download_path, &exe))
+        .map_err(|e| {
+            let _ = fs::remove_file(&download_path);
+            format!("Failed to replace {}: {}", exe.display(), e)
+        })?;
+    println!("Updated nvidia_oc from {} to {}.", current, latest);
+    Ok(())
+}
+
+/// Whether the binary's directory needs root to write to
+pub fn needs_root() -> bool {
+    let Some(dir) = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    else {
+        return true;
+    };
+    let Ok(dir) = std::ffi::CString::new(dir.as_os_str().as_encoded_bytes()) else {
+        return true;
+    };
+    unsafe { libc::access(dir.as_ptr(), libc::W_OK) != 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(a: &str, b: &str) -> Option<Ordering> {
+        Some(parse_version(a)?.cmp(&parse_version(b)?))
+    }
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert_eq!(order("0.10.0", "0.9.3"), Some(Ordering::Greater));
+        assert_eq!(order("1.2.0", "1.10.0"), Some(Ordering::Less));
+        assert_eq!(order("v1.2.3", "1.2.3"), Some(Ordering::Equal));
+        assert_eq!(order("1.2", "1.2.0"), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn pre_releases_sort_before_the_release() {
+        assert_eq!(order("1.2.0-rc1", "1.2.0"), Some(Ordering::Less));
+        assert_eq!(order("1.2.0-rc2", "1.2.0-rc1"), Some(Ordering::Greater));
+        assert_eq!(order("1.2.0+build5", "1.2.0"), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn rejects_non_numeric_versions() {
+        assert_eq!(parse_version("nightly"), None);
+        assert_eq!(parse_version("1.x.0"), None);
+        assert_eq!(parse_version(""), None);
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Replaces the default Ctrl-C behaviour of exiting with setting a flag that
+/// long running commands poll via [`interrupted`]
+pub fn catch_interrupt() {
+    unsafe {
+        libc::signal(libc::SIGINT, handle as *const () as libc::sighandler_t);
+    }
+}
+
+/// Like [`catch_interrupt`], and also catches SIGTERM, which is how systemd
+/// and `kill` stop the daemon
+pub fn catch_termination() {
+    catch_interrupt();
+    unsafe {
+        libc::signal(libc::SIGTERM, handle as *const () as libc::sighandler_t);
+    }
+}
+
+/// Whether Ctrl-C was pressed, or SIGTERM arrived after
+/// [`catch_termination`], since the handler was installed
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,118 @@
+use crate::telemetry::{Sample, Stats};
+use nvml_wrapper::Nvml;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+pub const SOCKET_PATH: &str = "/run/nvidia_oc.sock";
+
+/// How long a client gets to send its request before it counts as empty
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How often the rolling averages are sampled
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Rolling power and temperature per GPU index
+type SharedStats = Arc<Mutex<HashMap<u32, Stats>>>;
+
+fn telemetry(nvml: &Nvml, stats: &SharedStats) -> Value {
+    let stats = stats.lock().expect("Stats lock poisoned");
+    let count = nvml.device_count().unwrap_or(0);
+    let gpus: Vec<Value> = (0..count)
+        .filter_map(|index| {
+            let device = nvml.device_by_index(index).ok()?;
+            let stats = stats.get(&index).map(Stats::summary);
+            Some(json!({ "index": index, "sample": Sample::read(&device), "stats": stats }))
+        })
+        .collect();
+    Value::Array(gpus)
+}
+
+/// Answers one request line, see [`spawn`]
+fn respond(nvml: &Nvml, stats: &SharedStats, request: &str) -> Value {
+    let mut words = request.split_whitespace();
+    match (words.next(), words.next()) {
+        (None | Some("telemetry"), None) => telemetry(nvml, stats),
+        (Some("get"), Some(index)) => {
+            let Ok(index) = index.parse::<u32>() else {
+                return json!({ "error": format!("invalid GPU index {}", index) });
+            };
+            match nvml.device_by_index(index) {
+                Ok(device) => crate::device_report(index, &device),
+                Err(e) => json!({ "error": format!("failed to get GPU {}: {:?}", index, e) }),
+            }
+        }
+        _ => json!({ "error": format!("unknown request `{}`", request.trim()) }),
+    }
+}
+
+fn handle(nvml: &Nvml, stats: &SharedStats, stream: UnixStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = String::new();
+    // A client that only reads gets the default answer
+    match BufReader::new(&stream).read_line(&mut request) {
+        Ok(_) => {}
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) => {}
+        Err(e) => return Err(e),
+    }
+    let mut stream = stream;
+    writeln!(stream, "{}", respond(nvml, stats, &request))
+}
+
+/// Serves live readings as JSON on a UNIX socket from a thread with its own
+/// NVML handle, so status bars and overlays get them without starting
+/// nvidia-smi or initializing NVML per query. A client sends one line and
+/// gets one line of JSON back:
+///
+/// - nothing or `telemetry`: clocks, temperature, power, utilization, fan
+///   and throttle reasons of every GPU, with 10 and 60 second averages and
+///   peaks of power and temperature since the daemon started
+/// - `get <index>`: everything `nvidia_oc get` reports for that GPU, which is
+///   slower as the target temperature needs nvidia-smi
+///
+/// The socket is world readable, it only answers queries.
+pub fn spawn() -> io::Result<()> {
+    let _ = fs::remove_file(SOCKET_PATH);
+    let listener = UnixListener::bind(SOCKET_PATH)?;
+    fs::set_permissions(SOCKET_PATH, fs::Permissions::from_mode(0o666))?;
+    let nvml = Nvml::init().map_err(io::Error::other)?;
+    let stats = SharedStats::default();
+
+    let sampler_nvml = Nvml::init().map_err(io::Error::other)?;
+    let sampler_stats = stats.clone();
+    thread::spawn(move || loop {
+        let count = sampler_nvml.device_count().unwrap_or(0);
+        for index in 0..count {
+            if let Ok(device) = sampler_nvml.device_by_index(index) {
+                let sample = Sample::read(&device);
+                let mut stats = sampler_stats.lock().expect("Stats lock poisoned");
+                stats.entry(index).or_default().push(&sample);
+            }
+        }
+        thread::sleep(STATS_INTERVAL);
+    });
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| handle(&nvml, &stats, stream));
+            if let Err(e) = result {
+                eprintln!("Failed to answer socket client: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
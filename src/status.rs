@@ -0,0 +1,148 @@
+use crate::color::{pad, paint, visible_width, Severity};
+use crate::config::Config;
+use crate::drift;
+use crate::output::{self, OutputFormat};
+use crate::throttle::{throttle_reason_names, throttle_severity};
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::{Device, Nvml};
+use serde::Serialize;
+
+const HEADERS: [&str; 9] = [
+    "GPU", "NAME", "POWER", "CORE", "MEMORY", "LOCKED", "TEMP", "FAN", "CONFIG",
+];
+
+/// One GPU's row, unreadable values are `None`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Status {
+    index: u32,
+    name: String,
+    power_limit_w: Option<u32>,
+    default_power_limit_w: Option<u32>,
+    core_offset_mhz: Option<i32>,
+    mem_offset_mhz: Option<i32>,
+    /// Configured, NVML can't read locked clocks back
+    locked_clocks_mhz: Option<(u32, u32)>,
+    temperature_c: Option<u32>,
+    fan_percent: Option<u32>,
+    throttle_reasons: Vec<&'static str>,
+    /// Parameters that differ from the config, `None` without a config entry
+    drifts: Option<usize>,
+    #[serde(skip)]
+    throttle: Option<Severity>,
+}
+
+impl Status {
+    fn read(index: u32, device: &Device, config: Option<&Config>) -> Status {
+        let uuid = device.uuid().unwrap_or_default();
+        let name = device.name().unwrap_or_else(|_| "unknown".to_string());
+        let entry = config.and_then(|config| config.entry(index, &uuid, &name));
+        let reasons = device.current_throttle_reasons().ok();
+
+        Status {
+            index,
+            name,
+            power_limit_w: device.power_management_limit().ok().map(|mw| mw / 1000),
+            default_power_limit_w: device
+                .power_management_limit_default()
+                .ok()
+                .map(|mw| mw / 1000),
+            core_offset_mhz: device.gpc_clock_vf_offset().ok(),
+            mem_offset_mhz: device.mem_clock_vf_offset().ok(),
+            locked_clocks_mhz: entry
+                .as_ref()
+                .and_then(|(_, sets)| sets.min_clock.zip(sets.max_clock)),
+            temperature_c: device.temperature(TemperatureSensor::Gpu).ok(),
+            fan_percent: match device.num_fans() {
+                Ok(0) => None,
+                _ => device.fan_speed(0).ok(),
+            },
+            throttle_reasons: reasons.map(throttle_reason_names).unwrap_or_default(),
+            drifts: entry.map(|(_, sets)| drift::drift(&sets, device).len()),
+            throttle: reasons.and_then(throttle_severity),
+        }
+    }
+
+    fn cells(&self) -> Vec<String> {
+        let na = || "n/a".to_string();
+        let power = match (self.power_limit_w, self.default_power_limit_w) {
+            (Some(limit), Some(default)) => format!("{}/{} W", limit, default),
+            (Some(limit), None) => format!("{} W", limit),
+            _ => na(),
+        };
+        let offset = |offset: Option<i32>| offset.map_or_else(na, |o| format!("{:+} MHz", o));
+        let locked = self.locked_clocks_mhz.map_or_else(
+            || "-".to_string(),
+            |(min, max)| format!("{}-{} MHz", min, max),
+        );
+
+        // Red or yellow when the GPU is being held back right now
+        let temp = self
+            .temperature_c
+            .map_or_else(na, |temp| format!("{} °C", temp));
+        let temp = match self.throttle {
+            Some(severity) => paint(&temp, severity),
+            None => temp,
+        };
+
+        let matches = match self.drifts {
+            None => "-".to_string(),
+            Some(0) => paint("matches", Severity::Good),
+            Some(1) => paint("1 drift", Severity::Warning),
+            Some(drifts) => paint(&format!("{} drifts", drifts), Severity::Warning),
+        };
+
+        vec![
+            self.index.to_string(),
+            self.name.clone(),
+            power,
+            offset(self.core_offset_mhz),
+            offset(self.mem_offset_mhz),
+            locked,
+            temp,
+            self.fan_percent
+                .map_or_else(|| "-".to_string(), |speed| format!("{}%", speed)),
+            matches,
+        ]
+    }
+}
+
+/// Prints a table with one row per GPU. Drift against the config is only
+/// shown when there is a config, the temperature is colored while the GPU
+/// throttles.
+pub fn run(nvml: &Nvml, config: Option<&Config>, format: OutputFormat) {
+    let count = nvml.device_count().expect("Failed to get GPU count");
+    let mut statuses = Vec::new();
+    for index in 0..count {
+        match nvml.device_by_index(index) {
+            Ok(device) => statuses.push(Status::read(index, &device, config)),
+            Err(e) => eprintln!("Failed to get GPU {}: {:?}", index, e),
+        }
+    }
+
+    if format != OutputFormat::Text {
+        output::print(format, &statuses);
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = statuses.iter().map(Status::cells).collect();
+    let mut widths = HEADERS.map(visible_width);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(visible_width(cell));
+        }
+    }
+
+    let print_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| pad(cell, width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+    print_row(HEADERS.to_vec());
+    for row in &rows {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+}
@@ -0,0 +1,347 @@
+// Also compiled into the GUI, which writes runs and trials while the CLI only
+// queries them, so each binary leaves part of this unused
+#![allow(dead_code)]
+
+use rusqlite::{params, params_from_iter, Connection, Row};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_ms INTEGER NOT NULL,
+    gpu_name TEXT NOT NULL,
+    gpu_uuid TEXT NOT NULL,
+    driver_version TEXT NOT NULL,
+    strategy TEXT NOT NULL,
+    objective TEXT NOT NULL,
+    benchmark TEXT NOT NULL,
+    result TEXT
+);
+CREATE TABLE IF NOT EXISTS trials (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+    timestamp_ms INTEGER NOT NULL,
+    power_limit_mw INTEGER NOT NULL,
+    freq_offset INTEGER NOT NULL,
+    mem_offset INTEGER NOT NULL,
+    min_clock INTEGER NOT NULL,
+    max_clock INTEGER NOT NULL,
+    score REAL NOT NULL,
+    avg_power_w REAL NOT NULL,
+    energy_j REAL NOT NULL,
+    avg_clock_mhz REAL NOT NULL,
+    peak_clock_mhz INTEGER NOT NULL,
+    max_temp_c INTEGER NOT NULL,
+    throttled REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS trials_run ON trials(run_id);
+CREATE TABLE IF NOT EXISTS applied (
+    id INTEGER PRIMARY KEY,
+    timestamp_ms INTEGER NOT NULL,
+    gpu_uuid TEXT NOT NULL,
+    settings TEXT NOT NULL
+);
+";
+
+/// `$XDG_DATA_HOME/nvidia_oc/results.db`, or under `~/.local/share` without it
+pub fn default_path() -> PathBuf {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| {
+            let home = std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .unwrap_or_default();
+            home.join(".local/share")
+        });
+    data_dir.join("nvidia_oc/results.db")
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// One tuner search on one GPU
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Run {
+    /// Assigned by [`Store::start_run`]
+    pub id: i64,
+    pub started_ms: u64,
+    pub gpu_name: String,
+    pub gpu_uuid: String,
+    pub driver_version: String,
+    pub strategy: String,
+    pub objective: String,
+    pub benchmark: String,
+    /// What the search concluded, for searches that conclude more than their
+    /// best trial, see [`Store::finish_run`]
+    pub result: Option<String>,
+    /// Filled in by [`Store::runs`]
+    pub trials: u32,
+}
+
+/// A benchmarked combination of settings and the telemetry sampled while it
+/// ran
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trial {
+    pub run_id: i64,
+    pub timestamp_ms: u64,
+    pub power_limit_mw: u32,
+    pub freq_offset: i32,
+    pub mem_offset: i32,
+    pub min_clock: u32,
+    pub max_clock: u32,
+    pub score: f32,
+    pub avg_power_w: f32,
+    pub energy_j: f32,
+    pub avg_clock_mhz: f32,
+    pub peak_clock_mhz: u32,
+    pub max_temp_c: u32,
+    /// Share of the run spent power or thermal limited, from 0 to 1
+    pub throttled: f32,
+}
+
+impl Trial {
+    fn from_row(row: &Row) -> rusqlite::Result<Trial> {
+        Ok(Trial {
+            run_id: row.get("run_id")?,
+            timestamp_ms: row.get("timestamp_ms")?,
+            power_limit_mw: row.get("power_limit_mw")?,
+            freq_offset: row.get("freq_offset")?,
+            mem_offset: row.get("mem_offset")?,
+            min_clock: row.get("min_clock")?,
+            max_clock: row.get("max_clock")?,
+            score: row.get("score")?,
+            avg_power_w: row.get("avg_power_w")?,
+            energy_j: row.get("energy_j")?,
+            avg_clock_mhz: row.get("avg_clock_mhz")?,
+            peak_clock_mhz: row.get("peak_clock_mhz")?,
+            max_temp_c: row.get("max_temp_c")?,
+            throttled: row.get("throttled")?,
+        })
+    }
+}
+
+/// Which runs to query. Name and driver match as substrings, so `--gpu 4090`
+/// or `--driver 560.` work.
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub run: Option<i64>,
+    pub gpu: Option<String>,
+    pub driver: Option<String>,
+}
+
+impl Filter {
+    /// The WHERE clause over `runs` and its parameters
+    fn clause(&self) -> (String, Vec<String>) {
+        let mut conditions = vec!["1".to_string()];
+        let mut values = Vec::new();
+        if let Some(run) = self.run {
+            conditions.push("runs.id = ?".to_string());
+            values.push(run.to_string());
+        }
+        if let Some(gpu) = &self.gpu {
+            conditions.push("(runs.gpu_name LIKE ? OR runs.gpu_uuid = ?)".to_string());
+            values.push(format!("%{}%", gpu));
+            values.push(gpu.clone());
+        }
+        if let Some(driver) = &self.driver {
+            conditions.push("runs.driver_version LIKE ?".to_string());
+            values.push(format!("{}%", driver));
+        }
+        (conditions.join(" AND "), values)
+    }
+}
+
+/// What to rank trials by, highest first
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum TrialOrder {
+    Score,
+    /// Score per watt of average power draw
+    Efficiency,
+    /// Score per joule the benchmark run consumed
+    ScorePerJoule,
+    /// Most recent first
+    Time,
+}
+
+impl TrialOrder {
+    fn sql(&self) -> &'static str {
+        match self {
+            TrialOrder::Score => "trials.score DESC",
+            TrialOrder::Efficiency => "trials.score / NULLIF(trials.avg_power_w, 0) DESC",
+            TrialOrder::ScorePerJoule => "trials.score / NULLIF(trials.energy_j, 0) DESC",
+            TrialOrder::Time => "trials.timestamp_ms DESC",
+        }
+    }
+}
+
+/// Tuning runs, their trials and every applied set of settings, in SQLite so
+/// results of several runs, drivers and GPUs stay queryable
+pub struct Store {
+    connection: Connection,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> rusqlite::Result<Store> {
+        if let Some(dir) = path.parent() {
+            // A missing directory surfaces as SQLite's own open error
+            let _ = fs::create_dir_all(dir);
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+        // Databases from before runs recorded their result
+        let has_result: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('runs') WHERE name = 'result'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_result {
+            connection.execute_batch("ALTER TABLE runs ADD COLUMN result TEXT;")?;
+        }
+        Ok(Store { connection })
+    }
+
+    /// Opens an existing database without creating one, for queries
+    pub fn open_existing(path: &Path) -> Result<Store, String> {
+        if !path.exists() {
+            return Err(format!(
+                "{} doesn't exist, the GUI tuner creates it with its first run",
+                path.display()
+            ));
+        }
+        Store::open(path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Records the start of a run, returning its id
+    pub fn start_run(&self, run: &Run) -> rusqlite::Result<i64> {
+        self.connection.execute(
+            "INSERT INTO runs (started_ms, gpu_name, gpu_uuid, driver_version, strategy, objective, benchmark)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                run.started_ms,
+                run.gpu_name,
+                run.gpu_uuid,
+                run.driver_version,
+                run.strategy,
+                run.objective,
+                run.benchmark
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    /// Records what a run concluded, e.g. the highest memory offset without
+    /// errors
+    pub fn finish_run(&self, run_id: i64, result: &str) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "UPDATE runs SET result = ?1 WHERE id = ?2",
+            params![result, run_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn add_trial(&self, trial: &Trial) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "INSERT INTO trials (run_id, timestamp_ms, power_limit_mw, freq_offset, mem_offset,
+                 min_clock, max_clock, score, avg_power_w, energy_j, avg_clock_mhz,
+                 peak_clock_mhz, max_temp_c, throttled)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                trial.run_id,
+                trial.timestamp_ms,
+                trial.power_limit_mw,
+                trial.freq_offset,
+                trial.mem_offset,
+                trial.min_clock,
+                trial.max_clock,
+                trial.score,
+                trial.avg_power_w,
+                trial.energy_j,
+                trial.avg_clock_mhz,
+                trial.peak_clock_mhz,
+                trial.max_temp_c,
+                trial.throttled
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Records settings applied to a GPU, as the config keys they'd be saved
+    /// under
+    pub fn add_applied(
+        &self,
+        gpu_uuid: &str,
+        settings: &serde_json::Value,
+    ) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "INSERT INTO applied (timestamp_ms, gpu_uuid, settings) VALUES (?1, ?2, ?3)",
+            params![now_ms(), gpu_uuid, settings.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Matching runs with their trial counts, most recent first
+    pub fn runs(&self, filter: &Filter, limit: usize) -> rusqlite::Result<Vec<Run>> {
+        let (clause, values) = filter.clause();
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT runs.*, (SELECT COUNT(*) FROM trials WHERE trials.run_id = runs.id) AS trials
+             FROM runs WHERE {} ORDER BY runs.started_ms DESC LIMIT {}",
+            clause, limit
+        ))?;
+        let runs = statement.query_map(params_from_iter(values), |row| {
+            Ok(Run {
+                id: row.get("id")?,
+                started_ms: row.get("started_ms")?,
+                gpu_name: row.get("gpu_name")?,
+                gpu_uuid: row.get("gpu_uuid")?,
+                driver_version: row.get("driver_version")?,
+                strategy: row.get("strategy")?,
+                objective: row.get("objective")?,
+                benchmark: row.get("benchmark")?,
+                result: row.get("result")?,
+                trials: row.get("trials")?,
+            })
+        })?;
+        runs.collect()
+    }
+
+    /// Trials of the matching runs, best first by `order`
+    pub fn trials(
+        &self,
+        filter: &Filter,
+        order: TrialOrder,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<Trial>> {
+        let (clause, values) = filter.clause();
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT trials.* FROM trials JOIN runs ON runs.id = trials.run_id
+             WHERE {} ORDER BY {} LIMIT {}",
+            clause,
+            order.sql(),
+            limit
+        ))?;
+        let trials = statement.query_map(params_from_iter(values), Trial::from_row)?;
+        trials.collect()
+    }
+
+    /// Every trial of a run, in the order they ran
+    pub fn run_trials(&self, run_id: i64) -> rusqlite::Result<Vec<Trial>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT * FROM trials WHERE run_id = ?1 ORDER BY timestamp_ms")?;
+        let trials = statement.query_map([run_id], Trial::from_row)?;
+        trials.collect()
+    }
+}
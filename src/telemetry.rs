@@ -0,0 +1,184 @@
+use crate::throttle::throttle_reason_names;
+use nvml_wrapper::enum_wrappers::device::{Clock, TemperatureSensor};
+use nvml_wrapper::Device;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// One reading of the GPU's live state. Readings the GPU doesn't support are
+/// left empty rather than failing the whole sample.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    pub timestamp_ms: u64,
+    pub graphics_clock_mhz: Option<u32>,
+    pub sm_clock_mhz: Option<u32>,
+    pub memory_clock_mhz: Option<u32>,
+    pub temperature_c: Option<u32>,
+    pub power_w: Option<f32>,
+    pub gpu_utilization: Option<u32>,
+    pub memory_utilization: Option<u32>,
+    pub fan_speed: Option<u32>,
+    /// Active throttle reasons separated by `|`
+    pub throttle_reasons: String,
+}
+
+impl Sample {
+    pub fn read(device: &Device) -> Sample {
+        let utilization = device.utilization_rates().ok();
+        let throttle = device.current_throttle_reasons().ok();
+
+        Sample {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            graphics_clock_mhz: device.clock_info(Clock::Graphics).ok(),
+            sm_clock_mhz: device.clock_info(Clock::SM).ok(),
+            memory_clock_mhz: device.clock_info(Clock::Memory).ok(),
+            temperature_c: device.temperature(TemperatureSensor::Gpu).ok(),
+            power_w: device.power_usage().ok().map(|mw| mw as f32 / 1000.0),
+            gpu_utilization: utilization.as_ref().map(|u| u.gpu),
+            memory_utilization: utilization.as_ref().map(|u| u.memory),
+            fan_speed: device.fan_speed(0).ok(),
+            throttle_reasons: throttle
+                .map(|reasons| throttle_reason_names(reasons).join("|"))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The windows of the moving averages in `watch --averages` and the daemon
+/// socket
+const WINDOWS: [Duration; 2] = [Duration::from_secs(10), Duration::from_secs(60)];
+
+/// Moving averages and the session peak of one reading. Readings older than
+/// the longest window are dropped.
+#[derive(Default)]
+pub struct Rolling {
+    readings: VecDeque<(Instant, f64)>,
+    peak: Option<f64>,
+}
+
+/// Averages over the last 10 and 60 seconds, `None` before the first reading
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Summary {
+    pub average_10s: Option<f64>,
+    pub average_60s: Option<f64>,
+    pub peak: Option<f64>,
+}
+
+impl Rolling {
+    pub fn push(&mut self, value: Option<f64>) {
+        let now = Instant::now();
+        let longest = WINDOWS[WINDOWS.len() - 1];
+        while self
+            .readings
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > longest)
+        {
+            self.readings.pop_front();
+        }
+        if let Some(value) = value {
+            self.readings.push_back((now, value));
+            self.peak = Some(self.peak.map_or(value, |peak| peak.max(value)));
+        }
+    }
+
+    fn average(&self, window: Duration) -> Option<f64> {
+        let now = Instant::now();
+        let values: Vec<f64> = self
+            .readings
+            .iter()
+            .filter(|(at, _)| now.duration_since(*at) <= window)
+            .map(|(_, value)| *value)
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            average_10s: self.average(WINDOWS[0]),
+            average_60s: self.average(WINDOWS[1]),
+            peak: self.peak,
+        }
+    }
+}
+
+/// Rolling power and temperature of one GPU
+#[derive(Default)]
+pub struct Stats {
+    pub power_w: Rolling,
+    pub temperature_c: Rolling,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct StatsSummary {
+    pub power_w: Summary,
+    pub temperature_c: Summary,
+}
+
+impl Stats {
+    pub fn push(&mut self, sample: &Sample) {
+        self.power_w.push(sample.power_w.map(f64::from));
+        self.temperature_c.push(sample.temperature_c.map(f64::from));
+    }
+
+    pub fn summary(&self) -> StatsSummary {
+        StatsSummary {
+            power_w: self.power_w.summary(),
+            temperature_c: self.temperature_c.summary(),
+        }
+    }
+}
+
+enum Writer {
+    Csv(Box<csv::Writer<Box<dyn Write>>>),
+    JsonLines(Box<dyn Write>),
+}
+
+impl Writer {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        match self {
+            Writer::Csv(writer) => {
+                writer.serialize(sample)?;
+                writer.flush()
+            }
+            Writer::JsonLines(writer) => {
+                writeln!(writer, "{}", serde_json::to_string(sample)?)?;
+                writer.flush()
+            }
+        }
+    }
+}
+
+/// Records a sample every `interval` until interrupted. Writes JSON lines when
+/// the output ends in `.json`/`.jsonl`, CSV otherwise, and stdout without
+/// an output file. Every row is flushed so nothing is lost on Ctrl-C.
+pub fn log(device: &Device, interval: Duration, output: Option<&Path>) -> io::Result<()> {
+    let out: Box<dyn Write> = match output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    let json = output
+        .and_then(|path| path.extension())
+        .is_some_and(|ext| ext == "json" || ext == "jsonl");
+    let mut writer = if json {
+        Writer::JsonLines(out)
+    } else {
+        Writer::Csv(Box::new(csv::Writer::from_writer(out)))
+    };
+
+    loop {
+        writer.write(&Sample::read(device))?;
+        thread::sleep(interval);
+    }
+}
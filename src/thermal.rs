@@ -0,0 +1,86 @@
+use nvml_wrapper::enum_wrappers::device::TemperatureThreshold;
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Device;
+use std::process::Command;
+
+/// Thresholds the driver enforces on its own, none of them can be changed
+const FIXED_THRESHOLDS: [(&str, TemperatureThreshold); 4] = [
+    ("shutdown", TemperatureThreshold::Shutdown),
+    ("slowdown", TemperatureThreshold::Slowdown),
+    ("max operating", TemperatureThreshold::GpuMax),
+    ("memory max operating", TemperatureThreshold::MemoryMax),
+];
+
+fn nvidia_smi(device: &Device, args: &[&str]) -> Result<String, String> {
+    let bus_id = device
+        .pci_info()
+        .map(|info| info.bus_id)
+        .map_err(|e| format!("failed to get PCI info: {:?}", e))?;
+    let output = Command::new("nvidia-smi")
+        .args(["-i", &bus_id])
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to run nvidia-smi: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "nvidia-smi exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Sets the GPU target temperature the driver throttles to, in °C, via
+/// nvidia-smi: NVML has the call but nvml-wrapper doesn't expose it. The
+/// target is the acoustic threshold, the only adjustable one, and has to stay
+/// below the fixed slowdown threshold.
+pub fn set_temp_limit(device: &Device, celsius: u32) -> Result<(), String> {
+    if temp_limit(device)?.is_none() {
+        return Err(
+            "this GPU has no adjustable target temperature, its thresholds are read-only"
+                .to_string(),
+        );
+    }
+    if let Ok(slowdown) = device.temperature_threshold(TemperatureThreshold::Slowdown) {
+        if celsius >= slowdown {
+            return Err(format!(
+                "{} °C is not below the slowdown threshold of {} °C",
+                celsius, slowdown
+            ));
+        }
+    }
+    nvidia_smi(device, &["-gtt", &celsius.to_string()]).map(|_| ())
+}
+
+/// Reads the GPU target temperature, `None` when the card has no adjustable
+/// target
+pub fn temp_limit(device: &Device) -> Result<Option<u32>, String> {
+    let report = nvidia_smi(device, &["-q", "-d", "TEMPERATURE"])?;
+    Ok(report
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim() == "GPU Target Temperature")
+        .and_then(|(_, value)| value.trim().trim_end_matches('C').trim().parse().ok()))
+}
+
+/// Prints the adjustable target temperature and the fixed thresholds,
+/// skipping the ones the card doesn't report
+pub fn print(device: &Device) {
+    match temp_limit(device) {
+        Ok(Some(limit)) => println!(
+            "GPU target temperature: {} °C (adjustable with --temp-limit)",
+            limit
+        ),
+        Ok(None) => println!("GPU target temperature: not adjustable on this GPU"),
+        Err(e) => eprintln!("Failed to get GPU target temperature: {}", e),
+    }
+
+    for (name, threshold) in FIXED_THRESHOLDS {
+        match device.temperature_threshold(threshold) {
+            Ok(celsius) => println!("GPU {} temperature: {} °C (read-only)", name, celsius),
+            Err(NvmlError::NotSupported) => {}
+            Err(e) => eprintln!("Failed to get GPU {} temperature: {:?}", name, e),
+        }
+    }
+}
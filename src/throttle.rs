@@ -0,0 +1,89 @@
+use crate::color::Severity;
+use nvml_wrapper::bitmasks::device::ThrottleReasons;
+use nvml_wrapper::enum_wrappers::device::PerformancePolicy;
+use nvml_wrapper::struct_wrappers::device::ViolationTime;
+use nvml_wrapper::Device;
+
+/// Human readable names for the throttle reasons NVML reports
+pub const THROTTLE_REASONS: [(ThrottleReasons, &str); 9] = [
+    (ThrottleReasons::GPU_IDLE, "GPU idle"),
+    (
+        ThrottleReasons::APPLICATIONS_CLOCKS_SETTING,
+        "applications clocks setting",
+    ),
+    (ThrottleReasons::SW_POWER_CAP, "SW power cap"),
+    (ThrottleReasons::HW_SLOWDOWN, "HW slowdown"),
+    (ThrottleReasons::SYNC_BOOST, "sync boost"),
+    (ThrottleReasons::SW_THERMAL_SLOWDOWN, "SW thermal slowdown"),
+    (ThrottleReasons::HW_THERMAL_SLOWDOWN, "HW thermal slowdown"),
+    (
+        ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN,
+        "HW power brake slowdown",
+    ),
+    (
+        ThrottleReasons::DISPLAY_CLOCK_SETTING,
+        "display clock setting",
+    ),
+];
+
+/// Decodes a throttle reason bitmask into the names of the active reasons
+pub fn throttle_reason_names(reasons: ThrottleReasons) -> Vec<&'static str> {
+    THROTTLE_REASONS
+        .iter()
+        .filter(|(flag, _)| reasons.contains(*flag))
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// How worrying the active throttle reasons are: thermal and hardware
+/// slowdowns are bad, power and sync boost caps are normal under load but
+/// still worth a look. Idle and clock settings don't count.
+pub fn throttle_severity(reasons: ThrottleReasons) -> Option<Severity> {
+    let bad = ThrottleReasons::HW_SLOWDOWN
+        | ThrottleReasons::SW_THERMAL_SLOWDOWN
+        | ThrottleReasons::HW_THERMAL_SLOWDOWN
+        | ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN;
+    let warning = ThrottleReasons::SW_POWER_CAP | ThrottleReasons::SYNC_BOOST;
+    if reasons.intersects(bad) {
+        Some(Severity::Bad)
+    } else if reasons.intersects(warning) {
+        Some(Severity::Warning)
+    } else {
+        None
+    }
+}
+
+/// Performance policies NVML keeps violation (time spent capped) counters for
+pub const VIOLATION_POLICIES: [(PerformancePolicy, &str); 6] = [
+    (PerformancePolicy::Power, "power"),
+    (PerformancePolicy::Thermal, "thermal"),
+    (PerformancePolicy::SyncBoost, "sync boost"),
+    (PerformancePolicy::BoardLimit, "board limit"),
+    (PerformancePolicy::LowUtilization, "low utilization"),
+    (PerformancePolicy::Reliability, "reliability"),
+];
+
+/// Reads every violation counter the device supports
+pub fn violation_times(device: &Device) -> Vec<(&'static str, ViolationTime)> {
+    VIOLATION_POLICIES
+        .iter()
+        .filter_map(|(policy, name)| {
+            device
+                .violation_status(*policy)
+                .ok()
+                .map(|time| (*name, time))
+        })
+        .collect()
+}
+
+/// Share of the time between two readings of the same counter that was spent
+/// capped, from 0 to 1
+pub fn violation_ratio(before: &ViolationTime, after: &ViolationTime) -> f64 {
+    // The reference time is in microseconds, the violation time in nanoseconds
+    let elapsed_ns = after.reference_time.saturating_sub(before.reference_time) as f64 * 1000.0;
+    if elapsed_ns <= 0.0 {
+        return 0.0;
+    }
+    let violated_ns = after.violation_time.saturating_sub(before.violation_time) as f64;
+    (violated_ns / elapsed_ns).clamp(0.0, 1.0)
+}
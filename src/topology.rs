@@ -0,0 +1,134 @@
+use crate::nvlink;
+use crate::output::{self, OutputFormat};
+use nvml_wrapper::enum_wrappers::device::TopologyLevel;
+use nvml_wrapper::Nvml;
+use serde::Serialize;
+use std::{fs, path::PathBuf};
+
+/// Connection codes as `nvidia-smi topo -m` prints them
+fn level_code(level: TopologyLevel) -> &'static str {
+    match level {
+        TopologyLevel::Internal => "INT",
+        TopologyLevel::Single => "PIX",
+        TopologyLevel::Multiple => "PXB",
+        TopologyLevel::HostBridge => "PHB",
+        TopologyLevel::Node => "NODE",
+        TopologyLevel::System => "SYS",
+    }
+}
+
+const LEGEND: &str = "\
+  X    = self
+  NV#  = connected by # NVLinks
+  INT  = on the same board
+  PIX  = through a single PCIe switch
+  PXB  = through multiple PCIe switches, without a host bridge
+  PHB  = through a PCIe host bridge
+  NODE = through the interconnect between host bridges of one NUMA node
+  SYS  = through the interconnect between NUMA nodes";
+
+/// The sysfs directory of a PCI device. NVML pads the domain to 8 digits,
+/// sysfs to 4.
+fn sysfs_dir(bus_id: &str) -> PathBuf {
+    let bus_id = bus_id.to_lowercase();
+    let short = bus_id
+        .get(bus_id.len().saturating_sub(12)..)
+        .unwrap_or(&bus_id);
+    PathBuf::from("/sys/bus/pci/devices").join(short)
+}
+
+fn read_sysfs(bus_id: &str, attribute: &str) -> Option<String> {
+    fs::read_to_string(sysfs_dir(bus_id).join(attribute))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Gpu {
+    index: u32,
+    name: String,
+    bus_id: String,
+    /// CPUs closest to the GPU, e.g. `0-15,32-47`
+    cpu_affinity: Option<String>,
+    /// `None` on single node systems, where the kernel reports -1
+    numa_node: Option<u32>,
+    /// How the GPU connects to each GPU, in index order
+    connections: Vec<String>,
+}
+
+/// Prints how every pair of GPUs is connected, through NVLink or the PCIe
+/// hierarchy, and the CPUs and NUMA node each GPU is closest to
+pub fn run(nvml: &Nvml, format: OutputFormat) {
+    let count = nvml.device_count().expect("Failed to get GPU count");
+    let devices: Vec<_> = (0..count)
+        .map(|index| nvml.device_by_index(index).expect("Failed to get GPU"))
+        .collect();
+    let bus_ids: Vec<String> = devices
+        .iter()
+        .map(|device| device.pci_info().map(|pci| pci.bus_id).unwrap_or_default())
+        .collect();
+
+    let mut gpus = Vec::new();
+    for (index, device) in devices.iter().enumerate() {
+        let peers = nvlink::peer_bus_ids(device);
+        let connections = (0..count)
+            .map(|other| {
+                if other as usize == index {
+                    return "X".to_string();
+                }
+                let links = peers
+                    .iter()
+                    .filter(|peer| peer.eq_ignore_ascii_case(&bus_ids[other as usize]))
+                    .count();
+                if links > 0 {
+                    return format!("NV{}", links);
+                }
+                // The call takes the other device by value
+                nvml.device_by_index(other)
+                    .and_then(|other| device.topology_common_ancestor(other))
+                    .map_or_else(|_| "?".to_string(), |level| level_code(level).to_string())
+            })
+            .collect();
+
+        let bus_id = &bus_ids[index];
+        gpus.push(Gpu {
+            index: index as u32,
+            name: device.name().unwrap_or_else(|_| "unknown".to_string()),
+            bus_id: bus_id.clone(),
+            cpu_affinity: read_sysfs(bus_id, "local_cpulist"),
+            numa_node: read_sysfs(bus_id, "numa_node").and_then(|node| node.parse().ok()),
+            connections,
+        });
+    }
+
+    if format != OutputFormat::Text {
+        output::print(format, &gpus);
+        return;
+    }
+
+    let mut header = format!("{:<6}", "");
+    for gpu in &gpus {
+        header += &format!("{:<6}", format!("GPU{}", gpu.index));
+    }
+    println!("{}{:<16}NUMA NODE", header, "CPU AFFINITY");
+    for gpu in &gpus {
+        let mut line = format!("{:<6}", format!("GPU{}", gpu.index));
+        for connection in &gpu.connections {
+            line += &format!("{:<6}", connection);
+        }
+        println!(
+            "{}{:<16}{}",
+            line,
+            gpu.cpu_affinity.as_deref().unwrap_or("-"),
+            gpu.numa_node
+                .map_or_else(|| "-".to_string(), |node| node.to_string())
+        );
+    }
+    println!();
+    for gpu in &gpus {
+        println!("GPU{}: {} ({})", gpu.index, gpu.name, gpu.bus_id);
+    }
+    println!();
+    println!("{}", LEGEND);
+}
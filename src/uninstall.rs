@@ -0,0 +1,80 @@
+use crate::config::{dropin_dir, SYSTEM_CONFIG_PATH};
+use crate::history::Source;
+use crate::{polkit, watchdog, SLEEP_HOOK_PATH, UDEV_RULE_PATH};
+use nvml_wrapper::Nvml;
+use std::{fs, io, path::Path, process::Command};
+
+/// The unit the README suggests for applying the config at boot
+const SYSTEMD_UNIT: &str = "nvidia_oc.service";
+const SYSTEMD_UNIT_PATH: &str = "/etc/systemd/system/nvidia_oc.service";
+
+/// Where the change history and watchdog state live
+const STATE_DIR: &str = "/var/lib/nvidia_oc";
+
+/// Removes a file or directory, a missing one counts as removed
+fn remove(path: &str) {
+    let result = if Path::new(path).is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    match result {
+        Ok(()) => println!("Removed {}.", path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("Failed to remove {}: {}", path, e),
+    }
+}
+
+fn run_command(program: &str, args: &[&str]) {
+    match Command::new(program).args(args).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("{} {} exited with {}", program, args.join(" "), status),
+        Err(e) => eprintln!("Failed to run {}: {}", program, e),
+    }
+}
+
+/// Resets every GPU to stock and removes the systemd unit, udev rule, sleep
+/// hook and polkit policy. With `purge` the system config, its drop-in directory and
+/// the state directory go as well, a config given with `--file` is left
+/// alone.
+pub fn run(purge: bool) {
+    match Nvml::init() {
+        Ok(nvml) => {
+            let count = nvml.device_count().unwrap_or(0);
+            for index in 0..count {
+                match nvml.device_by_index(index) {
+                    Ok(mut device) => {
+                        watchdog::reset_to_stock(&mut device, Source::Uninstall);
+                        println!("Reset GPU {} to stock settings.", index);
+                    }
+                    Err(e) => eprintln!("Failed to get GPU {}: {:?}", index, e),
+                }
+            }
+        }
+        Err(e) => eprintln!(
+            "Failed to initialize NVML, GPUs keep their settings: {:?}",
+            e
+        ),
+    }
+
+    if Path::new(SYSTEMD_UNIT_PATH).exists() {
+        run_command("systemctl", &["disable", "--now", SYSTEMD_UNIT]);
+        remove(SYSTEMD_UNIT_PATH);
+        run_command("systemctl", &["daemon-reload"]);
+    }
+
+    if Path::new(UDEV_RULE_PATH).exists() {
+        remove(UDEV_RULE_PATH);
+        run_command("udevadm", &["control", "--reload-rules"]);
+    }
+
+    remove(SLEEP_HOOK_PATH);
+    remove(polkit::POLICY_PATH);
+    remove(polkit::RULES_PATH);
+
+    if purge {
+        remove(SYSTEM_CONFIG_PATH);
+        remove(&dropin_dir(Path::new(SYSTEM_CONFIG_PATH)).to_string_lossy());
+        remove(STATE_DIR);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::events::EventMonitor;
+use crate::history::format_timestamp;
+use crate::pcie::PcieLink;
+use crate::telemetry::{Sample, Stats, Summary};
+use crate::throttle::{violation_ratio, violation_times};
+use nvml_wrapper::Device;
+use std::{collections::VecDeque, thread, time::Duration};
+
+/// How far back the trend line looks
+const TREND_WINDOW: Duration = Duration::from_secs(60);
+
+/// Characters per sparkline, readings are averaged to fit
+const SPARKLINE_WIDTH: usize = 20;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The readings of one quantity over the trend window
+struct Trend {
+    values: VecDeque<Option<f64>>,
+    capacity: usize,
+}
+
+impl Trend {
+    fn new(interval: Duration) -> Self {
+        let capacity = (TREND_WINDOW.as_secs_f64() / interval.as_secs_f64()).ceil() as usize;
+        Trend {
+            values: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    fn push(&mut self, value: Option<f64>) {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    /// The sparkline with the range it spans, e.g. `62-71 ▁▂▄▇█▇▄`. Gaps
+    /// where the reading failed stay blank.
+    fn render(&self, unit: &str) -> String {
+        let values: Vec<f64> = self.values.iter().flatten().copied().collect();
+        let (Some(min), Some(max)) = (
+            values.iter().copied().reduce(f64::min),
+            values.iter().copied().reduce(f64::max),
+        ) else {
+            return "-".to_string();
+        };
+
+        let chunk = self.values.len().div_ceil(SPARKLINE_WIDTH);
+        let line: String = self
+            .values
+            .iter()
+            .collect::<Vec<_>>()
+            .chunks(chunk)
+            .map(|chunk| {
+                let readings: Vec<f64> = chunk.iter().copied().flatten().copied().collect();
+                if readings.is_empty() {
+                    return ' ';
+                }
+                let average = readings.iter().sum::<f64>() / readings.len() as f64;
+                let level = if max > min {
+                    ((average - min) / (max - min) * (BARS.len() - 1) as f64).round() as usize
+                } else {
+                    0
+                };
+                BARS[level.min(BARS.len() - 1)]
+            })
+            .collect();
+        format!("{:.0}-{:.0}{} {}", min, max, unit, line)
+    }
+}
+
+/// E.g. `avg 10s 61.2 W  60s 58.9 W  peak 74.0 W`
+fn show_summary(summary: &Summary, unit: &str) -> String {
+    let value = |value: Option<f64>| show(value.map(|v| format!("{:.1}", v)), unit);
+    format!(
+        "avg 10s {}  60s {}  peak {}",
+        value(summary.average_10s),
+        value(summary.average_60s),
+        value(summary.peak)
+    )
+}
+
+fn show<T: std::fmt::Display>(value: Option<T>, unit: &str) -> String {
+    match value {
+        Some(value) => format!("{}{}", value, unit),
+        None => "-".to_string(),
+    }
+}
+
+/// Prints one line of live readings every `interval` until interrupted,
+/// including how much of each interval the GPU spent capped by each policy.
+/// NVML events on the GPU are printed as they arrive, between the lines.
+/// With `trend` every line is followed by sparklines of the last minute of
+/// temperature, power and core clock, which shows oscillation like a power
+/// limit bouncing. With `averages` it's followed by the 10 and 60 second
+/// averages and session peaks of power and temperature, which judge an
+/// undervolt better than single readings.
+pub fn run(device: &Device, interval: Duration, trend: bool, averages: bool) {
+    let mut violations = violation_times(device);
+    let mut stats = Stats::default();
+    let mut temperatures = Trend::new(interval);
+    let mut power = Trend::new(interval);
+    let mut clocks = Trend::new(interval);
+    let events = match EventMonitor::new(device.nvml(), std::slice::from_ref(device)) {
+        Ok(events) => Some(events),
+        Err(e) => {
+            eprintln!("Failed to subscribe to GPU events: {:?}", e);
+            None
+        }
+    };
+
+    loop {
+        match &events {
+            Some(events) => events.wait(interval, |event| println!("{}", event)),
+            None => thread::sleep(interval),
+        }
+
+        let sample = Sample::read(device);
+        let current = violation_times(device);
+        let capped: Vec<String> = current
+            .iter()
+            .filter_map(|(name, after)| {
+                let (_, before) = violations.iter().find(|(n, _)| n == name)?;
+                Some(format!(
+                    "{} {:.0}%",
+                    name,
+                    violation_ratio(before, after) * 100.0
+                ))
+            })
+            .collect();
+        violations = current;
+
+        let pcie = match PcieLink::read(device) {
+            Ok(link) if link.downtrained(sample.gpu_utilization) => {
+                format!("{} (downtrained)", link)
+            }
+            Ok(link) => link.to_string(),
+            Err(_) => "-".to_string(),
+        };
+
+        println!(
+            "{}  core {:>9}  mem {:>9}  {:>5}  {:>8}  util {:>4}  fan {:>4}  pcie {}  capped: {}",
+            &format_timestamp(sample.timestamp_ms)[11..],
+            show(sample.graphics_clock_mhz, " MHz"),
+            show(sample.memory_clock_mhz, " MHz"),
+            show(sample.temperature_c, "°C"),
+            show(sample.power_w.map(|w| format!("{:.1}", w)), " W"),
+            show(sample.gpu_utilization, "%"),
+            show(sample.fan_speed, "%"),
+            pcie,
+            if capped.is_empty() {
+                "n/a".to_string()
+            } else {
+                capped.join(", ")
+            }
+        );
+
+        stats.push(&sample);
+        if averages {
+            let summary = stats.summary();
+            println!("          power {}", show_summary(&summary.power_w, " W"));
+            println!(
+                "          temp  {}",
+                show_summary(&summary.temperature_c, "°C")
+            );
+        }
+
+        if trend {
+            temperatures.push(sample.temperature_c.map(f64::from));
+            power.push(sample.power_w.map(f64::from));
+            clocks.push(sample.graphics_clock_mhz.map(f64::from));
+            println!(
+                "          temp {}  power {}  core {}",
+                temperatures.render("°C"),
+                power.render(" W"),
+                clocks.render(" MHz")
+            );
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::fan::FanPolicy;
+use crate::history::{Journal, Source};
+use nvml_wrapper::{Device, Nvml};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io, panic,
+    path::Path,
+    sync::{Mutex, PoisonError},
+    thread,
+};
+
+/// GPUs whose settings were applied with `--confirm-required` and not yet
+/// confirmed with `nvidia_oc confirm`
+pub const UNCONFIRMED_PATH: &str = "/var/lib/nvidia_oc/unconfirmed.json";
+
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+
+/// UUIDs of the GPUs this process applied settings to, the ones
+/// [`reset_on_panic`] resets
+static APPLIED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Remembers that settings are being applied to the GPU
+pub fn track(device: &Device) {
+    let Ok(uuid) = device.uuid() else {
+        return;
+    };
+    let mut applied = APPLIED.lock().unwrap_or_else(PoisonError::into_inner);
+    if !applied.contains(&uuid) {
+        applied.push(uuid);
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Unconfirmed {
+    /// Boot the settings were applied in, they are only reverted in a later one
+    boot_id: String,
+    uuids: Vec<String>,
+}
+
+fn boot_id() -> String {
+    fs::read_to_string(BOOT_ID_PATH)
+        .map(|id| id.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn read() -> io::Result<Option<Unconfirmed>> {
+    match fs::read_to_string(UNCONFIRMED_PATH) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Marks the GPU as needing confirmation. Called before applying, so settings
+/// are never applied unprotected when the marker can't be written.
+pub fn arm(device: &Device) -> io::Result<()> {
+    let uuid = device
+        .uuid()
+        .map_err(|e| io::Error::other(format!("failed to get GPU UUID: {:?}", e)))?;
+    let mut unconfirmed = read()?
+        .filter(|unconfirmed| unconfirmed.boot_id == boot_id())
+        .unwrap_or_default();
+    unconfirmed.boot_id = boot_id();
+    if !unconfirmed.uuids.contains(&uuid) {
+        unconfirmed.uuids.push(uuid);
+    }
+
+    if let Some(dir) = Path::new(UNCONFIRMED_PATH).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(UNCONFIRMED_PATH, serde_json::to_string(&unconfirmed)?)
+}
+
+/// Keeps the applied settings, returns false if nothing was waiting for
+/// confirmation
+pub fn confirm() -> io::Result<bool> {
+    match fs::remove_file(UNCONFIRMED_PATH) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Resets offsets, power limit, locked clocks and fans to the driver defaults
+pub fn reset_to_stock(device: &mut Device, source: Source) {
+    let journal = Journal::new(device, source);
+
+    let old = device.gpc_clock_vf_offset().ok();
+    match device.set_gpc_clock_vf_offset(0) {
+        Ok(()) => journal.record("freqOffset", old, 0),
+        Err(e) => eprintln!("Failed to reset GPU core clock offset: {:?}", e),
+    }
+
+    let old = device.mem_clock_vf_offset().ok();
+    match device.set_mem_clock_vf_offset(0) {
+        Ok(()) => journal.record("memOffset", old, 0),
+        Err(e) => eprintln!("Failed to reset GPU memory clock offset: {:?}", e),
+    }
+
+    if let Ok(default) = device.power_management_limit_default() {
+        let old = device.power_management_limit().ok();
+        match device.set_power_management_limit(default) {
+            Ok(()) => journal.record("powerLimit", old, default),
+            Err(e) => eprintln!("Failed to reset GPU power limit: {:?}", e),
+        }
+    }
+
+    // Locked clocks can't be read back, so the old values are unknown
+    match device.reset_gpu_locked_clocks() {
+        Ok(()) => {
+            journal.record("minClock", None::<u32>, "unlocked");
+            journal.record("maxClock", None::<u32>, "unlocked");
+        }
+        Err(e) => eprintln!("Failed to reset GPU locked clocks: {:?}", e),
+    }
+    match device.reset_mem_locked_clocks() {
+        Ok(()) => {
+            journal.record("minMemClock", None::<u32>, "unlocked");
+            journal.record("maxMemClock", None::<u32>, "unlocked");
+        }
+        Err(e) => eprintln!("Failed to reset GPU locked memory clocks: {:?}", e),
+    }
+
+    for fan in 0..device.num_fans().unwrap_or(0) {
+        if let Err(e) = FanPolicy::Auto.apply(device, fan) {
+            eprintln!("Failed to hand fan {} back to the driver: {:?}", fan, e);
+        }
+    }
+}
+
+/// Resets every GPU left unconfirmed in an earlier boot to stock and returns
+/// their UUIDs, so the caller can skip applying the config to them.
+pub fn revert_unconfirmed(nvml: &Nvml) -> Vec<String> {
+    let unconfirmed = match read() {
+        Ok(Some(unconfirmed)) if unconfirmed.boot_id != boot_id() => unconfirmed,
+        Ok(_) => return Vec::new(),
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", UNCONFIRMED_PATH, e);
+            return Vec::new();
+        }
+    };
+
+    for uuid in &unconfirmed.uuids {
+        match nvml.device_by_uuid(uuid.as_str()) {
+            Ok(mut device) => {
+                reset_to_stock(&mut device, Source::Watchdog);
+                eprintln!(
+                    "Settings of GPU {} were never confirmed, reverted it to stock.",
+                    uuid
+                );
+            }
+            Err(e) => eprintln!("Failed to get GPU {}: {:?}", uuid, e),
+        }
+    }
+
+    if let Err(e) = fs::remove_file(UNCONFIRMED_PATH) {
+        eprintln!("Failed to remove {}: {}", UNCONFIRMED_PATH, e);
+    }
+    unconfirmed.uuids
+}
+
+/// Resets the GPUs this process applied settings to, see [`track`], to
+/// stock when the main thread panics, before the process exits. A crash
+/// halfway through the daemon's work would otherwise leave whatever was last
+/// set, including manual fan speeds. GPUs it never touched keep their
+/// settings, and panics on other threads don't stop the process and are left
+/// alone.
+pub fn reset_on_panic() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        if thread::current().name() != Some("main") {
+            return;
+        }
+        let applied = APPLIED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if applied.is_empty() {
+            return;
+        }
+        let Ok(nvml) = Nvml::init() else {
+            return;
+        };
+        for uuid in &applied {
+            if let Ok(mut device) = nvml.device_by_uuid(uuid.as_str()) {
+                reset_to_stock(&mut device, Source::Crash);
+                eprintln!("Reset GPU {} to stock settings after the crash.", uuid);
+            }
+        }
+    }));
+}